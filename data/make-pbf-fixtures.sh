@@ -0,0 +1,17 @@
+#!/bin/sh
+# writes the PBF versions of the XML fixtures. data/xml-to-pbf.py rebuilds the committed
+# ones, osmium-tool writes a second set so the reader is also checked against a standard
+# encoder. Run from the repository root, then
+#   cargo test -- --ignored
+set -e
+python3 data/xml-to-pbf.py data/rutland-tiny.osm.xml data/rutland-tiny.osm.pbf
+python3 data/xml-to-pbf.py data/turn-restriction.osm.xml data/turn-restriction.osm.pbf
+if ! command -v osmium >/dev/null; then
+    echo "osmium not found, skipping the osmium fixtures" >&2
+    exit 0
+fi
+osmium cat --overwrite data/rutland-tiny.osm.xml -o data/rutland-tiny.osmium.osm.pbf
+osmium cat --overwrite data/turn-restriction.osm.xml -o data/turn-restriction.osmium.osm.pbf
+if [ -f data/rutland-latest.osm.xml ]; then
+    osmium cat --overwrite data/rutland-latest.osm.xml -o data/rutland-latest.osm.pbf
+fi
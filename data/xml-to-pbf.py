@@ -0,0 +1,123 @@
+#!/usr/bin/env python3
+# writes an OSM XML file as PBF, the encoder behind data/rutland-tiny.osm.pbf and
+# data/turn-restriction.osm.pbf. It keeps to the parts of the format the loader reads: one
+# header blob, then one data blob holding dense nodes, ways and relations in separate
+# groups of a single block, with default granularity and offsets.
+#
+#     python3 data/xml-to-pbf.py data/rutland-tiny.osm.xml data/rutland-tiny.osm.pbf
+import struct
+import sys
+import zlib
+import xml.etree.ElementTree as ET
+
+
+def varint(value):
+    out = bytearray()
+    while True:
+        byte = value & 0x7F
+        value >>= 7
+        if value:
+            out.append(byte | 0x80)
+        else:
+            out.append(byte)
+            return bytes(out)
+
+
+def zigzag(value):
+    return (value << 1) if value >= 0 else ((-value) << 1) - 1
+
+
+def key(field, wire_type):
+    return varint((field << 3) | wire_type)
+
+
+def int_field(field, value):
+    return key(field, 0) + varint(value)
+
+
+def bytes_field(field, data):
+    return key(field, 2) + varint(len(data)) + data
+
+
+def packed(field, values):
+    return bytes_field(field, b"".join(varint(value) for value in values))
+
+
+def deltas(values):
+    previous = 0
+    for value in values:
+        yield zigzag(value - previous)
+        previous = value
+
+
+# a BlobHeader with its length in front, then a zlib compressed Blob
+def blob(blob_type, data):
+    body = int_field(2, len(data)) + bytes_field(3, zlib.compress(data))
+    header = bytes_field(1, blob_type.encode()) + int_field(3, len(body))
+    return struct.pack(">I", len(header)) + header + body
+
+
+def main(xml_file, pbf_file):
+    root = ET.parse(xml_file).getroot()
+    strings = [""]
+    positions = {}
+
+    def string(text):
+        if text not in positions:
+            positions[text] = len(strings)
+            strings.append(text)
+        return positions[text]
+
+    ids, lats, lons, keys_vals = [], [], [], []
+    for node in root.findall("node"):
+        ids.append(int(node.get("id")))
+        lats.append(round(float(node.get("lat")) * 1e7))
+        lons.append(round(float(node.get("lon")) * 1e7))
+        for tag in node.findall("tag"):
+            keys_vals += [string(tag.get("k")), string(tag.get("v"))]
+        keys_vals.append(0)
+    dense = packed(1, deltas(ids)) + packed(8, deltas(lats)) + packed(9, deltas(lons)) + packed(10, keys_vals)
+    groups = [bytes_field(2, dense)]
+
+    ways = b""
+    for way in root.findall("way"):
+        tags = way.findall("tag")
+        refs = [int(nd.get("ref")) for nd in way.findall("nd")]
+        ways += bytes_field(
+            3,
+            int_field(1, int(way.get("id")))
+            + packed(2, [string(tag.get("k")) for tag in tags])
+            + packed(3, [string(tag.get("v")) for tag in tags])
+            + packed(8, deltas(refs)),
+        )
+    if ways:
+        groups.append(ways)
+
+    relations = b""
+    member_types = {"node": 0, "way": 1, "relation": 2}
+    for relation in root.findall("relation"):
+        tags = relation.findall("tag")
+        members = relation.findall("member")
+        relations += bytes_field(
+            4,
+            int_field(1, int(relation.get("id")))
+            + packed(2, [string(tag.get("k")) for tag in tags])
+            + packed(3, [string(tag.get("v")) for tag in tags])
+            + packed(8, [string(member.get("role")) for member in members])
+            + packed(9, deltas([int(member.get("ref")) for member in members]))
+            + packed(10, [member_types[member.get("type")] for member in members]),
+        )
+    if relations:
+        groups.append(relations)
+
+    string_table = b"".join(bytes_field(1, text.encode()) for text in strings)
+    block = bytes_field(1, string_table) + b"".join(bytes_field(2, group) for group in groups)
+    header = bytes_field(4, b"OsmSchema-V0.6") + bytes_field(4, b"DenseNodes") + bytes_field(16, b"crate fixture")
+    with open(pbf_file, "wb") as out:
+        out.write(blob("OSMHeader", header) + blob("OSMData", block))
+
+
+if __name__ == "__main__":
+    if len(sys.argv) != 3:
+        sys.exit("usage: xml-to-pbf.py <osm-xml-file> <pbf-file>")
+    main(sys.argv[1], sys.argv[2])
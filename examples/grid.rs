@@ -0,0 +1,70 @@
+use std::env;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::process;
+
+// writes an n x n grid of two way residential streets roughly 100m apart as OSM XML, the
+// network the benchmarks in the README were run on. The node at row r and column c has
+// id r * n + c + 1, so node 80201 is in the middle of the 400 x 400 grid
+//
+//     cargo run --release --example grid -- 400 data/grid-400.osm.xml
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (size, output) = match args.as_slice() {
+        [size, output] => match size.parse::<u64>() {
+            Ok(size) if size >= 2 => (size, output),
+            _ => usage(),
+        },
+        _ => usage(),
+    };
+
+    let result = File::create(output).and_then(|file| write_grid(size, &mut BufWriter::new(file)));
+    if let Err(e) = result {
+        eprintln!("couldn't write {}: {}", output, e);
+        process::exit(1);
+    }
+    println!(
+        "wrote {} nodes and {} arcs to {}",
+        size * size,
+        4 * size * (size - 1),
+        output
+    );
+}
+
+fn usage() -> ! {
+    eprintln!("usage: grid <size> <output.osm.xml>");
+    process::exit(1);
+}
+
+fn write_grid<W: Write>(size: u64, w: &mut W) -> std::io::Result<()> {
+    writeln!(w, "<?xml version='1.0' encoding='UTF-8'?>")?;
+    writeln!(w, "<osm version='0.6' generator='grid'>")?;
+    for row in 0..size {
+        for col in 0..size {
+            writeln!(
+                w,
+                "  <node id='{}' lat='{:.7}' lon='{:.7}'/>",
+                row * size + col + 1,
+                52.6 + row as f64 * 0.0009,
+                -0.7 + col as f64 * 0.0015
+            )?;
+        }
+    }
+
+    // one way along every row and one down every column
+    for line in 0..size {
+        write_way(w, 2 * line + 1, (0..size).map(|col| line * size + col + 1))?;
+        write_way(w, 2 * line + 2, (0..size).map(|row| row * size + line + 1))?;
+    }
+    writeln!(w, "</osm>")?;
+    w.flush()
+}
+
+fn write_way<W: Write, I: Iterator<Item = u64>>(w: &mut W, id: u64, node_ids: I) -> std::io::Result<()> {
+    writeln!(w, "  <way id='{}'>", id)?;
+    for node_id in node_ids {
+        writeln!(w, "    <nd ref='{}'/>", node_id)?;
+    }
+    writeln!(w, "    <tag k='highway' v='residential'/>")?;
+    writeln!(w, "  </way>")
+}
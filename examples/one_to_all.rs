@@ -0,0 +1,61 @@
+use efficient_route_planning_freiburg::dijkstra;
+use efficient_route_planning_freiburg::network::OSMNodeId;
+use efficient_route_planning_freiburg::osm;
+use efficient_route_planning_freiburg::osm::profile::Car;
+use std::env;
+use std::process;
+use std::time::{Duration, Instant};
+
+// times one-to-all Dijkstra from a node of an OSM file loaded with the car profile, the
+// benchmark behind the graph layout table in the README. It only uses run_dijsktra so the
+// same file builds on older commits for before and after numbers
+//
+//     cargo run --release --example one_to_all -- data/grid-400.osm.xml 80201 21
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (file, from, runs) = match args.as_slice() {
+        [file, from, runs] => match (from.parse::<OSMNodeId>(), runs.parse::<usize>()) {
+            (Ok(from), Ok(runs)) if runs > 0 => (file, from, runs),
+            _ => usage(),
+        },
+        _ => usage(),
+    };
+
+    let network = match osm::load_network_from_file(file, &Car) {
+        Ok(network) => network,
+        Err(e) => {
+            eprintln!("loading network {} failed: {}", file, e);
+            process::exit(1);
+        }
+    };
+    if network.get_node(&from).is_none() {
+        eprintln!("node not found in network: {}", from);
+        process::exit(1);
+    }
+
+    // one untimed run to fault in the network. The target is never in it so every reachable
+    // node is settled
+    dijkstra::run_dijsktra(from, OSMNodeId::MAX, &network, 0, false);
+    let mut times: Vec<Duration> = (0..runs)
+        .map(|_| {
+            let start = Instant::now();
+            dijkstra::run_dijsktra(from, OSMNodeId::MAX, &network, 0, false);
+            start.elapsed()
+        })
+        .collect();
+    times.sort();
+    println!(
+        "one-to-all from {} over {} nodes, {} runs: min {:?} median {:?} max {:?}",
+        from,
+        network.node_count(),
+        runs,
+        times[0],
+        times[runs / 2],
+        times[runs - 1]
+    );
+}
+
+fn usage() -> ! {
+    eprintln!("usage: one_to_all <osm-file> <from> <runs>");
+    process::exit(1);
+}
@@ -0,0 +1,401 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashSet;
+use std::error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+use crate::dijkstra::{search_with_potential, settle_nodes, DijkstraWorkspace, Entry, SearchResult};
+use crate::network::{Direction, Network, NodeIndex, OSMNodeId};
+
+const UNREACHABLE: u64 = u64::MAX;
+
+// how many random roots the avoid strategy tries before falling back to a random landmark
+const AVOID_ATTEMPTS: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LandmarkStrategy {
+    Random,
+    Farthest,
+    Avoid,
+}
+
+/// Shortest path costs from and to each landmark, used as lower bounds for A* by the
+/// triangle inequality. Tables are indexed by landmark then NodeIndex and only make
+/// sense for the network they were computed on.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct LandmarkTables {
+    node_count: usize,
+    arc_count: usize,
+    landmarks: Vec<NodeIndex>,
+    from_landmark: Vec<Vec<u64>>,
+    to_landmark: Vec<Vec<u64>>,
+}
+
+impl LandmarkTables {
+    pub fn new(
+        network: &Network,
+        landmark_count: usize,
+        strategy: LandmarkStrategy,
+        seed: u64,
+    ) -> LandmarkTables {
+        let mut tables = LandmarkTables {
+            node_count: network.node_count(),
+            arc_count: network.arc_count(),
+            landmarks: vec![],
+            from_landmark: vec![],
+            to_landmark: vec![],
+        };
+        let landmark_count = landmark_count.min(network.node_count());
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut workspace = DijkstraWorkspace::for_network(network);
+
+        match strategy {
+            LandmarkStrategy::Random => {
+                while tables.landmarks.len() < landmark_count {
+                    let candidate = rng.gen_range(0..network.node_count());
+                    if !tables.landmarks.contains(&candidate) {
+                        tables.add_landmark(&mut workspace, network, candidate);
+                    }
+                }
+            }
+            // stops short of landmark_count once every node the landmarks reach is one
+            LandmarkStrategy::Farthest => {
+                if landmark_count > 0 {
+                    let root = rng.gen_range(0..network.node_count());
+                    let root_costs = settle_nodes(&mut workspace, root, network, Direction::Forward, None).into_costs();
+                    if let Some(landmark) = farthest_node(&root_costs, &[]) {
+                        tables.add_landmark(&mut workspace, network, landmark);
+                    }
+                }
+                while tables.landmarks.len() < landmark_count {
+                    let nearest_landmark_costs: Vec<u64> = (0..network.node_count())
+                        .map(|node| {
+                            tables.from_landmark.iter().map(|costs| costs[node]).min().unwrap_or(UNREACHABLE)
+                        })
+                        .collect();
+                    match farthest_node(&nearest_landmark_costs, &tables.landmarks) {
+                        Some(candidate) => tables.add_landmark(&mut workspace, network, candidate),
+                        None => break,
+                    }
+                }
+            }
+            LandmarkStrategy::Avoid => {
+                while tables.landmarks.len() < landmark_count {
+                    let candidate = (0..AVOID_ATTEMPTS)
+                        .filter_map(|_| tables.avoid_candidate(&mut workspace, network, &mut rng))
+                        .next()
+                        .unwrap_or_else(|| random_non_landmark(network, &tables.landmarks, &mut rng));
+                    tables.add_landmark(&mut workspace, network, candidate);
+                }
+            }
+        }
+        info!("chose landmarks {:?} using {:?}", tables.landmarks, strategy);
+        tables
+    }
+
+    pub fn landmarks(&self) -> &[NodeIndex] {
+        &self.landmarks
+    }
+
+    pub fn save(&self, file_path: &str) -> Result<(), Box<dyn error::Error>> {
+        let writer = BufWriter::new(File::create(file_path)?);
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+
+    /// reads tables written by save, refusing tables computed on a different network
+    pub fn load(file_path: &str, network: &Network) -> Result<LandmarkTables, Box<dyn error::Error>> {
+        let reader = BufReader::new(File::open(file_path)?);
+        let tables: LandmarkTables = serde_json::from_reader(reader)?;
+        if tables.node_count != network.node_count() || tables.arc_count != network.arc_count() {
+            return Err(Box::new(std::io::Error::other(format!(
+                "landmark tables in {} were built for a network with {} nodes and {} arcs",
+                file_path, tables.node_count, tables.arc_count
+            ))));
+        }
+        Ok(tables)
+    }
+
+    /// lower bound on the cost from one node to another, the best of
+    /// cost(L, to) - cost(L, from) and cost(from, L) - cost(to, L) over all landmarks L
+    pub fn lower_bound(&self, from: NodeIndex, to: NodeIndex) -> u64 {
+        let mut bound = 0;
+        for (from_landmark, to_landmark) in self.from_landmark.iter().zip(self.to_landmark.iter()) {
+            if from_landmark[to] != UNREACHABLE && from_landmark[from] != UNREACHABLE {
+                bound = bound.max(from_landmark[to].saturating_sub(from_landmark[from]));
+            }
+            if to_landmark[from] != UNREACHABLE && to_landmark[to] != UNREACHABLE {
+                bound = bound.max(to_landmark[from].saturating_sub(to_landmark[to]));
+            }
+        }
+        bound
+    }
+
+    fn add_landmark(&mut self, workspace: &mut DijkstraWorkspace, network: &Network, landmark: NodeIndex) {
+        let from_costs = settle_nodes(workspace, landmark, network, Direction::Forward, None).into_costs();
+        let to_costs = settle_nodes(workspace, landmark, network, Direction::Backward, None).into_costs();
+        self.landmarks.push(landmark);
+        self.from_landmark.push(from_costs);
+        self.to_landmark.push(to_costs);
+    }
+
+    // Goldberg & Harrelson's avoid: grow a shortest path tree from a random root, weight
+    // each node by how badly the current landmarks bound its cost from the root and
+    // follow the heaviest subtrees without a landmark down to a leaf
+    fn avoid_candidate(
+        &self,
+        workspace: &mut DijkstraWorkspace,
+        network: &Network,
+        rng: &mut StdRng,
+    ) -> Option<NodeIndex> {
+        let root = rng.gen_range(0..network.node_count());
+        let tree = settle_nodes(workspace, root, network, Direction::Forward, None);
+
+        let mut children: Vec<Vec<NodeIndex>> = vec![vec![]; network.node_count()];
+        for node in 0..network.node_count() {
+            if let Some(parent) = tree.predecessor(node) {
+                children[parent].push(node);
+            }
+        }
+        let mut tree_order = vec![root];
+        let mut next = 0;
+        while next < tree_order.len() {
+            tree_order.extend_from_slice(&children[tree_order[next]]);
+            next += 1;
+        }
+
+        let landmarks: HashSet<NodeIndex> = self.landmarks.iter().cloned().collect();
+        let mut sizes = vec![0; network.node_count()];
+        let mut covered = vec![false; network.node_count()];
+        for &node in tree_order.iter().rev() {
+            // every node in the tree was settled
+            let cost = tree.cost(node).unwrap();
+            let weight = cost - self.lower_bound(root, node).min(cost);
+            covered[node] = landmarks.contains(&node) || children[node].iter().any(|&c| covered[c]);
+            sizes[node] = if covered[node] {
+                0
+            } else {
+                weight + children[node].iter().map(|&c| sizes[c]).sum::<u64>()
+            };
+        }
+
+        let mut current = *tree_order.iter().max_by_key(|&&node| sizes[node])?;
+        if sizes[current] == 0 {
+            return None;
+        }
+        while let Some(&child) = children[current].iter().filter(|&&c| sizes[c] > 0).max_by_key(|&&c| sizes[c]) {
+            current = child;
+        }
+        Some(current)
+    }
+}
+
+/// None when every reachable node is excluded
+fn farthest_node(costs: &[u64], exclude: &[NodeIndex]) -> Option<NodeIndex> {
+    costs
+        .iter()
+        .enumerate()
+        .filter(|&(node, &cost)| cost != UNREACHABLE && !exclude.contains(&node))
+        .max_by_key(|&(_, &cost)| cost)
+        .map(|(node, _)| node)
+}
+
+fn random_non_landmark(network: &Network, landmarks: &[NodeIndex], rng: &mut StdRng) -> NodeIndex {
+    loop {
+        let candidate = rng.gen_range(0..network.node_count());
+        if !landmarks.contains(&candidate) {
+            return candidate;
+        }
+    }
+}
+
+pub fn run_alt<'a>(
+    source: OSMNodeId,
+    target: OSMNodeId,
+    network: &'a Network,
+    tables: &LandmarkTables,
+    trace_path: bool,
+) -> Option<Entry<'a>> {
+    search_alt(source, target, network, tables, trace_path).entry
+}
+
+/// A* using the landmark lower bounds to the target as its potential
+pub fn search_alt<'a>(
+    source: OSMNodeId,
+    target: OSMNodeId,
+    network: &'a Network,
+    tables: &LandmarkTables,
+    trace_path: bool,
+) -> SearchResult<'a> {
+    let mut workspace = DijkstraWorkspace::for_network(network);
+    search_alt_in(&mut workspace, source, target, network, tables, trace_path)
+}
+
+/// search_alt reusing the workspace of earlier queries on the same network
+pub fn search_alt_in<'a>(
+    workspace: &mut DijkstraWorkspace,
+    source: OSMNodeId,
+    target: OSMNodeId,
+    network: &'a Network,
+    tables: &LandmarkTables,
+    trace_path: bool,
+) -> SearchResult<'a> {
+    match (network.node_index(&source), network.node_index(&target)) {
+        (Some(source_index), Some(target_index)) => search_with_potential(
+            workspace,
+            source_index,
+            Some(target_index),
+            network,
+            0,
+            trace_path,
+            |index| tables.lower_bound(index, target_index),
+        ),
+        _ => {
+            debug!("alt. couldn't find source {} or target {}", source, target);
+            SearchResult {
+                entry: None,
+                settled_nodes: 0,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod alt_test {
+    use super::*;
+    use crate::astar::search_astar;
+    use crate::dijkstra::{run_dijsktra, search_dijkstra};
+    use crate::test_networks::{grid_id, make_grid_network, make_oneway_network, GRID_SIZE};
+
+    use std::env;
+    use std::fs;
+
+    const STRATEGIES: [LandmarkStrategy; 3] = [
+        LandmarkStrategy::Random,
+        LandmarkStrategy::Farthest,
+        LandmarkStrategy::Avoid,
+    ];
+
+    #[test]
+    fn distinct_landmarks() {
+        let network = make_grid_network();
+        for &strategy in &STRATEGIES {
+            let tables = LandmarkTables::new(&network, 4, strategy, 7);
+            let distinct: HashSet<&NodeIndex> = tables.landmarks().iter().collect();
+            assert_eq!(4, distinct.len(), "{:?} landmarks", strategy);
+        }
+    }
+
+    #[test]
+    fn farthest_picks_corners() {
+        let network = make_grid_network();
+        let tables = LandmarkTables::new(&network, 2, LandmarkStrategy::Farthest, 7);
+        let corners: Vec<NodeIndex> = [
+            grid_id(0, 0),
+            grid_id(0, GRID_SIZE - 1),
+            grid_id(GRID_SIZE - 1, 0),
+            grid_id(GRID_SIZE - 1, GRID_SIZE - 1),
+        ]
+        .iter()
+        .map(|id| network.node_index(id).unwrap())
+        .collect();
+        for landmark in tables.landmarks() {
+            assert!(corners.contains(landmark), "landmark {} is not a corner", landmark);
+        }
+    }
+
+    #[test]
+    fn farthest_stops_when_nothing_is_left_to_reach() {
+        // whichever node is the root, 3 is farthest and reaches no other node
+        let network = make_oneway_network();
+        let tables = LandmarkTables::new(&network, 3, LandmarkStrategy::Farthest, 7);
+        assert_eq!(&[network.node_index(&3).unwrap()], tables.landmarks());
+    }
+
+    #[test]
+    fn lower_bounds_hold() {
+        let network = make_grid_network();
+        let tables = LandmarkTables::new(&network, 4, LandmarkStrategy::Avoid, 3);
+        let mut workspace = DijkstraWorkspace::for_network(&network);
+        for source in 0..network.node_count() {
+            let costs = settle_nodes(&mut workspace, source, &network, Direction::Forward, None);
+            for target in 0..network.node_count() {
+                assert!(tables.lower_bound(source, target) <= costs.cost(target).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn same_costs_as_dijkstra() {
+        let network = make_grid_network();
+        let mut workspace = DijkstraWorkspace::for_network(&network);
+        for &strategy in &STRATEGIES {
+            let tables = LandmarkTables::new(&network, 3, strategy, 11);
+            for &source in &[grid_id(0, 0), grid_id(4, 7), grid_id(9, 2)] {
+                for row in 0..GRID_SIZE {
+                    for col in 0..GRID_SIZE {
+                        let target = grid_id(row, col);
+                        let expected = run_dijsktra(source, target, &network, 0, false).unwrap();
+                        let actual = run_alt(source, target, &network, &tables, false).unwrap();
+                        assert_eq!(expected.cost, actual.cost, "{:?} {} to {}", strategy, source, target);
+                        let reused = search_alt_in(&mut workspace, source, target, &network, &tables, false);
+                        assert_eq!(Some(expected.cost), reused.entry.map(|e| e.cost));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn settles_fewer_nodes() {
+        let network = make_grid_network();
+        let tables = LandmarkTables::new(&network, 4, LandmarkStrategy::Avoid, 5);
+        let source = grid_id(0, 0);
+        let target = grid_id(GRID_SIZE - 1, GRID_SIZE - 3);
+
+        let dijkstra = search_dijkstra(source, target, &network, 0, false);
+        let astar = search_astar(source, target, &network, false);
+        let alt = search_alt(source, target, &network, &tables, false);
+
+        assert_eq!(dijkstra.entry.unwrap().cost, alt.entry.unwrap().cost);
+        assert!(alt.settled_nodes < dijkstra.settled_nodes);
+        assert!(
+            alt.settled_nodes <= astar.settled_nodes,
+            "alt settled {}, astar settled {}",
+            alt.settled_nodes,
+            astar.settled_nodes
+        );
+    }
+
+    #[test]
+    fn save_and_load() {
+        let network = make_grid_network();
+        let tables = LandmarkTables::new(&network, 3, LandmarkStrategy::Farthest, 1);
+
+        let file = env::temp_dir().join("alt_test_save_and_load.json");
+        let file_path = file.to_str().unwrap();
+        tables.save(file_path).unwrap();
+        let loaded = LandmarkTables::load(file_path, &network);
+        fs::remove_file(file_path).unwrap();
+
+        assert_eq!(tables, loaded.unwrap());
+    }
+
+    #[test]
+    fn load_rejects_other_network() {
+        let network = make_grid_network();
+        let mut tables = LandmarkTables::new(&network, 2, LandmarkStrategy::Random, 1);
+        tables.node_count += 1;
+
+        let file = env::temp_dir().join("alt_test_load_rejects_other_network.json");
+        let file_path = file.to_str().unwrap();
+        tables.save(file_path).unwrap();
+        let loaded = LandmarkTables::load(file_path, &network);
+        fs::remove_file(file_path).unwrap();
+
+        assert!(loaded.is_err());
+    }
+}
@@ -0,0 +1,284 @@
+use crate::dijkstra::{settle_nodes, DijkstraWorkspace, Entry, PrunedSearch, SearchResult};
+use crate::network::{Direction, Network, NodeIndex, OSMNodeId};
+
+const BITS_PER_WORD: usize = 64;
+
+/// how nodes are split into regions
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Partition {
+    // a k x k grid over the bounding box of the nodes, some cells may be empty
+    Grid(usize),
+    // splits the nodes in half across the longer side of their bounding box until
+    // there are this many regions of about the same size
+    Balanced(usize),
+}
+
+/// a region per node and a bit per region for every forward arc, set when the arc starts
+/// a shortest path to somewhere in the region. A query only has to follow the arcs flagged
+/// for the target's region
+#[derive(Clone, Debug)]
+pub struct ArcFlags {
+    region_count: usize,
+    regions: Vec<usize>,
+    words_per_arc: usize,
+    // words_per_arc words per arc in the order of Network::fwd_arc_ids
+    flags: Vec<u64>,
+}
+
+impl ArcFlags {
+    /// flags arcs inside each region, then runs a backward dijkstra from every boundary
+    /// node, a node with an arc from another region, and flags the arcs of its shortest
+    /// path tree. One shortest path to each boundary node is enough and flagging every
+    /// arc on any shortest path would prune far less where there are ties
+    pub fn new(network: &Network, partition: Partition) -> ArcFlags {
+        let (regions, region_count) = match partition {
+            Partition::Grid(k) => grid_regions(network, k),
+            Partition::Balanced(count) => balanced_regions(network, count),
+        };
+        let words_per_arc = region_count.div_ceil(BITS_PER_WORD);
+        let mut arc_flags = ArcFlags {
+            region_count,
+            regions,
+            words_per_arc,
+            flags: vec![0; network.arc_count() * words_per_arc],
+        };
+
+        let mut workspace = DijkstraWorkspace::for_network(network);
+        let mut boundary_nodes = 0;
+        for node in 0..network.node_count() {
+            let region = arc_flags.regions[node];
+            for (arc, arc_id) in network.fwd_arcs(node).iter().zip(network.fwd_arc_ids(node)) {
+                if arc_flags.regions[arc.head_node] == region {
+                    arc_flags.set_flag(arc_id, region);
+                }
+            }
+
+            let on_boundary = network
+                .bwd_arcs(node)
+                .iter()
+                .any(|arc| arc_flags.regions[arc.head_node] != region);
+            if !on_boundary {
+                continue;
+            }
+            boundary_nodes += 1;
+            // the parent of a node in a backward tree is the head of its next arc towards the root
+            let tree = settle_nodes(&mut workspace, node, network, Direction::Backward, None);
+            for tail in 0..network.node_count() {
+                let parent = match tree.predecessor(tail) {
+                    Some(parent) => parent,
+                    None => continue,
+                };
+                let tree_arc = network
+                    .fwd_arcs(tail)
+                    .iter()
+                    .zip(network.fwd_arc_ids(tail))
+                    .find(|(arc, _)| {
+                        arc.head_node == parent && tree.cost(parent).map(|cost| cost + arc.cost) == tree.cost(tail)
+                    });
+                if let Some((_, arc_id)) = tree_arc {
+                    arc_flags.set_flag(arc_id, region);
+                }
+            }
+        }
+        info!(
+            "arc flags for {} regions from {} boundary nodes",
+            region_count, boundary_nodes
+        );
+        arc_flags
+    }
+
+    pub fn region_count(&self) -> usize {
+        self.region_count
+    }
+
+    pub fn region(&self, node: NodeIndex) -> usize {
+        self.regions[node]
+    }
+
+    pub fn is_flagged(&self, arc_id: usize, region: usize) -> bool {
+        let word = self.flags[arc_id * self.words_per_arc + region / BITS_PER_WORD];
+        word & (1 << (region % BITS_PER_WORD)) != 0
+    }
+
+    /// the share of arc and region pairs that are flagged, the lower the more a query can prune
+    pub fn flagged_fraction(&self) -> f64 {
+        let arc_count = self.flags.len() / self.words_per_arc.max(1);
+        let flagged: u32 = self.flags.iter().map(|word| word.count_ones()).sum();
+        flagged as f64 / (arc_count * self.region_count).max(1) as f64
+    }
+
+    fn set_flag(&mut self, arc_id: usize, region: usize) {
+        self.flags[arc_id * self.words_per_arc + region / BITS_PER_WORD] |= 1 << (region % BITS_PER_WORD);
+    }
+}
+
+fn grid_regions(network: &Network, k: usize) -> (Vec<usize>, usize) {
+    let k = k.max(1);
+    let nodes = (0..network.node_count()).map(|index| network.node_at(index));
+    let (mut min_lat, mut max_lat, mut min_long, mut max_long) = (i32::MAX, i32::MIN, i32::MAX, i32::MIN);
+    for node in nodes.clone() {
+        min_lat = min_lat.min(node.latitude);
+        max_lat = max_lat.max(node.latitude);
+        min_long = min_long.min(node.longitude);
+        max_long = max_long.max(node.longitude);
+    }
+    // the cell of a coordinate along one side, the largest value falls in the last cell
+    let cell = |value: i32, min: i32, max: i32| {
+        let span = (max as i64 - min as i64 + 1) as usize;
+        ((value as i64 - min as i64) as usize * k / span).min(k - 1)
+    };
+    let regions = nodes
+        .map(|node| cell(node.latitude, min_lat, max_lat) * k + cell(node.longitude, min_long, max_long))
+        .collect();
+    (regions, k * k)
+}
+
+fn balanced_regions(network: &Network, region_count: usize) -> (Vec<usize>, usize) {
+    let region_count = region_count.clamp(1, network.node_count().max(1));
+    let mut regions = vec![0; network.node_count()];
+    let mut nodes: Vec<NodeIndex> = (0..network.node_count()).collect();
+    split(network, &mut nodes, 0, region_count, &mut regions);
+    (regions, region_count)
+}
+
+// gives the nodes the regions first_region..first_region + count, each about the same size
+fn split(network: &Network, nodes: &mut [NodeIndex], first_region: usize, count: usize, regions: &mut [usize]) {
+    if count == 1 {
+        for &node in nodes.iter() {
+            regions[node] = first_region;
+        }
+        return;
+    }
+    let span = |coordinate: fn(NodeIndex, &Network) -> i32| {
+        let values = nodes.iter().map(|&node| coordinate(node, network));
+        values.clone().max().unwrap_or(0) as i64 - values.min().unwrap_or(0) as i64
+    };
+    let latitude: fn(NodeIndex, &Network) -> i32 = |node, network| network.node_at(node).latitude;
+    let longitude: fn(NodeIndex, &Network) -> i32 = |node, network| network.node_at(node).longitude;
+    let coordinate = if span(latitude) >= span(longitude) { latitude } else { longitude };
+    nodes.sort_by_key(|&node| (coordinate(node, network), node));
+
+    let left_count = count / 2;
+    let middle = nodes.len() * left_count / count;
+    let (left, right) = nodes.split_at_mut(middle);
+    split(network, left, first_region, left_count, regions);
+    split(network, right, first_region + left_count, count - left_count, regions);
+}
+
+pub fn run_arc_flags<'a>(
+    source: OSMNodeId,
+    target: OSMNodeId,
+    network: &'a Network,
+    arc_flags: &ArcFlags,
+    trace_path: bool,
+) -> Option<Entry<'a>> {
+    search_arc_flags(source, target, network, arc_flags, trace_path).entry
+}
+
+/// dijkstra following only the arcs flagged for the target's region
+pub fn search_arc_flags<'a>(
+    source: OSMNodeId,
+    target: OSMNodeId,
+    network: &'a Network,
+    arc_flags: &ArcFlags,
+    trace_path: bool,
+) -> SearchResult<'a> {
+    let mut workspace = DijkstraWorkspace::for_network(network);
+    search_arc_flags_in(&mut workspace, source, target, network, arc_flags, trace_path)
+}
+
+/// search_arc_flags reusing the workspace of earlier queries on the same network
+pub fn search_arc_flags_in<'a>(
+    workspace: &mut DijkstraWorkspace,
+    source: OSMNodeId,
+    target: OSMNodeId,
+    network: &'a Network,
+    arc_flags: &ArcFlags,
+    trace_path: bool,
+) -> SearchResult<'a> {
+    match (network.node_index(&source), network.node_index(&target)) {
+        (Some(source_index), Some(target_index)) => {
+            let search = PrunedSearch {
+                source_index,
+                target_index: Some(target_index),
+                max_distance: 0,
+                trace_path,
+            };
+            let region = arc_flags.region(target_index);
+            search.run(workspace, network, |_| 0, |arc_id, arc, _| {
+                Some(arc.cost).filter(|_| arc_flags.is_flagged(arc_id, region))
+            })
+        }
+        _ => {
+            debug!("arc flags. couldn't find source {} or target {}", source, target);
+            SearchResult {
+                entry: None,
+                settled_nodes: 0,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod arc_flags_test {
+    use super::*;
+    use crate::dijkstra::{run_dijsktra, search_dijkstra};
+    use crate::test_networks::{grid_id, make_grid_network, GRID_SIZE};
+
+    const PARTITIONS: [Partition; 4] = [
+        Partition::Grid(1),
+        Partition::Grid(3),
+        Partition::Balanced(5),
+        Partition::Balanced(70),
+    ];
+
+    #[test]
+    fn balanced_regions_are_even() {
+        let network = make_grid_network();
+        let arc_flags = ArcFlags::new(&network, Partition::Balanced(4));
+        let mut sizes = vec![0; arc_flags.region_count()];
+        for node in 0..network.node_count() {
+            sizes[arc_flags.region(node)] += 1;
+        }
+        assert_eq!(vec![25, 25, 25, 25], sizes);
+
+        let grid = ArcFlags::new(&network, Partition::Grid(2));
+        assert_eq!(4, grid.region_count());
+        assert_eq!(0, grid.region(network.node_index(&grid_id(0, 0)).unwrap()));
+        assert_eq!(3, grid.region(network.node_index(&grid_id(GRID_SIZE - 1, GRID_SIZE - 1)).unwrap()));
+    }
+
+    #[test]
+    fn same_costs_as_dijkstra() {
+        let network = make_grid_network();
+        let mut workspace = DijkstraWorkspace::for_network(&network);
+        for &partition in &PARTITIONS {
+            let arc_flags = ArcFlags::new(&network, partition);
+            for &source in &[grid_id(0, 0), grid_id(4, 7), grid_id(9, 2)] {
+                for row in 0..GRID_SIZE {
+                    for col in 0..GRID_SIZE {
+                        let target = grid_id(row, col);
+                        let expected = run_dijsktra(source, target, &network, 0, false).unwrap();
+                        let actual = run_arc_flags(source, target, &network, &arc_flags, false).unwrap();
+                        assert_eq!(expected.cost, actual.cost, "{:?} {} to {}", partition, source, target);
+                        let reused = search_arc_flags_in(&mut workspace, source, target, &network, &arc_flags, false);
+                        assert_eq!(Some(expected.cost), reused.entry.map(|e| e.cost));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn settles_fewer_nodes() {
+        let network = make_grid_network();
+        let arc_flags = ArcFlags::new(&network, Partition::Grid(4));
+        assert!(arc_flags.flagged_fraction() < 1.0);
+
+        let (source, target) = (grid_id(0, 0), grid_id(GRID_SIZE - 1, GRID_SIZE - 3));
+        let dijkstra = search_dijkstra(source, target, &network, 0, false);
+        let pruned = search_arc_flags(source, target, &network, &arc_flags, true);
+        assert_eq!(dijkstra.entry.unwrap().cost, pruned.entry.unwrap().cost);
+        assert!(pruned.settled_nodes < dijkstra.settled_nodes);
+    }
+}
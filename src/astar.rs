@@ -0,0 +1,111 @@
+use crate::dijkstra::{search_with_potential, DijkstraWorkspace, Entry, SearchResult};
+use crate::network::{Network, NodeIndex, OSMNodeId};
+use crate::utils;
+
+pub fn run_astar(
+    source: OSMNodeId,
+    target: OSMNodeId,
+    network: &Network,
+    trace_path: bool,
+) -> Option<Entry<'_>> {
+    search_astar(source, target, network, trace_path).entry
+}
+
+/// A* using the great circle distance to the target as its potential. The distance is
+/// scaled by the network's cheapest cost per metre so that it never overestimates the
+/// remaining cost whatever the cost metric is.
+pub fn search_astar(
+    source: OSMNodeId,
+    target: OSMNodeId,
+    network: &Network,
+    trace_path: bool,
+) -> SearchResult<'_> {
+    let mut workspace = DijkstraWorkspace::for_network(network);
+    search_astar_in(&mut workspace, source, target, network, trace_path)
+}
+
+/// search_astar reusing the workspace of earlier queries on the same network
+pub fn search_astar_in<'a>(
+    workspace: &mut DijkstraWorkspace,
+    source: OSMNodeId,
+    target: OSMNodeId,
+    network: &'a Network,
+    trace_path: bool,
+) -> SearchResult<'a> {
+    match (network.node_index(&source), network.node_index(&target)) {
+        (Some(source_index), Some(target_index)) => search_with_potential(
+            workspace,
+            source_index,
+            Some(target_index),
+            network,
+            0,
+            trace_path,
+            great_circle_potential(network, target_index),
+        ),
+        _ => {
+            debug!("astar. couldn't find source {} or target {}", source, target);
+            SearchResult {
+                entry: None,
+                settled_nodes: 0,
+            }
+        }
+    }
+}
+
+fn great_circle_potential(network: &Network, target_index: NodeIndex) -> impl Fn(NodeIndex) -> u64 + '_ {
+    let target_lat_long = network.node_at(target_index).lat_long_f64();
+    let scale = network.min_cost_per_metre();
+    move |index| {
+        let metres = utils::haversine_distance_metres_f64(network.node_at(index).lat_long_f64(), target_lat_long);
+        (metres * scale).floor() as u64
+    }
+}
+
+#[cfg(test)]
+mod astar_test {
+    use super::*;
+    use crate::dijkstra::{run_dijsktra, search_dijkstra};
+    use crate::test_networks::{grid_id, make_grid_network, GRID_SIZE};
+
+    #[test]
+    fn same_costs_as_dijkstra() {
+        let network = make_grid_network();
+        let mut workspace = DijkstraWorkspace::for_network(&network);
+        let source = grid_id(3, 4);
+        for row in 0..GRID_SIZE {
+            for col in 0..GRID_SIZE {
+                let target = grid_id(row, col);
+                let expected = run_dijsktra(source, target, &network, 0, false).unwrap();
+                let actual = run_astar(source, target, &network, false).unwrap();
+                assert_eq!(expected.cost, actual.cost, "cost to {}", target);
+                let reused = search_astar_in(&mut workspace, source, target, &network, false);
+                assert_eq!(Some(expected.cost), reused.entry.map(|e| e.cost));
+            }
+        }
+    }
+
+    #[test]
+    fn settles_fewer_nodes() {
+        let network = make_grid_network();
+        let source = grid_id(0, 0);
+        let target = grid_id(GRID_SIZE - 1, 2);
+
+        let dijkstra = search_dijkstra(source, target, &network, 0, false);
+        let astar = search_astar(source, target, &network, false);
+
+        assert_eq!(dijkstra.entry.unwrap().cost, astar.entry.unwrap().cost);
+        assert!(
+            astar.settled_nodes < dijkstra.settled_nodes,
+            "astar settled {}, dijkstra settled {}",
+            astar.settled_nodes,
+            dijkstra.settled_nodes
+        );
+    }
+
+    #[test]
+    fn unknown_nodes() {
+        let network = make_grid_network();
+        assert!(run_astar(grid_id(0, 0), 999, &network, false).is_none());
+        assert!(run_astar(999, grid_id(0, 0), &network, false).is_none());
+    }
+}
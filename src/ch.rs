@@ -0,0 +1,545 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::dijkstra::{Entry, SearchResult};
+use crate::network::{Arc, Direction, Network, NodeIndex, OSMNodeId, OSMWayId};
+
+// witness searches give up after settling this many nodes, which only costs extra shortcuts
+const WITNESS_SETTLE_LIMIT: usize = 500;
+
+#[derive(Clone, Debug, PartialEq)]
+enum ChArcKind {
+    Original(OSMWayId),
+    // ids of the arcs into and out of the contracted node that the shortcut bypasses
+    Shortcut(usize, usize),
+}
+
+#[derive(Clone, Debug)]
+struct ChArc {
+    tail: NodeIndex,
+    head: NodeIndex,
+    cost: u64,
+    distance: u64,
+    kind: ChArcKind,
+}
+
+/// The network's arcs plus the shortcuts added while contracting its nodes one at a time.
+/// Queries only ever move to higher ranked nodes, forwards from the source and backwards
+/// from the target.
+#[derive(Debug)]
+pub struct ContractionHierarchy {
+    arcs: Vec<ChArc>,
+    rank: Vec<usize>,
+    // arc ids to higher ranked nodes, indexed by tail for forward searches
+    upward: Vec<Vec<usize>>,
+    // arc ids from higher ranked nodes, indexed by head for backward searches
+    downward: Vec<Vec<usize>>,
+}
+
+impl ContractionHierarchy {
+    /// contracts nodes in order of edge difference plus contracted neighbours,
+    /// recomputing each node's priority lazily when it reaches the front of the queue
+    pub fn new(network: &Network) -> ContractionHierarchy {
+        let mut contractor = Contractor::new(network);
+        let node_count = network.node_count();
+
+        let mut queue: BinaryHeap<Reverse<(i64, NodeIndex)>> = (0..node_count)
+            .map(|node| Reverse((contractor.priority(node, &contractor.shortcuts_for(node)), node)))
+            .collect();
+
+        let mut rank = vec![0; node_count];
+        let mut next_rank = 0;
+        while let Some(Reverse((_, node))) = queue.pop() {
+            let shortcuts = contractor.shortcuts_for(node);
+            let priority = contractor.priority(node, &shortcuts);
+            if let Some(Reverse((next_priority, _))) = queue.peek() {
+                if priority > *next_priority {
+                    queue.push(Reverse((priority, node)));
+                    continue;
+                }
+            }
+            contractor.contract(node, &shortcuts);
+            rank[node] = next_rank;
+            next_rank += 1;
+        }
+
+        let arcs = contractor.arcs;
+        let mut upward = vec![vec![]; node_count];
+        let mut downward = vec![vec![]; node_count];
+        for (id, arc) in arcs.iter().enumerate() {
+            if rank[arc.head] > rank[arc.tail] {
+                upward[arc.tail].push(id);
+            } else if rank[arc.head] < rank[arc.tail] {
+                downward[arc.head].push(id);
+            }
+        }
+
+        let hierarchy = ContractionHierarchy {
+            arcs,
+            rank,
+            upward,
+            downward,
+        };
+        info!(
+            "contracted {} nodes adding {} shortcuts",
+            node_count,
+            hierarchy.shortcut_count()
+        );
+        hierarchy
+    }
+
+    pub fn shortcut_count(&self) -> usize {
+        self.arcs
+            .iter()
+            .filter(|arc| matches!(arc.kind, ChArcKind::Shortcut(_, _)))
+            .count()
+    }
+
+    /// position of the node in the contraction order, higher ranks were contracted later
+    pub fn rank(&self, index: NodeIndex) -> usize {
+        self.rank[index]
+    }
+
+    /// every node reached from the start only moving to higher ranked nodes, with its cost
+    /// and distance. Backward searches follow arcs into the node so their costs are to the start.
+    /// Costs are upper bounds, they're only exact for the nodes on a shortest path's upward part.
+    /// Nodes the stop function picks are settled but their arcs aren't followed
+    pub(crate) fn upward_search<F: Fn(NodeIndex) -> bool>(
+        &self,
+        start: NodeIndex,
+        direction: Direction,
+        stop: F,
+    ) -> Vec<(NodeIndex, u64, u64)> {
+        let mut labels: HashMap<NodeIndex, (u64, u64)> = HashMap::new();
+        let mut heap = BinaryHeap::new();
+        labels.insert(start, (0, 0));
+        heap.push(Reverse((0, start)));
+
+        let mut settled = vec![];
+        while let Some(Reverse((cost, node))) = heap.pop() {
+            let (best_cost, distance) = labels[&node];
+            if cost > best_cost {
+                continue;
+            }
+            settled.push((node, cost, distance));
+            if stop(node) {
+                continue;
+            }
+
+            let arc_ids = match direction {
+                Direction::Forward => &self.upward[node],
+                Direction::Backward => &self.downward[node],
+            };
+            for &id in arc_ids {
+                let arc = &self.arcs[id];
+                let next = match direction {
+                    Direction::Forward => arc.head,
+                    Direction::Backward => arc.tail,
+                };
+                let next_cost = cost + arc.cost;
+                if labels.get(&next).is_none_or(|&(existing, _)| next_cost < existing) {
+                    labels.insert(next, (next_cost, distance + arc.distance));
+                    heap.push(Reverse((next_cost, next)));
+                }
+            }
+        }
+        settled
+    }
+
+    // replaces shortcuts by the original arcs they bypass, in path order
+    fn unpack(&self, arc_id: usize, path: &mut Vec<Arc<NodeIndex>>) {
+        let mut stack = vec![arc_id];
+        while let Some(id) = stack.pop() {
+            let arc = &self.arcs[id];
+            match arc.kind {
+                ChArcKind::Original(way) => path.push(Arc {
+                    head_node: arc.head,
+                    distance: arc.distance,
+                    cost: arc.cost,
+                    part_of_way: way,
+                }),
+                ChArcKind::Shortcut(first, second) => {
+                    stack.push(second);
+                    stack.push(first);
+                }
+            }
+        }
+    }
+}
+
+struct Contractor {
+    arcs: Vec<ChArc>,
+    out_arcs: Vec<Vec<usize>>,
+    in_arcs: Vec<Vec<usize>>,
+    contracted: Vec<bool>,
+    contracted_neighbours: Vec<i64>,
+}
+
+impl Contractor {
+    fn new(network: &Network) -> Contractor {
+        let node_count = network.node_count();
+        let mut contractor = Contractor {
+            arcs: vec![],
+            out_arcs: vec![vec![]; node_count],
+            in_arcs: vec![vec![]; node_count],
+            contracted: vec![false; node_count],
+            contracted_neighbours: vec![0; node_count],
+        };
+        for tail in 0..node_count {
+            for arc in network.fwd_arcs(tail) {
+                contractor.add_arc(ChArc {
+                    tail,
+                    head: arc.head_node,
+                    cost: arc.cost,
+                    distance: arc.distance,
+                    kind: ChArcKind::Original(arc.part_of_way),
+                });
+            }
+        }
+        contractor
+    }
+
+    fn add_arc(&mut self, arc: ChArc) {
+        let id = self.arcs.len();
+        self.out_arcs[arc.tail].push(id);
+        self.in_arcs[arc.head].push(id);
+        self.arcs.push(arc);
+    }
+
+    // the cheapest arc to or from each neighbour that hasn't been contracted yet
+    fn cheapest_arcs(&self, node: NodeIndex, arc_ids: &[usize], incoming: bool) -> Vec<usize> {
+        let mut cheapest: HashMap<NodeIndex, usize> = HashMap::new();
+        for &id in arc_ids {
+            let arc = &self.arcs[id];
+            let neighbour = if incoming { arc.tail } else { arc.head };
+            if neighbour == node || self.contracted[neighbour] {
+                continue;
+            }
+            let existing = cheapest.entry(neighbour).or_insert(id);
+            if arc.cost < self.arcs[*existing].cost {
+                *existing = id;
+            }
+        }
+        let mut arcs: Vec<usize> = cheapest.into_values().collect();
+        arcs.sort_unstable();
+        arcs
+    }
+
+    // pairs of arcs (u -> node, node -> w) whose path has no witness avoiding node
+    fn shortcuts_for(&self, node: NodeIndex) -> Vec<(usize, usize)> {
+        let in_arcs = self.cheapest_arcs(node, &self.in_arcs[node], true);
+        let out_arcs = self.cheapest_arcs(node, &self.out_arcs[node], false);
+
+        let mut shortcuts = vec![];
+        for &in_id in &in_arcs {
+            let in_arc = &self.arcs[in_id];
+            let max_out_cost = out_arcs
+                .iter()
+                .map(|&id| &self.arcs[id])
+                .filter(|arc| arc.head != in_arc.tail)
+                .map(|arc| arc.cost)
+                .max();
+            let max_out_cost = match max_out_cost {
+                Some(cost) => cost,
+                None => continue,
+            };
+
+            let witness_costs = self.witness_costs(in_arc.tail, node, in_arc.cost + max_out_cost);
+            for &out_id in &out_arcs {
+                let out_arc = &self.arcs[out_id];
+                if out_arc.head == in_arc.tail {
+                    continue;
+                }
+                let via_cost = in_arc.cost + out_arc.cost;
+                if witness_costs.get(&out_arc.head).is_none_or(|&cost| cost > via_cost) {
+                    shortcuts.push((in_id, out_id));
+                }
+            }
+        }
+        shortcuts
+    }
+
+    fn priority(&self, node: NodeIndex, shortcuts: &[(usize, usize)]) -> i64 {
+        let removed_arcs = self.cheapest_arcs(node, &self.in_arcs[node], true).len()
+            + self.cheapest_arcs(node, &self.out_arcs[node], false).len();
+        shortcuts.len() as i64 - removed_arcs as i64 + self.contracted_neighbours[node]
+    }
+
+    fn contract(&mut self, node: NodeIndex, shortcuts: &[(usize, usize)]) {
+        for &(in_id, out_id) in shortcuts {
+            let (in_arc, out_arc) = (&self.arcs[in_id], &self.arcs[out_id]);
+            let shortcut = ChArc {
+                tail: in_arc.tail,
+                head: out_arc.head,
+                cost: in_arc.cost + out_arc.cost,
+                distance: in_arc.distance + out_arc.distance,
+                kind: ChArcKind::Shortcut(in_id, out_id),
+            };
+            self.add_arc(shortcut);
+        }
+
+        self.contracted[node] = true;
+        let neighbours: Vec<NodeIndex> = self.in_arcs[node]
+            .iter()
+            .map(|&id| self.arcs[id].tail)
+            .chain(self.out_arcs[node].iter().map(|&id| self.arcs[id].head))
+            .collect();
+        for neighbour in neighbours {
+            if !self.contracted[neighbour] {
+                self.contracted_neighbours[neighbour] += 1;
+            }
+        }
+    }
+
+    // costs of paths from the source that avoid the excluded node, up to max_cost
+    fn witness_costs(&self, source: NodeIndex, excluded: NodeIndex, max_cost: u64) -> HashMap<NodeIndex, u64> {
+        let mut costs = HashMap::new();
+        let mut heap = BinaryHeap::new();
+        costs.insert(source, 0);
+        heap.push(Reverse((0, source)));
+
+        let mut settled = 0;
+        while let Some(Reverse((cost, node))) = heap.pop() {
+            if cost > costs[&node] {
+                continue;
+            }
+            settled += 1;
+            if cost > max_cost || settled > WITNESS_SETTLE_LIMIT {
+                break;
+            }
+            for &id in &self.out_arcs[node] {
+                let arc = &self.arcs[id];
+                if arc.head == excluded || self.contracted[arc.head] {
+                    continue;
+                }
+                let arc_cost = cost + arc.cost;
+                if costs.get(&arc.head).is_none_or(|&existing| arc_cost < existing) {
+                    costs.insert(arc.head, arc_cost);
+                    heap.push(Reverse((arc_cost, arc.head)));
+                }
+            }
+        }
+        costs
+    }
+}
+
+pub fn run_ch_query<'a>(
+    source: OSMNodeId,
+    target: OSMNodeId,
+    network: &'a Network,
+    hierarchy: &ContractionHierarchy,
+) -> Option<Entry<'a>> {
+    search_ch(source, target, network, hierarchy).entry
+}
+
+/// bidirectional dijkstra that only relaxes arcs towards higher ranked nodes, with the
+/// shortcuts on the cheapest path unpacked so the entry lists the real arcs
+pub fn search_ch<'a>(
+    source: OSMNodeId,
+    target: OSMNodeId,
+    network: &'a Network,
+    hierarchy: &ContractionHierarchy,
+) -> SearchResult<'a> {
+    let (source_index, target_index) = match (network.node_index(&source), network.node_index(&target)) {
+        (Some(s), Some(t)) => (s, t),
+        _ => {
+            debug!("ch. couldn't find source {} or target {}", source, target);
+            return SearchResult {
+                entry: None,
+                settled_nodes: 0,
+            };
+        }
+    };
+
+    let (path, settled_nodes) = search_ch_path(source_index, target_index, hierarchy);
+    SearchResult {
+        entry: path.map(|path| Entry::from_path(network, source_index, &path)),
+        settled_nodes,
+    }
+}
+
+/// the real arcs of a cheapest path from the source to the target and the number of nodes
+/// settled finding it
+pub(crate) fn search_ch_path(
+    source_index: NodeIndex,
+    target_index: NodeIndex,
+    hierarchy: &ContractionHierarchy,
+) -> (Option<Vec<Arc<NodeIndex>>>, usize) {
+    // cost and the arc used to reach each node
+    let mut labels: [HashMap<NodeIndex, (u64, Option<usize>)>; 2] = [HashMap::new(), HashMap::new()];
+    let mut heaps = [BinaryHeap::new(), BinaryHeap::new()];
+    for (side, &start) in [source_index, target_index].iter().enumerate() {
+        labels[side].insert(start, (0, None));
+        heaps[side].push(Reverse((0, start)));
+    }
+
+    let mut best: Option<(u64, NodeIndex)> = None;
+    let mut settled_nodes = 0;
+    loop {
+        let min_keys = [0, 1].map(|side| heaps[side].peek().map(|Reverse((cost, _))| *cost));
+        let side = match min_keys {
+            [Some(f), Some(b)] => if f <= b { 0 } else { 1 },
+            [Some(_), None] => 0,
+            [None, Some(_)] => 1,
+            [None, None] => break,
+        };
+        let Reverse((cost, node)) = heaps[side].pop().unwrap();
+        if best.is_some_and(|(best_cost, _)| cost >= best_cost) {
+            // the other queue's minimum is no smaller so neither search can improve the best path
+            break;
+        }
+        if cost > labels[side][&node].0 {
+            continue;
+        }
+        settled_nodes += 1;
+
+        if let Some(&(other_cost, _)) = labels[1 - side].get(&node) {
+            if best.is_none_or(|(best_cost, _)| cost + other_cost < best_cost) {
+                best = Some((cost + other_cost, node));
+            }
+        }
+
+        let arc_ids = if side == 0 {
+            &hierarchy.upward[node]
+        } else {
+            &hierarchy.downward[node]
+        };
+        for &id in arc_ids {
+            let arc = &hierarchy.arcs[id];
+            let next = if side == 0 { arc.head } else { arc.tail };
+            let next_cost = cost + arc.cost;
+            if labels[side].get(&next).is_none_or(|&(existing, _)| next_cost < existing) {
+                labels[side].insert(next, (next_cost, Some(id)));
+                heaps[side].push(Reverse((next_cost, next)));
+            }
+        }
+    }
+
+    let path = best.map(|(_, meeting_node)| {
+        let mut up_arcs = vec![];
+        let mut node = meeting_node;
+        while let Some(id) = labels[0][&node].1 {
+            up_arcs.push(id);
+            node = hierarchy.arcs[id].tail;
+        }
+        up_arcs.reverse();
+
+        let mut down_arcs = vec![];
+        let mut node = meeting_node;
+        while let Some(id) = labels[1][&node].1 {
+            down_arcs.push(id);
+            node = hierarchy.arcs[id].head;
+        }
+
+        let mut path = vec![];
+        for id in up_arcs.into_iter().chain(down_arcs) {
+            hierarchy.unpack(id, &mut path);
+        }
+        path
+    });
+    (path, settled_nodes)
+}
+
+#[cfg(test)]
+mod ch_test {
+    use super::*;
+    use crate::dijkstra::{run_dijsktra, search_dijkstra};
+    use crate::network::NetworkBuilder;
+    use crate::test_networks::{grid_id, make_grid_network, GRID_SIZE};
+
+    #[test]
+    fn same_costs_as_dijkstra() {
+        let network = make_grid_network();
+        let hierarchy = ContractionHierarchy::new(&network);
+        for source_row in 0..GRID_SIZE {
+            let source = grid_id(source_row, (source_row * 3) % GRID_SIZE);
+            for row in 0..GRID_SIZE {
+                for col in 0..GRID_SIZE {
+                    let target = grid_id(row, col);
+                    let expected = run_dijsktra(source, target, &network, 0, false).unwrap();
+                    let actual = run_ch_query(source, target, &network, &hierarchy).unwrap();
+                    assert_eq!(expected.cost, actual.cost, "cost from {} to {}", source, target);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn shortcuts_unpack_to_network_arcs() {
+        let network = make_grid_network();
+        let hierarchy = ContractionHierarchy::new(&network);
+        assert!(hierarchy.shortcut_count() > 0);
+
+        for (id, shortcut) in hierarchy.arcs.iter().enumerate() {
+            let mut path = vec![];
+            hierarchy.unpack(id, &mut path);
+
+            let mut node = shortcut.tail;
+            for arc in &path {
+                assert!(network.fwd_arcs(node).iter().any(|a| a == *arc));
+                node = arc.head_node;
+            }
+            assert_eq!(shortcut.head, node);
+            assert_eq!(shortcut.cost, path.iter().map(|arc| arc.cost).sum::<u64>());
+            assert_eq!(shortcut.distance, path.iter().map(|arc| arc.distance).sum::<u64>());
+        }
+    }
+
+    #[test]
+    fn same_distances_as_dijkstra() {
+        let network = make_grid_network();
+        let hierarchy = ContractionHierarchy::new(&network);
+        let (source, target) = (grid_id(0, 0), grid_id(GRID_SIZE - 1, GRID_SIZE - 1));
+
+        let expected = run_dijsktra(source, target, &network, 0, true).unwrap();
+        let actual = run_ch_query(source, target, &network, &hierarchy).unwrap();
+        assert_eq!(expected.cost, actual.cost);
+        assert_eq!(expected.distance, actual.distance);
+    }
+
+    #[test]
+    fn settles_fewer_nodes() {
+        let network = make_grid_network();
+        let hierarchy = ContractionHierarchy::new(&network);
+        let (source, target) = (grid_id(0, 0), grid_id(GRID_SIZE - 1, GRID_SIZE - 1));
+
+        let dijkstra = search_dijkstra(source, target, &network, 0, false);
+        let ch = search_ch(source, target, &network, &hierarchy);
+        assert!(ch.settled_nodes < dijkstra.settled_nodes);
+    }
+
+    #[test]
+    fn traversed_ways() {
+        let network_json = r#"{
+        "all_nodes":{
+            "1": {"id": 1, "latitude": 0, "longitude": 0},
+            "2": {"id": 2, "latitude": 0, "longitude": 0},
+            "3": {"id": 3, "latitude": 0, "longitude": 0},
+            "4": {"id": 4, "latitude": 0, "longitude": 0},
+            "5": {"id": 5, "latitude": 0, "longitude": 0}
+        },
+        "used_nodes":[1, 2, 3, 4, 5],
+        "way_info":{
+            "10": { "id": 10, "name": "High Street" },
+            "20": { "id": 20, "name": "Station Road" },
+            "30": { "id": 30, "name": "Mill Lane" }
+        },
+        "adjacent_arcs":{
+            "1": [{"head_node": 2, "distance": 3, "cost": 3, "part_of_way": 10}],
+            "2": [{"head_node": 3, "distance": 4, "cost": 4, "part_of_way": 10}, {"head_node": 5, "distance": 11, "cost": 11, "part_of_way": 30}],
+            "3": [{"head_node": 4, "distance": 5, "cost": 5, "part_of_way": 20}],
+            "4": [{"head_node": 5, "distance": 1, "cost": 1, "part_of_way": 20}]
+        }
+    }
+    "#;
+        let network = NetworkBuilder::from_json(network_json).unwrap().build_network().unwrap();
+        let hierarchy = ContractionHierarchy::new(&network);
+
+        let entry = run_ch_query(1, 5, &network, &hierarchy).unwrap();
+        assert_eq!(13, entry.cost);
+        assert_eq!(13, entry.distance);
+        assert_eq!("High Street->Station Road", entry.report_traversed_ways());
+
+        assert!(run_ch_query(5, 1, &network, &hierarchy).is_none());
+    }
+}
@@ -1,11 +1,13 @@
-extern crate env_logger;
-extern crate log;
+use crate::network::{Arc, Direction, Network, NodeIndex, OSMNodeId};
+use crate::turns::TurnCostModel;
 
-use crate::network::{Network, NetworkBuilder, NodeIndex, OSMNodeId};
-
-use std::cmp::{Ord, Ordering};
+use std::cmp::Reverse;
 use std::collections::BinaryHeap;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+mod workspace;
+
+pub use self::workspace::DijkstraWorkspace;
 
 const DEBUG: bool = false;
 const REPORT_HEAP: bool = false;
@@ -14,18 +16,9 @@ const REPORT_HEAP: bool = false;
 pub struct Entry<'a> {
     node: NodeIndex,
     pub cost: u64,
-    arc_name: Option<&'a str>,
-    prev_entry: Option<Box<Entry<'a>>>,
-}
-impl<'a> Ord for Entry<'a> {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.cost.cmp(&other.cost)
-    }
-}
-impl<'a> PartialOrd for Entry<'a> {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(other.cost.cmp(&self.cost))
-    }
+    pub distance: u64,
+    // the name of the way of each arc from the source, empty when the path wasn't traced
+    arc_names: Vec<Option<&'a str>>,
 }
 impl<'a> PartialEq for Entry<'a> {
     fn eq(&self, other: &Self) -> bool {
@@ -35,200 +28,587 @@ impl<'a> PartialEq for Entry<'a> {
 impl<'a> Eq for Entry<'a> {}
 
 impl<'a> Entry<'a> {
-    pub fn report_traversed_ways(&self) -> String {
-        let mut prev_entries = vec![];
-        let mut prev = &self.prev_entry;
-
-        while prev.is_some() {
-            match prev {
-                Some(p) => {
-                    prev_entries.push(p);
-                    prev = &p.prev_entry;
-                }
-                None => (),
-            }
+    /// the entry a traced search would have ended with after following the arcs from the source
+    pub(crate) fn from_path(network: &'a Network, source_index: NodeIndex, path: &[Arc<NodeIndex>]) -> Entry<'a> {
+        Entry::from_penalised_path(network, source_index, path.iter().map(|arc| (0, arc)))
+    }
+
+    /// the entry an untraced search ends with, knowing only where it got to
+    pub(crate) fn without_path(node: NodeIndex, cost: u64, distance: u64) -> Entry<'a> {
+        Entry {
+            node,
+            cost,
+            distance,
+            arc_names: vec![],
         }
+    }
 
-        let mut arc_names: Vec<&str> = prev_entries
-            .iter()
-            .map(|e| e.arc_name.unwrap_or("unknown"))
-            .collect();
+    /// like from_path with a penalty added to the cost before each arc is followed
+    fn from_penalised_path<'p, I>(network: &'a Network, source_index: NodeIndex, path: I) -> Entry<'a>
+    where
+        I: Iterator<Item = (u64, &'p Arc<NodeIndex>)>,
+    {
+        let mut entry = Entry {
+            node: source_index,
+            cost: 0,
+            distance: 0,
+            arc_names: vec![],
+        };
+        for (penalty, arc) in path {
+            entry.node = arc.head_node;
+            entry.cost += penalty + arc.cost;
+            entry.distance += arc.distance;
+            entry.arc_names.push(network.get_way_info(arc).and_then(|i| i.name.as_deref()));
+        }
+        entry
+    }
+
+    pub fn report_traversed_ways(&self) -> String {
+        let mut arc_names: Vec<&str> = self.arc_names.iter().map(|name| name.unwrap_or("unknown")).collect();
         arc_names.dedup();
-        arc_names.reverse();
-        let ways = arc_names.join("->");
-        ways
+        arc_names.join("->")
     }
 }
 
+pub struct SearchResult<'a> {
+    pub entry: Option<Entry<'a>>,
+    pub settled_nodes: usize,
+}
+
 pub fn run_dijsktra(
     source: OSMNodeId,
     target: OSMNodeId,
     network: &Network,
     max_distance: u64,
     trace_path: bool,
-) -> Option<Entry> {
-    if network.get_node(&target).is_none() {
-        println!("!!run_dijkstra. target not in network")
+) -> Option<Entry<'_>> {
+    search_dijkstra(source, target, network, max_distance, trace_path).entry
+}
+
+pub fn search_dijkstra(
+    source: OSMNodeId,
+    target: OSMNodeId,
+    network: &Network,
+    max_distance: u64,
+    trace_path: bool,
+) -> SearchResult<'_> {
+    let mut workspace = DijkstraWorkspace::for_network(network);
+    search_dijkstra_in(&mut workspace, source, target, network, max_distance, trace_path)
+}
+
+/// search_dijkstra reusing the workspace of earlier queries on the same network. The
+/// workspace holds the costs and predecessors of this query until the next one
+pub fn search_dijkstra_in<'a>(
+    workspace: &mut DijkstraWorkspace,
+    source: OSMNodeId,
+    target: OSMNodeId,
+    network: &'a Network,
+    max_distance: u64,
+    trace_path: bool,
+) -> SearchResult<'a> {
+    let source_index = match network.node_indexes.get(&source) {
+        Some(s) => *s,
+        None => {
+            debug!("dijkstra. couldn't find source index for {}", source);
+            return SearchResult { entry: None, settled_nodes: 0 };
+        }
+    };
+    // a target outside the network settles every node reachable from the source
+    let target_index = network.node_indexes.get(&target).cloned();
+    if target_index.is_none() {
+        debug!("dijkstra. target {} not in network, searching whole network", target);
     }
 
-    let mut costs: HashMap<NodeIndex, u64> = HashMap::new();
+    search_with_potential(workspace, source_index, target_index, network, max_distance, trace_path, |_| 0)
+}
 
-    let mut heap = BinaryHeap::new();
+// the potential must never overestimate the remaining cost to the target and must
+// not drop by more than an arc's cost along that arc, otherwise settled nodes can be wrong
+pub(crate) fn search_with_potential<'a, P: Fn(NodeIndex) -> u64>(
+    workspace: &mut DijkstraWorkspace,
+    source_index: NodeIndex,
+    target_index: Option<NodeIndex>,
+    network: &'a Network,
+    max_distance: u64,
+    trace_path: bool,
+    potential: P,
+) -> SearchResult<'a> {
+    let search = PrunedSearch {
+        source_index,
+        target_index,
+        max_distance,
+        trace_path,
+    };
+    search.run(workspace, network, potential, |_, arc, _| Some(arc.cost))
+}
+
+/// a search that asks for the cost of each arc it follows, given the arc's id from
+/// Network::fwd_arc_ids, the arc and the cost of reaching its tail. An arc without a
+/// cost isn't followed
+pub(crate) struct PrunedSearch {
+    pub source_index: NodeIndex,
+    pub target_index: Option<NodeIndex>,
+    pub max_distance: u64,
+    pub trace_path: bool,
+}
 
-    let maybe_source_index = network.node_indexes.get(&source);
-    let maybe_target_index = network.node_indexes.get(&target);
-    let (source_index, target_index) = match (maybe_source_index, maybe_target_index) {
-        (Some(s), Some(t)) => (*s,*t),
+impl PrunedSearch {
+    pub fn run<'a, P, F>(
+        &self,
+        workspace: &mut DijkstraWorkspace,
+        network: &'a Network,
+        potential: P,
+        arc_cost: F,
+    ) -> SearchResult<'a>
+    where
+        P: Fn(NodeIndex) -> u64,
+        F: Fn(usize, &Arc<NodeIndex>, u64) -> Option<u64>,
+    {
+        let PrunedSearch {
+            source_index,
+            target_index,
+            max_distance,
+            trace_path,
+        } = *self;
+        assert_eq!(network.node_count(), workspace.node_count(), "workspace is for a different network");
+        workspace.reset();
+        workspace.relax(source_index, 0, 0, None);
+        workspace.heap.push_or_decrease(source_index, potential(source_index));
+
+        let mut settled_nodes = 0;
+        while let Some((_, node)) = workspace.heap.pop() {
+            let cost = workspace.cost(node).unwrap();
+            if max_distance > 0 && cost > max_distance {
+                break;
+            }
+            settled_nodes += 1;
+
+            if DEBUG {
+                print_progress(node, cost, workspace);
+                if REPORT_HEAP {
+                    print_heap(workspace)
+                }
+            }
+
+            if Some(node) == target_index {
+                debug!("dijkstra. finished after settling {} nodes", settled_nodes);
+                let entry = if trace_path {
+                    let path = workspace.path_to(node, network).unwrap();
+                    let mut entry = Entry::from_penalised_path(network, source_index, path.iter().map(|arc| (0, arc)));
+                    // the costs the search used needn't be the arcs' own
+                    entry.cost = cost;
+                    entry
+                } else {
+                    Entry::without_path(node, cost, workspace.distance(node).unwrap())
+                };
+                return SearchResult { entry: Some(entry), settled_nodes };
+            }
+
+            let arcs = network.fwd_arcs(node);
+            if DEBUG { println!("forward arcs from {}, {:?}", node, arcs)}
+
+            let distance = workspace.distance(node).unwrap();
+            for ((position, arc), arc_id) in arcs.iter().enumerate().zip(network.fwd_arc_ids(node)) {
+                let arc_cost = match arc_cost(arc_id, &arc, cost) {
+                    Some(arc_cost) => cost + arc_cost,
+                    None => continue,
+                };
+                if workspace.relax(arc.head_node, arc_cost, distance + arc.distance, Some((node, position))) {
+                    workspace.heap.push_or_decrease(arc.head_node, arc_cost + potential(arc.head_node));
+                }
+            }
+        }
+        SearchResult { entry: None, settled_nodes }
+    }
+}
+
+/// the cost, distance and predecessor of every node settled by a search from one source.
+/// Nodes that weren't settled have no cost
+#[derive(Clone, Debug)]
+pub struct DistanceTable {
+    source: NodeIndex,
+    costs: Vec<u64>,
+    distances: Vec<u64>,
+    predecessors: Vec<Option<NodeIndex>>,
+    settled_nodes: usize,
+}
+
+impl DistanceTable {
+    pub fn source(&self) -> NodeIndex {
+        self.source
+    }
+
+    pub fn cost(&self, node: NodeIndex) -> Option<u64> {
+        Some(self.costs[node]).filter(|&cost| cost != u64::MAX)
+    }
+
+    pub fn distance(&self, node: NodeIndex) -> Option<u64> {
+        self.cost(node).map(|_| self.distances[node])
+    }
+
+    pub fn predecessor(&self, node: NodeIndex) -> Option<NodeIndex> {
+        self.predecessors[node]
+    }
+
+    pub fn settled_nodes(&self) -> usize {
+        self.settled_nodes
+    }
+
+    /// the cost of every node, u64::MAX for the ones that weren't settled
+    pub(crate) fn into_costs(self) -> Vec<u64> {
+        self.costs
+    }
+
+    /// the nodes from the source to the node, None if the node wasn't settled
+    pub fn path(&self, node: NodeIndex) -> Option<Vec<NodeIndex>> {
+        self.cost(node)?;
+        let mut path = vec![node];
+        while let Some(previous) = self.predecessors[*path.last().unwrap()] {
+            path.push(previous);
+        }
+        path.reverse();
+        Some(path)
+    }
+}
+
+/// the costs from the source to every node it can reach
+pub fn dijkstra_one_to_all(source: OSMNodeId, network: &Network) -> Option<DistanceTable> {
+    let source_index = network.node_index(&source)?;
+    let mut workspace = DijkstraWorkspace::for_network(network);
+    Some(settle_nodes(&mut workspace, source_index, network, Direction::Forward, None))
+}
+
+/// the costs from the source to the targets, stopping as soon as every target is settled.
+/// Targets that aren't in the network are ignored, None if the source isn't
+pub fn dijkstra_one_to_many(source: OSMNodeId, targets: &[OSMNodeId], network: &Network) -> Option<DistanceTable> {
+    let source_index = network.node_index(&source)?;
+    let target_indexes: HashSet<NodeIndex> = targets
+        .iter()
+        .filter_map(|target| {
+            let index = network.node_index(target);
+            if index.is_none() {
+                debug!("dijkstra one to many. target {} not in network", target);
+            }
+            index
+        })
+        .collect();
+    let mut workspace = DijkstraWorkspace::for_network(network);
+    Some(settle_nodes(&mut workspace, source_index, network, Direction::Forward, Some(&target_indexes)))
+}
+
+/// settles nodes in cost order until every target is settled, or every reachable node
+/// without targets. A backward search gives the costs from every node to the source
+pub(crate) fn settle_nodes(
+    workspace: &mut DijkstraWorkspace,
+    source_index: NodeIndex,
+    network: &Network,
+    direction: Direction,
+    targets: Option<&HashSet<NodeIndex>>,
+) -> DistanceTable {
+    let mut table = DistanceTable {
+        source: source_index,
+        costs: vec![u64::MAX; network.node_count()],
+        distances: vec![0; network.node_count()],
+        predecessors: vec![None; network.node_count()],
+        settled_nodes: 0,
+    };
+    // every node is settled once, so counting the targets settled is enough
+    let mut unsettled_targets = targets.map(HashSet::len);
+    if unsettled_targets == Some(0) {
+        return table;
+    }
+    workspace.reset();
+    workspace.relax(source_index, 0, 0, None);
+    workspace.heap.push_or_decrease(source_index, 0);
+
+    while let Some((cost, node)) = workspace.heap.pop() {
+        table.costs[node] = cost;
+        table.distances[node] = workspace.distance(node).unwrap();
+        table.predecessors[node] = workspace.predecessor(node);
+        table.settled_nodes += 1;
+        if let (Some(targets), Some(unsettled)) = (targets, unsettled_targets.as_mut()) {
+            if targets.contains(&node) {
+                *unsettled -= 1;
+                if *unsettled == 0 {
+                    break;
+                }
+            }
+        }
+
+        // only the fields a plain search needs, way ids are left unread
+        let distance = table.distances[node];
+        let arcs = network.arcs(node, direction);
+        let fields = arcs.heads().iter().zip(arcs.costs()).zip(arcs.distances());
+        for (position, ((&head_node, &arc_cost), &arc_distance)) in fields.enumerate() {
+            let arc_cost = cost + arc_cost;
+            if workspace.relax(head_node, arc_cost, distance + arc_distance, Some((node, position))) {
+                workspace.heap.push_or_decrease(head_node, arc_cost);
+            }
+        }
+    }
+    debug!("dijkstra. settled {} nodes", table.settled_nodes);
+    table
+}
+
+/// A path found by meeting a forward search from the source with a backward search
+/// from the target. The arcs either side of the meeting node run from source to target.
+#[derive(Clone, Debug)]
+pub struct BidirectionalPath<'a> {
+    network: &'a Network,
+    source_index: NodeIndex,
+    pub cost: u64,
+    pub distance: u64,
+    pub meeting_node: OSMNodeId,
+    to_meeting: Vec<Arc<NodeIndex>>,
+    from_meeting: Vec<Arc<NodeIndex>>,
+}
+
+impl<'a> BidirectionalPath<'a> {
+    /// the arcs from the source to the target through the meeting node
+    pub fn arcs(&self) -> Vec<Arc<NodeIndex>> {
+        self.to_meeting.iter().chain(self.from_meeting.iter()).cloned().collect()
+    }
+
+    pub fn nodes(&self) -> Vec<OSMNodeId> {
+        let mut nodes = vec![self.network.node_at(self.source_index).id];
+        nodes.extend(self.arcs().iter().map(|arc| self.network.node_at(arc.head_node).id));
+        nodes
+    }
+
+    pub fn to_entry(&self) -> Entry<'a> {
+        Entry::from_path(self.network, self.source_index, &self.arcs())
+    }
+}
+
+// cost and the neighbour plus arc a node was reached by
+type BidirectionalLabel = (u64, Option<(NodeIndex, Arc<NodeIndex>)>);
+
+pub struct BidirectionalResult<'a> {
+    pub path: Option<BidirectionalPath<'a>>,
+    pub settled_nodes: usize,
+}
+
+pub fn run_bidirectional_dijkstra(
+    source: OSMNodeId,
+    target: OSMNodeId,
+    network: &Network,
+) -> Option<BidirectionalPath<'_>> {
+    search_bidirectional_dijkstra(source, target, network).path
+}
+
+/// alternates between a forward search from the source and a backward search from the
+/// target, stopping once the two queue minimums add up to at least the best meeting cost
+pub fn search_bidirectional_dijkstra(
+    source: OSMNodeId,
+    target: OSMNodeId,
+    network: &Network,
+) -> BidirectionalResult<'_> {
+    let (source_index, target_index) = match (network.node_index(&source), network.node_index(&target)) {
+        (Some(s), Some(t)) => (s, t),
         _ => {
-            println!("dijkstra. Couldn't find source & target indexes");
-            return None
+            debug!("bidirectional dijkstra. couldn't find source {} or target {}", source, target);
+            return BidirectionalResult {
+                path: None,
+                settled_nodes: 0,
+            };
         }
     };
 
-    // best_costs.insert(source, 0);
-    heap.push(Entry {
-        node: source_index,
-        cost: 0,
-        arc_name: None,
-        prev_entry: None,
-    });
-    costs.insert(source_index, 0);
+    let directions = [Direction::Forward, Direction::Backward];
+    let mut labels: [HashMap<NodeIndex, BidirectionalLabel>; 2] = [HashMap::new(), HashMap::new()];
+    let mut heaps = [BinaryHeap::new(), BinaryHeap::new()];
+    for (side, &start) in [source_index, target_index].iter().enumerate() {
+        labels[side].insert(start, (0, None));
+        heaps[side].push(Reverse((0, start)));
+    }
 
-    let mut count = 0;
-    while let Some(entry) = heap.pop() {
-        if max_distance > 0 && entry.cost > max_distance {
+    let mut best: Option<(u64, NodeIndex)> = if source_index == target_index {
+        Some((0, source_index))
+    } else {
+        None
+    };
+    let mut settled_nodes = 0;
+    let mut side = 1;
+    loop {
+        let min_costs = [0, 1].map(|s| heaps[s].peek().map(|Reverse((cost, _))| *cost));
+        let (forward_min, backward_min) = match min_costs {
+            [Some(f), Some(b)] => (f, b),
+            _ => break,
+        };
+        if best.is_some_and(|(best_cost, _)| forward_min + backward_min >= best_cost) {
             break;
         }
 
-        if DEBUG {
-            count += 1;
-            if count % 1 == 0 {
-                print_progress(&entry, &costs, &heap)
-            }
-            if REPORT_HEAP {
-                print_heap(&heap)
+        side = 1 - side;
+        let Reverse((cost, node)) = heaps[side].pop().unwrap();
+        if cost > labels[side][&node].0 {
+            continue;
+        }
+        settled_nodes += 1;
+
+        for arc in network.arcs(node, directions[side]) {
+            let arc_cost = cost + arc.cost;
+            if labels[side].get(&arc.head_node).is_none_or(|&(existing, _)| arc_cost < existing) {
+                labels[side].insert(arc.head_node, (arc_cost, Some((node, arc))));
+                heaps[side].push(Reverse((arc_cost, arc.head_node)));
+
+                if let Some(&(other_cost, _)) = labels[1 - side].get(&arc.head_node) {
+                    if best.is_none_or(|(best_cost, _)| arc_cost + other_cost < best_cost) {
+                        best = Some((arc_cost + other_cost, arc.head_node));
+                    }
+                }
             }
         }
+    }
 
-        if entry.node == target_index {
-            println!("dijkstra. finished");
-            return Some(entry);
+    let path = best.map(|(cost, meeting_index)| {
+        let mut to_meeting = vec![];
+        let mut node = meeting_index;
+        while let Some((previous, arc)) = labels[0][&node].1 {
+            to_meeting.push(arc);
+            node = previous;
         }
+        to_meeting.reverse();
 
-        let arcs = &network.forward_graph[entry.node];
-        if DEBUG { println!("forward arcs from {}, {:?}", entry.node, arcs.iter())}
+        // reverse arcs point back to where the original arc started
+        let mut from_meeting = vec![];
+        let mut node = meeting_index;
+        while let Some((next, arc)) = labels[1][&node].1 {
+            from_meeting.push(Arc {
+                head_node: next,
+                distance: arc.distance,
+                cost: arc.cost,
+                part_of_way: arc.part_of_way,
+            });
+            node = next;
+        }
 
-            for arc in arcs {
-                let arc_name = if trace_path {
-                    // arc.part_of_way.as_ref().map(|s| s.as_str())
-                    network.get_way_info(&arc).and_then(|i| i.name.as_ref().map(String::as_str))
-                } else {
-                    None
-                };
-                let prev_entry = if trace_path {
-                    Some(Box::new(entry.clone()))
-                } else {
-                    None
-                };
-                let arc_entry = Entry {
-                    node: arc.head_node,
-                    cost: arc.cost + entry.cost,
-                    arc_name: arc_name,
-                    prev_entry: prev_entry,
-                };
-                if DEBUG && REPORT_HEAP {
-                    println!("\tneighbouring arc to {}, {:?}", arc.head_node, arc_name)
-                }
+        BidirectionalPath {
+            network,
+            source_index,
+            cost,
+            distance: to_meeting.iter().chain(from_meeting.iter()).map(|arc| arc.distance).sum(),
+            meeting_node: network.node_at(meeting_index).id,
+            to_meeting,
+            from_meeting,
+        }
+    });
+    BidirectionalResult { path, settled_nodes }
+}
 
-                if is_best_cost(&arc_entry, &costs) {
-                    costs.insert(arc_entry.node, arc_entry.cost);
-                    heap.push(arc_entry);
-                }
+/// an arc identified by the node it leaves and its position in that node's arcs
+type ArcKey = (NodeIndex, usize);
+
+pub fn run_turn_aware_dijkstra<'a>(
+    source: OSMNodeId,
+    target: OSMNodeId,
+    network: &'a Network,
+    turn_costs: Option<&TurnCostModel>,
+) -> Option<Entry<'a>> {
+    search_turn_aware_dijkstra(source, target, network, turn_costs).entry
+}
+
+/// dijkstra with a label per arc rather than per node, so that every label knows the arc
+/// it arrived over. Turns the network bans at its head are never taken and the turn cost
+/// model, when given, charges for each turn. A node can be passed more than once when a
+/// restriction or a turn penalty makes a detour through it cheaper. settled_nodes counts
+/// settled arcs
+pub fn search_turn_aware_dijkstra<'a>(
+    source: OSMNodeId,
+    target: OSMNodeId,
+    network: &'a Network,
+    turn_costs: Option<&TurnCostModel>,
+) -> SearchResult<'a> {
+    let (source_index, target_index) = match (network.node_index(&source), network.node_index(&target)) {
+        (Some(s), Some(t)) => (s, t),
+        _ => {
+            debug!("turn aware dijkstra. couldn't find source {} or target {}", source, target);
+            return SearchResult { entry: None, settled_nodes: 0 };
+        }
+    };
+    if source_index == target_index {
+        return SearchResult {
+            entry: Some(Entry::from_path(network, source_index, &[])),
+            settled_nodes: 0,
         };
     }
-    None
+
+    let arc_at = |(tail, position): ArcKey| network.fwd_arcs(tail).get(position);
+    let mut best_costs: HashMap<ArcKey, u64> = HashMap::new();
+    // the arc followed before each arc and the turn penalty between them
+    let mut parents: HashMap<ArcKey, (ArcKey, u64)> = HashMap::new();
+    let mut heap = BinaryHeap::new();
+
+    for (position, arc) in network.fwd_arcs(source_index).iter().enumerate() {
+        let key = (source_index, position);
+        if best_costs.get(&key).is_none_or(|&best| arc.cost < best) {
+            best_costs.insert(key, arc.cost);
+            heap.push(Reverse((arc.cost, key)));
+        }
+    }
+
+    let mut settled_arcs = 0;
+    while let Some(Reverse((cost, key))) = heap.pop() {
+        if cost > best_costs[&key] {
+            continue;
+        }
+        settled_arcs += 1;
+        let arc = arc_at(key);
+        let via = arc.head_node;
+        if via == target_index {
+            let mut path = vec![];
+            let mut current = key;
+            while let Some(&(parent, penalty)) = parents.get(&current) {
+                path.push((penalty, arc_at(current)));
+                current = parent;
+            }
+            path.push((0, arc_at(current)));
+            path.reverse();
+            return SearchResult {
+                entry: Some(Entry::from_penalised_path(network, source_index, path.iter().map(|(penalty, arc)| (*penalty, arc)))),
+                settled_nodes: settled_arcs,
+            };
+        }
+        for (position, next) in network.fwd_arcs(via).iter().enumerate() {
+            if !network.is_turn_allowed(&arc, via, &next) {
+                continue;
+            }
+            let penalty = turn_costs.map_or(0, |model| model.turn_cost(network, key.0, via, next.head_node));
+            let next_key = (via, position);
+            let next_cost = cost + penalty + next.cost;
+            if best_costs.get(&next_key).is_none_or(|&best| next_cost < best) {
+                best_costs.insert(next_key, next_cost);
+                parents.insert(next_key, (key, penalty));
+                heap.push(Reverse((next_cost, next_key)));
+            }
+        }
+    }
+    SearchResult { entry: None, settled_nodes: settled_arcs }
 }
 
-fn print_progress(
-    current_entry: &Entry,
-    best_costs: &HashMap<NodeIndex, u64>,
-    heap: &BinaryHeap<Entry>,
-) {
+fn print_progress(node: NodeIndex, cost: u64, workspace: &DijkstraWorkspace) {
     println!("--");
-    println!(
-        "assessing node {} with cost {}",
-        current_entry.node, current_entry.cost
-    );
-    println!("{} entries still in heap", heap.len());
-    println!("{} entries in best_cost", best_costs.len());
+    println!("assessing node {} with cost {}", node, cost);
+    println!("{} entries still in heap", workspace.heap.len());
 }
 
-fn print_heap(heap: &BinaryHeap<Entry>) {
+fn print_heap(workspace: &DijkstraWorkspace) {
     println!(
         "current heap <{}>",
-        heap.iter()
-            .map(|e| format!("(n:{}, c:{})", e.node, e.cost))
+        workspace
+            .heap
+            .iter()
+            .map(|&(priority, node)| format!("(n:{}, p:{})", node, priority))
             .collect::<Vec<String>>()
             .join(", ")
     );
 }
 
-fn is_best_cost(entry: &Entry, best_costs: &HashMap<NodeIndex, u64>) -> bool {
-    let maybe_best = best_costs.get(&entry.node);
-    match maybe_best {
-        Some(existing_best) if entry.cost < *existing_best => true,
-        None => true,
-        _ => false,
-    }
-}
-
 #[cfg(test)]
 mod dijkstra_test {
     use super::*;
-
-    #[test]
-    fn test_best_cost() {
-        assert_eq!(
-            true,
-            is_best_cost(
-                &Entry {
-                    node: 1,
-                    cost: 10,
-                    arc_name: None,
-                    prev_entry: None
-                },
-                &HashMap::new()
-            )
-        );
-
-        let mut best_costs = HashMap::new();
-        best_costs.insert(1, 9);
-
-        assert_eq!(
-            true,
-            is_best_cost(
-                &Entry {
-                    node: 1,
-                    cost: 8,
-                    arc_name: None,
-                    prev_entry: None
-                },
-                &best_costs
-            )
-        );
-        assert_eq!(
-            false,
-            is_best_cost(
-                &Entry {
-                    node: 1,
-                    cost: 11,
-                    arc_name: None,
-                    prev_entry: None
-                },
-                &best_costs
-            )
-        );
-    }
+    use crate::network::NetworkBuilder;
+    use crate::test_networks::{grid_id, make_grid_network, make_oneway_network, GRID_SIZE};
 
     #[test]
     fn test_dijsktra() {
@@ -242,6 +622,169 @@ mod dijkstra_test {
         do_disjktra(&dummy_network, 95, 94, 5);
     }
 
+    #[test]
+    fn test_traversed_ways() {
+        let network_json = r#"{
+        "all_nodes":{
+            "1": {"id": 1, "latitude": 0, "longitude": 0},
+            "2": {"id": 2, "latitude": 0, "longitude": 0},
+            "3": {"id": 3, "latitude": 0, "longitude": 0},
+            "4": {"id": 4, "latitude": 0, "longitude": 0}
+        },
+        "used_nodes":[1, 2, 3, 4],
+        "way_info":{
+            "10": { "id": 10, "name": "High Street" },
+            "20": { "id": 20, "name": "Station Road" }
+        },
+        "adjacent_arcs":{
+            "1": [{"head_node": 2, "distance": 3, "cost": 3, "part_of_way": 10}],
+            "2": [{"head_node": 3, "distance": 4, "cost": 4, "part_of_way": 10}],
+            "3": [{"head_node": 4, "distance": 5, "cost": 5, "part_of_way": 20}]
+        }
+    }
+    "#;
+        let network = NetworkBuilder::from_json(network_json).unwrap().build_network().unwrap();
+
+        let entry = run_dijsktra(1, 4, &network, 0, true).unwrap();
+        assert_eq!(12, entry.cost);
+        assert_eq!(12, entry.distance);
+        assert_eq!("High Street->Station Road", entry.report_traversed_ways());
+    }
+
+    #[test]
+    fn test_workspace_reused_between_queries() {
+        let network = make_grid_network();
+        let mut workspace = DijkstraWorkspace::for_network(&network);
+        for &(source, target) in &[((0, 0), (9, 9)), ((9, 9), (0, 0)), ((2, 3), (8, 1)), ((5, 5), (5, 5))] {
+            let (source, target) = (grid_id(source.0, source.1), grid_id(target.0, target.1));
+            let fresh = search_dijkstra(source, target, &network, 0, true).entry.unwrap();
+            let reused = search_dijkstra_in(&mut workspace, source, target, &network, 0, true).entry.unwrap();
+            assert_eq!(fresh.cost, reused.cost);
+            assert_eq!(fresh.distance, reused.distance);
+
+            // the path through the predecessors ends at the target and adds up to the cost
+            let target_index = network.node_index(&target).unwrap();
+            let path = workspace.path_to(target_index, &network).unwrap();
+            assert_eq!(reused.cost, path.iter().map(|arc| arc.cost).sum::<u64>());
+            assert_eq!(reused.arc_names.len(), path.len());
+            assert_eq!(Some(target_index), path.last().map_or(network.node_index(&source), |arc| Some(arc.head_node)));
+        }
+    }
+
+    #[test]
+    fn test_one_to_all() {
+        let network = make_dummy_network();
+        let table = dijkstra_one_to_all(91, &network).unwrap();
+        assert_eq!(network.node_count(), table.settled_nodes());
+        for &target in &[91, 92, 93, 94, 95] {
+            let index = network.node_index(&target).unwrap();
+            let expected = run_dijsktra(91, target, &network, 0, false).unwrap();
+            assert_eq!(Some(expected.cost), table.cost(index));
+            assert_eq!(Some(expected.distance), table.distance(index));
+        }
+
+        let path = table.path(network.node_index(&95).unwrap()).unwrap();
+        let ids: Vec<_> = path.iter().map(|&index| network.node_at(index).id).collect();
+        assert_eq!(vec![91, 93, 95], ids);
+        assert_eq!(None, table.predecessor(table.source()));
+        assert!(dijkstra_one_to_all(999, &network).is_none());
+    }
+
+    #[test]
+    fn test_one_to_many_stops_at_last_target() {
+        let network = make_grid_network();
+        let source = grid_id(0, 0);
+        let targets = [grid_id(1, 2), grid_id(2, 1), 999];
+        let all = dijkstra_one_to_all(source, &network).unwrap();
+        let many = dijkstra_one_to_many(source, &targets, &network).unwrap();
+
+        for target in &targets[..2] {
+            let index = network.node_index(target).unwrap();
+            assert_eq!(all.cost(index), many.cost(index));
+            assert!(many.cost(index).is_some());
+        }
+        assert!(many.settled_nodes() < all.settled_nodes());
+        assert_eq!(None, many.cost(network.node_index(&grid_id(9, 9)).unwrap()));
+
+        let none = dijkstra_one_to_many(source, &[], &network).unwrap();
+        assert_eq!(0, none.settled_nodes());
+    }
+
+    #[test]
+    fn test_bidirectional_dijkstra() {
+        let dummy_network = make_dummy_network();
+        let ids = [91, 92, 93, 94, 95];
+
+        for &source in &ids {
+            for &target in &ids {
+                let expected = run_dijsktra(source, target, &dummy_network, 0, true).unwrap();
+                let path = run_bidirectional_dijkstra(source, target, &dummy_network).unwrap();
+                assert_eq!(expected.cost, path.cost, "cost from {} to {}", source, target);
+                assert_eq!(expected.distance, path.distance);
+
+                let nodes = path.nodes();
+                assert_eq!(source, nodes[0]);
+                assert_eq!(target, *nodes.last().unwrap());
+                assert!(nodes.contains(&path.meeting_node));
+                assert_eq!(path.cost, path.to_entry().cost);
+            }
+        }
+
+        let path = run_bidirectional_dijkstra(94, 92, &dummy_network).unwrap();
+        assert_eq!(vec![94, 93, 92], path.nodes());
+    }
+
+    #[test]
+    fn test_bidirectional_settles_fewer_nodes() {
+        let network = make_grid_network();
+
+        for &(source, target) in &[((0, 0), (9, 9)), ((2, 3), (8, 1)), ((9, 0), (0, 7)), ((5, 5), (5, 6))] {
+            let (source, target) = (grid_id(source.0, source.1), grid_id(target.0, target.1));
+            let dijkstra = search_dijkstra(source, target, &network, 0, false);
+            let bidirectional = search_bidirectional_dijkstra(source, target, &network);
+
+            let path = bidirectional.path.unwrap();
+            assert_eq!(dijkstra.entry.unwrap().cost, path.cost);
+            assert_eq!(source, path.nodes()[0]);
+
+            let mut tail = network.node_index(&source).unwrap();
+            for arc in path.arcs() {
+                assert!(network.fwd_arcs(tail).iter().any(|a| a == arc));
+                tail = arc.head_node;
+            }
+            assert_eq!(network.node_index(&target).unwrap(), tail);
+        }
+
+        let far = search_dijkstra(grid_id(0, 0), grid_id(9, 9), &network, 0, false);
+        let far_bidirectional = search_bidirectional_dijkstra(grid_id(0, 0), grid_id(9, 9), &network);
+        assert!(far_bidirectional.settled_nodes < far.settled_nodes);
+    }
+
+    #[test]
+    fn test_turn_aware_matches_dijkstra_without_restrictions() {
+        let network = make_grid_network();
+        let source = grid_id(2, 7);
+        for row in 0..GRID_SIZE {
+            for col in 0..GRID_SIZE {
+                let target = grid_id(row, col);
+                let expected = run_dijsktra(source, target, &network, 0, true).unwrap();
+                let actual = run_turn_aware_dijkstra(source, target, &network, None).unwrap();
+                assert_eq!(expected.cost, actual.cost, "cost to {}", target);
+            }
+        }
+        assert!(run_turn_aware_dijkstra(source, 999, &network, None).is_none());
+    }
+
+    #[test]
+    fn test_bidirectional_no_path() {
+        let network = make_grid_network();
+        assert!(run_bidirectional_dijkstra(grid_id(0, 0), 999, &network).is_none());
+
+        let oneway = make_oneway_network();
+        assert_eq!(4, run_bidirectional_dijkstra(1, 2, &oneway).unwrap().cost);
+        assert!(run_bidirectional_dijkstra(2, 1, &oneway).is_none());
+    }
+
     fn do_disjktra(network: &Network, source: OSMNodeId, destination: OSMNodeId, expected_cost: u64) {
         let maybe_entry = run_dijsktra(source, destination, network, 0, true);
 
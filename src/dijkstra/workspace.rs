@@ -0,0 +1,243 @@
+use crate::network::{Arc, Network, NodeIndex};
+
+// position of a node that isn't in the heap
+const NOT_IN_HEAP: usize = usize::MAX;
+
+/// the per node state of a dijkstra search, kept between queries so that each search
+/// doesn't allocate. A node's cost, distance and predecessor only count when its stamp
+/// matches the current query, so starting a query doesn't have to clear them
+pub struct DijkstraWorkspace {
+    costs: Vec<u64>,
+    distances: Vec<u64>,
+    // the node each node was reached from and the position of the arc in its arcs in the
+    // direction searched
+    predecessors: Vec<Option<(NodeIndex, usize)>>,
+    stamps: Vec<u32>,
+    current_stamp: u32,
+    pub(crate) heap: IndexedHeap,
+}
+
+impl DijkstraWorkspace {
+    pub fn new(node_count: usize) -> DijkstraWorkspace {
+        DijkstraWorkspace {
+            costs: vec![u64::MAX; node_count],
+            distances: vec![0; node_count],
+            predecessors: vec![None; node_count],
+            stamps: vec![0; node_count],
+            current_stamp: 0,
+            heap: IndexedHeap::new(node_count),
+        }
+    }
+
+    pub fn for_network(network: &Network) -> DijkstraWorkspace {
+        DijkstraWorkspace::new(network.node_count())
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.costs.len()
+    }
+
+    /// forget the previous query. Only when the stamp wraps around are the stamps cleared
+    pub(crate) fn reset(&mut self) {
+        self.heap.clear();
+        if self.current_stamp == u32::MAX {
+            self.stamps.iter_mut().for_each(|stamp| *stamp = 0);
+            self.current_stamp = 0;
+        }
+        self.current_stamp += 1;
+    }
+
+    /// the cost of the best path to the node found by the last query, None if it wasn't reached
+    pub fn cost(&self, node: NodeIndex) -> Option<u64> {
+        if self.stamps[node] == self.current_stamp {
+            Some(self.costs[node])
+        } else {
+            None
+        }
+    }
+
+    pub fn distance(&self, node: NodeIndex) -> Option<u64> {
+        self.cost(node).map(|_| self.distances[node])
+    }
+
+    pub fn predecessor(&self, node: NodeIndex) -> Option<NodeIndex> {
+        self.cost(node).and(self.predecessors[node]).map(|(tail, _)| tail)
+    }
+
+    /// record a path to the node if it's cheaper than the best one so far
+    pub(crate) fn relax(
+        &mut self,
+        node: NodeIndex,
+        cost: u64,
+        distance: u64,
+        predecessor: Option<(NodeIndex, usize)>,
+    ) -> bool {
+        if self.cost(node).is_some_and(|best| cost >= best) {
+            return false;
+        }
+        self.stamps[node] = self.current_stamp;
+        self.costs[node] = cost;
+        self.distances[node] = distance;
+        self.predecessors[node] = predecessor;
+        true
+    }
+
+    /// the arcs from the source of the last forward query to the node, following the predecessors back
+    pub fn path_to(&self, node: NodeIndex, network: &Network) -> Option<Vec<Arc<NodeIndex>>> {
+        self.cost(node)?;
+        let mut path = vec![];
+        let mut current = node;
+        while let Some((tail, position)) = self.predecessors[current] {
+            path.push(network.fwd_arcs(tail).get(position));
+            current = tail;
+        }
+        path.reverse();
+        Some(path)
+    }
+}
+
+/// a binary min heap of nodes keyed by priority that knows where each node is, so a
+/// node's priority can be lowered in place rather than pushing a second entry
+pub(crate) struct IndexedHeap {
+    entries: Vec<(u64, NodeIndex)>,
+    positions: Vec<usize>,
+}
+
+impl IndexedHeap {
+    pub fn new(node_count: usize) -> IndexedHeap {
+        IndexedHeap {
+            entries: vec![],
+            positions: vec![NOT_IN_HEAP; node_count],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(u64, NodeIndex)> {
+        self.entries.iter()
+    }
+
+    pub fn clear(&mut self) {
+        for &(_, node) in &self.entries {
+            self.positions[node] = NOT_IN_HEAP;
+        }
+        self.entries.clear();
+    }
+
+    /// add the node or lower its priority if it's already in the heap. A higher priority
+    /// than the current one is ignored
+    pub fn push_or_decrease(&mut self, node: NodeIndex, priority: u64) {
+        match self.positions[node] {
+            NOT_IN_HEAP => {
+                self.entries.push((priority, node));
+                self.positions[node] = self.entries.len() - 1;
+                self.sift_up(self.entries.len() - 1);
+            }
+            position if priority < self.entries[position].0 => {
+                self.entries[position].0 = priority;
+                self.sift_up(position);
+            }
+            _ => (),
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<(u64, NodeIndex)> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let last = self.entries.len() - 1;
+        self.swap(0, last);
+        let (priority, node) = self.entries.pop().unwrap();
+        self.positions[node] = NOT_IN_HEAP;
+        if !self.entries.is_empty() {
+            self.sift_down(0);
+        }
+        Some((priority, node))
+    }
+
+    fn sift_up(&mut self, mut position: usize) {
+        while position > 0 {
+            let parent = (position - 1) / 2;
+            if self.entries[parent] <= self.entries[position] {
+                break;
+            }
+            self.swap(parent, position);
+            position = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut position: usize) {
+        loop {
+            let left = 2 * position + 1;
+            let right = left + 1;
+            let mut smallest = position;
+            if left < self.entries.len() && self.entries[left] < self.entries[smallest] {
+                smallest = left;
+            }
+            if right < self.entries.len() && self.entries[right] < self.entries[smallest] {
+                smallest = right;
+            }
+            if smallest == position {
+                break;
+            }
+            self.swap(smallest, position);
+            position = smallest;
+        }
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.entries.swap(a, b);
+        self.positions[self.entries[a].1] = a;
+        self.positions[self.entries[b].1] = b;
+    }
+}
+
+#[cfg(test)]
+mod workspace_test {
+    use super::*;
+
+    #[test]
+    fn heap_decrease_key() {
+        let mut heap = IndexedHeap::new(6);
+        for &(node, priority) in &[(0, 50), (1, 20), (2, 40), (3, 10), (4, 30)] {
+            heap.push_or_decrease(node, priority);
+        }
+        heap.push_or_decrease(2, 5);
+        heap.push_or_decrease(3, 60);
+        heap.push_or_decrease(0, 15);
+        assert_eq!(5, heap.len());
+
+        let order: Vec<_> = std::iter::from_fn(|| heap.pop()).collect();
+        assert_eq!(vec![(5, 2), (10, 3), (15, 0), (20, 1), (30, 4)], order);
+
+        heap.push_or_decrease(5, 1);
+        heap.clear();
+        assert_eq!(None, heap.pop());
+        heap.push_or_decrease(5, 2);
+        assert_eq!(Some((2, 5)), heap.pop());
+    }
+
+    #[test]
+    fn stamps_reset_between_queries() {
+        let mut workspace = DijkstraWorkspace::new(3);
+        workspace.reset();
+        assert!(workspace.relax(1, 10, 100, Some((0, 0))));
+        assert!(!workspace.relax(1, 12, 90, None));
+        assert_eq!(Some(10), workspace.cost(1));
+        assert_eq!(Some(0), workspace.predecessor(1));
+
+        workspace.reset();
+        assert_eq!(None, workspace.cost(1));
+        assert_eq!(None, workspace.predecessor(1));
+
+        // the stamps are cleared when they wrap around
+        workspace.relax(2, 3, 3, None);
+        workspace.current_stamp = u32::MAX;
+        workspace.stamps[2] = u32::MAX;
+        workspace.reset();
+        assert_eq!(None, workspace.cost(2));
+        assert_eq!(1, workspace.current_stamp);
+    }
+}
@@ -0,0 +1,22 @@
+#[macro_use]
+extern crate log;
+extern crate quick_xml;
+
+pub mod alt;
+pub mod arc_flags;
+pub mod astar;
+pub mod ch;
+pub mod dijkstra;
+pub mod matrix;
+pub mod network;
+pub mod scc;
+pub mod time_dependent;
+pub mod tnr;
+pub mod transit;
+pub mod turns;
+pub mod utils;
+
+pub mod osm;
+
+#[cfg(test)]
+mod test_networks;
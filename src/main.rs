@@ -1,113 +1,960 @@
-#[macro_use]
-extern crate log;
+extern crate efficient_route_planning_freiburg;
 extern crate env_logger;
-extern crate quick_xml;
 
-mod dijkstra;
-mod network;
-mod utils;
+use efficient_route_planning_freiburg::alt::{self, LandmarkStrategy, LandmarkTables};
+use efficient_route_planning_freiburg::arc_flags::{self, ArcFlags, Partition};
+use efficient_route_planning_freiburg::astar;
+use efficient_route_planning_freiburg::ch::{self, ContractionHierarchy};
+use efficient_route_planning_freiburg::dijkstra::{self, DijkstraWorkspace};
+use efficient_route_planning_freiburg::matrix::{self, MatrixEntry};
+use efficient_route_planning_freiburg::network::{Network, OSMNodeId};
+use efficient_route_planning_freiburg::osm::{self, LoadMode};
+use efficient_route_planning_freiburg::osm::profile::{self, Profile};
+use efficient_route_planning_freiburg::scc;
+use efficient_route_planning_freiburg::tnr::{self, TransitNodeRouting};
+use efficient_route_planning_freiburg::turns::TurnCostModel;
+use failure::Fail;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
-mod osm;
-
-use network::{Network, OSMNodeId};
-use osm::load_xml;
-
-use quick_xml::events::Event;
-use quick_xml::Reader;
-use std::time::Instant;
+use std::env;
+use std::process;
+use std::time::{Duration, Instant};
 
 const OSM_DATA_FILE: &str = "data/rutland-latest.osm.xml";
 // const OSM_DATA_FILE: &str = "/home/waynec/Downloads/great-britain-latest.osm.xml";
 
+const EXIT_USAGE: i32 = 1;
+const EXIT_LOAD_FAILED: i32 = 5;
+const EXIT_NO_PATH: i32 = 2;
+const EXIT_NODE_NOT_FOUND: i32 = 3;
+const EXIT_PREPROCESSING_FAILED: i32 = 4;
+
+const DEFAULT_PROFILE: &str = "car";
+/// files with this extension are network snapshots written by the save command
+const SNAPSHOT_EXTENSION: &str = ".network";
+const DEFAULT_LANDMARK_COUNT: usize = 16;
+const LANDMARK_SEED: u64 = 42;
+const DEFAULT_REGIONS: usize = 8;
+const DEFAULT_QUERIES: usize = 100;
+const QUERY_SEED: u64 = 2019;
+const DEFAULT_TRANSIT_NODES: usize = 256;
+
+fn usage() -> String {
+    format!(
+        "usage: efficient-route-planning-freiburg [--file <osm-file>] [--profile car|bike|foot] [--largest-component] [--strict] <command>
+
+commands:
+    load                                  load the network and report the time taken
+    stats                                 report node, arc, way and component counts
+    route --from <osm-id> --to <osm-id> [--max-cost <cost>] [--algorithm dijkstra|bidirectional|astar|alt|ch|turns]
+          [--landmarks <file>] [--turn-costs]
+                                          find the cheapest path between two nodes
+    landmarks --output <file> [--count <k>] [--strategy random|farthest|avoid]
+                                          precompute landmark tables for alt routing
+    one-to-all --from <osm-id>            run dijkstra from a node over the whole network
+    arc-flags [--partition grid|balanced] [--regions <k>] [--queries <n>]
+                                          precompute arc flags for a k x k grid or k balanced
+                                          regions and compare random queries with dijkstra
+    tnr [--transit-nodes <k>] [--queries <n>]
+                                          precompute transit node routing over the k highest
+                                          ranked nodes and compare random queries with dijkstra
+    matrix --sources <osm-id,...> --targets <osm-id,...> [--algorithm dijkstra|ch]
+                                          print the cost from every source to every target,
+                                          - where there's no path
+    save --output <file>                  save the network as a snapshot, loaded in place of
+                                          the OSM file by --file <file>{}
+
+the network is read from {} unless --file is given, using the ways and travel
+speeds of the {} profile unless --profile is given. --largest-component drops every
+node outside the largest strongly connected component before running the command. Files
+ending in .pbf are read as OSM PBF and anything else as OSM XML. Bad elements and nodes
+missing from the file are skipped and counted unless --strict is given",
+        SNAPSHOT_EXTENSION, OSM_DATA_FILE, DEFAULT_PROFILE
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Algorithm {
+    Dijkstra,
+    Bidirectional,
+    AStar,
+    Alt,
+    Ch,
+    TurnAware,
+}
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Load,
+    Stats,
+    Route {
+        from: OSMNodeId,
+        to: OSMNodeId,
+        max_cost: u64,
+        algorithm: Algorithm,
+        landmarks: Option<String>,
+        turn_costs: bool,
+    },
+    Landmarks {
+        count: usize,
+        strategy: LandmarkStrategy,
+        output: String,
+    },
+    OneToAll {
+        from: OSMNodeId,
+    },
+    ArcFlags {
+        partition: Partition,
+        queries: usize,
+    },
+    TransitNodes {
+        transit_nodes: usize,
+        queries: usize,
+    },
+    Matrix {
+        sources: Vec<OSMNodeId>,
+        targets: Vec<OSMNodeId>,
+        algorithm: Algorithm,
+    },
+    Save {
+        output: String,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    file: String,
+    /// None when --profile wasn't given
+    profile: Option<String>,
+    largest_component: bool,
+    strict: bool,
+    command: Command,
+}
+
 fn main() {
     env_logger::init();
 
-    println!("Hello, world!");
-    let start_load_network = Instant::now();
-    let network = from_osm_file(OSM_DATA_FILE);
-    let duration_load_network = start_load_network.elapsed();
-    println!(
-        "time to load network {} {:?}",
-        OSM_DATA_FILE, duration_load_network
-    );
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n", message);
+            eprintln!("{}", usage());
+            process::exit(EXIT_USAGE);
+        }
+    };
 
-    let oakham_the_avenue: OSMNodeId = 3711862961;
-    let oakham_braunston_road: OSMNodeId = 18335097;
-    let oakham_tolenthorpe_close: OSMNodeId = 18334319;
-    let oakham_woodland_view: OSMNodeId = 18339438;
-    let uppingham_queens_road: OSMNodeId = 18327809;
-    let result = dijkstra::run_dijsktra(
-        oakham_braunston_road,
-        uppingham_queens_road,
-        &network,
-        15000,
-        true,
-    );
-    match result {
-        Some(entry) => {
-            println!("path result cost: {}", entry.cost);
-            println!("ways travelled: {}", entry.report_traversed_ways());
+    process::exit(run(&options));
+}
+
+/// loads the network the options name and runs their command, returning the exit code
+fn run(options: &Options) -> i32 {
+    let mode = if options.strict { LoadMode::Strict } else { LoadMode::Lenient };
+    let (mut network, profile_name) = match from_file(&options.file, options.profile.as_deref(), mode) {
+        Ok(network) => network,
+        Err(e) => {
+            eprintln!("loading network {} failed: {}", options.file, e);
+            return EXIT_LOAD_FAILED;
         }
-        None => println!("no path found!!"),
+    };
+    if options.largest_component {
+        let report = network.reduce_to_largest_component();
+        println!(
+            "removed {} nodes and {} arcs outside the largest component",
+            report.removed_nodes, report.removed_arcs
+        );
     }
 
-    let start = Instant::now();
-    let whole_network_result = dijkstra::run_dijsktra(oakham_the_avenue, 0, &network, 0, false);
-    let duration = start.elapsed();
-    println!("time to complete full dijkstra {:?}", duration);
+    run_command(&options.command, &network, &profile_name)
 }
 
-fn from_osm_rutland() -> Network {
-    from_osm_file("data/rutland-latest.osm.xml")
-}
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut file = OSM_DATA_FILE.to_string();
+    let mut profile_name = None;
+    let mut largest_component = false;
+    let mut strict = false;
+    let mut command_name = None;
+    let mut from = None;
+    let mut to = None;
+    let mut max_cost = None;
+    let mut algorithm = Algorithm::Dijkstra;
+    let mut landmarks = None;
+    let mut turn_costs = false;
+    let mut landmark_count = DEFAULT_LANDMARK_COUNT;
+    let mut strategy = LandmarkStrategy::Avoid;
+    let mut output = None;
+    let mut sources = None;
+    let mut partition_name = "grid".to_string();
+    let mut regions = DEFAULT_REGIONS;
+    let mut queries = DEFAULT_QUERIES;
+    let mut transit_nodes = DEFAULT_TRANSIT_NODES;
+    let mut targets = None;
 
-fn from_osm_file(file: &str) -> Network {
-    // let xml_string = fs::read_to_string(file).expect("couldn't read osm file");
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--file" => file = option_value(arg, iter.next())?.to_string(),
+            "--profile" => {
+                let name = option_value(arg, iter.next())?;
+                if profile::by_name(name).is_none() {
+                    return Err(format!("unknown profile {}", name));
+                }
+                profile_name = Some(name.to_string());
+            }
+            "--largest-component" => largest_component = true,
+            "--strict" => strict = true,
+            "--from" => from = Some(parse_node_id(arg, iter.next())?),
+            "--to" => to = Some(parse_node_id(arg, iter.next())?),
+            "--max-cost" => {
+                max_cost = Some(
+                    option_value(arg, iter.next())?
+                        .parse::<u64>()
+                        .map_err(|e| format!("invalid value for {}: {}", arg, e))?,
+                )
+            }
+            "--algorithm" => {
+                algorithm = match option_value(arg, iter.next())? {
+                    "dijkstra" => Algorithm::Dijkstra,
+                    "bidirectional" => Algorithm::Bidirectional,
+                    "astar" => Algorithm::AStar,
+                    "alt" => Algorithm::Alt,
+                    "ch" => Algorithm::Ch,
+                    "turns" => Algorithm::TurnAware,
+                    other => return Err(format!("unknown algorithm {}", other)),
+                }
+            }
+            "--landmarks" => landmarks = Some(option_value(arg, iter.next())?.to_string()),
+            "--turn-costs" => turn_costs = true,
+            "--count" => {
+                landmark_count = option_value(arg, iter.next())?
+                    .parse::<usize>()
+                    .map_err(|e| format!("invalid value for {}: {}", arg, e))?
+            }
+            "--strategy" => {
+                strategy = match option_value(arg, iter.next())? {
+                    "random" => LandmarkStrategy::Random,
+                    "farthest" => LandmarkStrategy::Farthest,
+                    "avoid" => LandmarkStrategy::Avoid,
+                    other => return Err(format!("unknown landmark strategy {}", other)),
+                }
+            }
+            "--output" => output = Some(option_value(arg, iter.next())?.to_string()),
+            "--partition" => partition_name = option_value(arg, iter.next())?.to_string(),
+            "--regions" => {
+                regions = option_value(arg, iter.next())?
+                    .parse::<usize>()
+                    .map_err(|e| format!("invalid value for {}: {}", arg, e))?
+            }
+            "--queries" => {
+                queries = option_value(arg, iter.next())?
+                    .parse::<usize>()
+                    .map_err(|e| format!("invalid value for {}: {}", arg, e))?
+            }
+            "--transit-nodes" => {
+                transit_nodes = option_value(arg, iter.next())?
+                    .parse::<usize>()
+                    .map_err(|e| format!("invalid value for {}: {}", arg, e))?
+            }
+            "--sources" => sources = Some(parse_node_ids(arg, iter.next())?),
+            "--targets" => targets = Some(parse_node_ids(arg, iter.next())?),
+            flag if flag.starts_with("--") => return Err(format!("unknown option {}", flag)),
+            name if command_name.is_none() => command_name = Some(name),
+            extra => return Err(format!("unexpected argument {}", extra)),
+        }
+    }
 
-    println!("process {}", file);
-    // echo_xml(&xml_string);
-    match load_xml::load_network_from_file(file) {
-        Ok(network) => network,
-        _ => panic!("loading network failed"),
+    if turn_costs && algorithm != Algorithm::TurnAware {
+        return Err("--turn-costs needs --algorithm turns".to_string());
     }
+    if max_cost.is_some() && algorithm != Algorithm::Dijkstra {
+        return Err("--max-cost needs --algorithm dijkstra".to_string());
+    }
+
+    let command = match command_name {
+        Some("load") => Command::Load,
+        Some("stats") => Command::Stats,
+        Some("route") => match (from, to) {
+            (Some(from), Some(to)) => Command::Route {
+                from,
+                to,
+                max_cost: max_cost.unwrap_or(0),
+                algorithm,
+                landmarks,
+                turn_costs,
+            },
+            _ => return Err("route needs --from and --to".to_string()),
+        },
+        Some("landmarks") => match output {
+            Some(output) => Command::Landmarks {
+                count: landmark_count,
+                strategy,
+                output,
+            },
+            None => return Err("landmarks needs --output".to_string()),
+        },
+        Some("one-to-all") => match from {
+            Some(from) => Command::OneToAll { from },
+            None => return Err("one-to-all needs --from".to_string()),
+        },
+        Some("arc-flags") => {
+            if regions == 0 {
+                return Err("--regions must be at least 1".to_string());
+            }
+            let partition = match partition_name.as_str() {
+                "grid" => Partition::Grid(regions),
+                "balanced" => Partition::Balanced(regions),
+                other => return Err(format!("unknown partition {}", other)),
+            };
+            Command::ArcFlags { partition, queries }
+        }
+        Some("tnr") => Command::TransitNodes { transit_nodes, queries },
+        Some("matrix") => match (sources, targets, algorithm) {
+            (Some(sources), Some(targets), Algorithm::Dijkstra | Algorithm::Ch) => Command::Matrix {
+                sources,
+                targets,
+                algorithm,
+            },
+            (Some(_), Some(_), _) => return Err("matrix only supports --algorithm dijkstra or ch".to_string()),
+            _ => return Err("matrix needs --sources and --targets".to_string()),
+        },
+        Some("save") => match output {
+            Some(output) => Command::Save { output },
+            None => return Err("save needs --output".to_string()),
+        },
+        Some(other) => return Err(format!("unknown command {}", other)),
+        None => return Err("no command given".to_string()),
+    };
+
+    Ok(Options {
+        file,
+        profile: profile_name,
+        largest_component,
+        strict,
+        command,
+    })
 }
 
-#[cfg(test)]
-fn from_osm_dummy() -> Option<Network> {
-    let test_xml = r#"<osm>
-	<node>
-		<tag k="odbl" v="clean"/>
-	</node>
-        <way>
-            <nd>
-            </nd>
-        </way>
-    </osm>"#;
+fn option_value<'a>(option: &str, value: Option<&'a String>) -> Result<&'a str, String> {
+    value
+        .map(String::as_str)
+        .ok_or_else(|| format!("{} needs a value", option))
+}
 
-    // echo_xml(&test_xml);
-    load_xml::load_network_from_string(test_xml)
+fn parse_node_id(option: &str, value: Option<&String>) -> Result<OSMNodeId, String> {
+    option_value(option, value)?
+        .parse::<OSMNodeId>()
+        .map_err(|e| format!("invalid node id for {}: {}", option, e))
 }
 
-fn _echo_xml(xml_string: &str) -> () {
-    let mut reader = Reader::from_str(&xml_string);
+fn parse_node_ids(option: &str, value: Option<&String>) -> Result<Vec<OSMNodeId>, String> {
+    option_value(option, value)?
+        .split(',')
+        .map(|id| {
+            id.parse::<OSMNodeId>()
+                .map_err(|e| format!("invalid node id for {}: {}", option, e))
+        })
+        .collect()
+}
 
-    let mut buf = Vec::new();
+/// profile_name is the profile the network was built with, saved along with it
+fn run_command(command: &Command, network: &Network, profile_name: &str) -> i32 {
+    match command {
+        Command::Load => 0,
+        Command::Stats => {
+            println!("nodes: {}", network.node_count());
+            println!("arcs: {}", network.arc_count());
+            println!("reverse arcs: {}", network.reverse_arc_count());
+            println!("ways: {}", network.way_count());
+            println!("turn restrictions: {}", network.turn_restriction_count());
+            println!("traffic signals: {}", network.traffic_signal_count());
+            let sizes = scc::component_sizes(&scc::strongly_connected_components(network));
+            println!("strongly connected components: {}", sizes.len());
+            println!("largest component: {} nodes", sizes.iter().max().unwrap_or(&0));
+            0
+        }
+        Command::Route {
+            from,
+            to,
+            max_cost,
+            algorithm,
+            landmarks,
+            turn_costs,
+        } => {
+            let (from, to) = (*from, *to);
+            if let Some(code) = check_nodes_exist(network, &[from, to]) {
+                return code;
+            }
 
-    loop {
-        match reader.read_event(&mut buf) {
-            Ok(Event::Start(ref e)) => {
-                println!("<{}>", String::from_utf8(e.name().to_vec()).unwrap())
+            let landmark_tables = if *algorithm == Algorithm::Alt {
+                match landmark_tables(network, landmarks) {
+                    Ok(tables) => Some(tables),
+                    Err(e) => {
+                        eprintln!("couldn't get landmark tables: {}", e);
+                        return EXIT_PREPROCESSING_FAILED;
+                    }
+                }
+            } else {
+                None
+            };
+            let hierarchy = if *algorithm == Algorithm::Ch {
+                let start = Instant::now();
+                let hierarchy = ContractionHierarchy::new(network);
+                println!(
+                    "time to contract network with {} shortcuts {:?}",
+                    hierarchy.shortcut_count(),
+                    start.elapsed()
+                );
+                Some(hierarchy)
+            } else {
+                None
+            };
+
+            let start = Instant::now();
+            let result = match (algorithm, &landmark_tables, &hierarchy) {
+                (Algorithm::Alt, Some(tables), _) => alt::search_alt(from, to, network, tables, true),
+                (Algorithm::Ch, _, Some(hierarchy)) => ch::search_ch(from, to, network, hierarchy),
+                (Algorithm::AStar, _, _) => astar::search_astar(from, to, network, true),
+                (Algorithm::TurnAware, _, _) => {
+                    let model = if *turn_costs { Some(TurnCostModel::default()) } else { None };
+                    dijkstra::search_turn_aware_dijkstra(from, to, network, model.as_ref())
+                }
+                (Algorithm::Bidirectional, _, _) => {
+                    let result = dijkstra::search_bidirectional_dijkstra(from, to, network);
+                    dijkstra::SearchResult {
+                        entry: result.path.map(|path| path.to_entry()),
+                        settled_nodes: result.settled_nodes,
+                    }
+                }
+                _ => dijkstra::search_dijkstra(from, to, network, *max_cost, true),
+            };
+            let duration = start.elapsed();
+            match result.entry {
+                Some(entry) => {
+                    println!("path result cost: {} (travel time {:.1}s)", entry.cost, entry.cost as f64 / 1000.0);
+                    println!("path distance: {}m", entry.distance);
+                    println!("ways travelled: {}", entry.report_traversed_ways());
+                    println!("settled {} nodes in {:?}", result.settled_nodes, duration);
+                    0
+                }
+                None => {
+                    println!("no path found from {} to {}", from, to);
+                    EXIT_NO_PATH
+                }
             }
-            Ok(Event::Empty(ref e)) => {
-                println!("<{}/>", String::from_utf8(e.name().to_vec()).unwrap())
+        }
+        Command::OneToAll { from } => {
+            if let Some(code) = check_nodes_exist(network, &[*from]) {
+                return code;
             }
-            Ok(Event::End(ref e)) => {
-                println!("</{}>", String::from_utf8(e.name().to_vec()).unwrap())
+
+            let start = Instant::now();
+            let table = dijkstra::dijkstra_one_to_all(*from, network).unwrap();
+            let duration = start.elapsed();
+            println!(
+                "reached {} of {} nodes from {}",
+                table.settled_nodes(),
+                network.node_count(),
+                from
+            );
+            println!("time to complete full dijkstra {:?}", duration);
+            0
+        }
+        Command::ArcFlags { partition, queries } => {
+            if let Some(code) = check_not_empty(network) {
+                return code;
+            }
+
+            let start = Instant::now();
+            let flags = ArcFlags::new(network, *partition);
+            println!(
+                "time to compute arc flags for {} regions {:?}, {:.1}% of arc flags set",
+                flags.region_count(),
+                start.elapsed(),
+                flags.flagged_fraction() * 100.0
+            );
+
+            let mut rng = StdRng::seed_from_u64(QUERY_SEED);
+            let mut workspace = DijkstraWorkspace::for_network(network);
+            let (mut dijkstra_time, mut arc_flags_time) = (Duration::default(), Duration::default());
+            let (mut dijkstra_settled, mut arc_flags_settled) = (0, 0);
+            for _ in 0..*queries {
+                let from = network.node_at(rng.gen_range(0..network.node_count())).id;
+                let to = network.node_at(rng.gen_range(0..network.node_count())).id;
+
+                let start = Instant::now();
+                let expected = dijkstra::search_dijkstra_in(&mut workspace, from, to, network, 0, false);
+                dijkstra_time += start.elapsed();
+                let start = Instant::now();
+                let actual = arc_flags::search_arc_flags_in(&mut workspace, from, to, network, &flags, false);
+                arc_flags_time += start.elapsed();
+
+                if expected.entry.map(|e| e.cost) != actual.entry.map(|e| e.cost) {
+                    eprintln!("arc flags and dijkstra disagree from {} to {}", from, to);
+                    return EXIT_PREPROCESSING_FAILED;
+                }
+                dijkstra_settled += expected.settled_nodes;
+                arc_flags_settled += actual.settled_nodes;
+            }
+            println!("{} random queries", queries);
+            println!("dijkstra settled {} nodes in {:?}", dijkstra_settled, dijkstra_time);
+            println!("arc flags settled {} nodes in {:?}", arc_flags_settled, arc_flags_time);
+            println!(
+                "speedup {:.1}x by time, {:.1}x by settled nodes",
+                dijkstra_time.as_secs_f64() / arc_flags_time.as_secs_f64().max(f64::EPSILON),
+                dijkstra_settled as f64 / arc_flags_settled.max(1) as f64
+            );
+            0
+        }
+        Command::TransitNodes { transit_nodes, queries } => {
+            if let Some(code) = check_not_empty(network) {
+                return code;
+            }
+
+            let start = Instant::now();
+            let hierarchy = ContractionHierarchy::new(network);
+            println!("time to contract network {:?}", start.elapsed());
+            let start = Instant::now();
+            let routing = TransitNodeRouting::new(network, &hierarchy, *transit_nodes);
+            println!(
+                "time to compute tables for {} transit nodes {:?}",
+                routing.transit_nodes().len(),
+                start.elapsed()
+            );
+
+            let mut rng = StdRng::seed_from_u64(QUERY_SEED);
+            let mut workspace = DijkstraWorkspace::for_network(network);
+            let mut dijkstra_time = Duration::default();
+            let mut ch_time = Duration::default();
+            let mut tnr_time = Duration::default();
+            let (mut dijkstra_settled, mut ch_settled, mut tnr_settled) = (0, 0, 0);
+            let mut local_queries = 0;
+            for _ in 0..*queries {
+                let from = network.node_at(rng.gen_range(0..network.node_count())).id;
+                let to = network.node_at(rng.gen_range(0..network.node_count())).id;
+
+                let start = Instant::now();
+                let expected = dijkstra::search_dijkstra_in(&mut workspace, from, to, network, 0, false);
+                dijkstra_time += start.elapsed();
+                let start = Instant::now();
+                let ch_result = ch::search_ch(from, to, network, &hierarchy);
+                ch_time += start.elapsed();
+                let start = Instant::now();
+                let actual = tnr::search_tnr(from, to, network, &hierarchy, &routing, false);
+                tnr_time += start.elapsed();
+
+                if expected.entry.map(|e| e.cost) != actual.entry.map(|e| e.cost) {
+                    eprintln!("transit node routing and dijkstra disagree from {} to {}", from, to);
+                    return EXIT_PREPROCESSING_FAILED;
+                }
+                if actual.local {
+                    local_queries += 1;
+                }
+                dijkstra_settled += expected.settled_nodes;
+                ch_settled += ch_result.settled_nodes;
+                tnr_settled += actual.settled_nodes;
+            }
+            println!("{} random queries, {} local", queries, local_queries);
+            println!("dijkstra settled {} nodes in {:?}", dijkstra_settled, dijkstra_time);
+            println!("ch settled {} nodes in {:?}", ch_settled, ch_time);
+            println!("transit node routing settled {} nodes in {:?}", tnr_settled, tnr_time);
+            let tnr_secs = tnr_time.as_secs_f64().max(f64::EPSILON);
+            println!(
+                "speedup {:.1}x over dijkstra, {:.1}x over ch",
+                dijkstra_time.as_secs_f64() / tnr_secs,
+                ch_time.as_secs_f64() / tnr_secs
+            );
+            0
+        }
+        Command::Matrix {
+            sources,
+            targets,
+            algorithm,
+        } => {
+            let all_nodes: Vec<OSMNodeId> = sources.iter().chain(targets.iter()).cloned().collect();
+            if let Some(code) = check_nodes_exist(network, &all_nodes) {
+                return code;
+            }
+
+            let hierarchy = if *algorithm == Algorithm::Ch {
+                let start = Instant::now();
+                let hierarchy = ContractionHierarchy::new(network);
+                println!("time to contract network {:?}", start.elapsed());
+                Some(hierarchy)
+            } else {
+                None
+            };
+
+            let start = Instant::now();
+            let matrix = matrix::many_to_many(sources, targets, network, hierarchy.as_ref());
+            let duration = start.elapsed();
+            let header: Vec<String> = targets.iter().map(|id| id.to_string()).collect();
+            println!("\t{}", header.join("\t"));
+            for (row, source) in sources.iter().enumerate() {
+                let costs: Vec<String> = matrix
+                    .row(row)
+                    .iter()
+                    .map(|entry| match entry {
+                        MatrixEntry::Reachable { cost, .. } => cost.to_string(),
+                        MatrixEntry::Unreachable => "-".to_string(),
+                    })
+                    .collect();
+                println!("{}\t{}", source, costs.join("\t"));
+            }
+            println!("time to compute {}x{} matrix {:?}", sources.len(), targets.len(), duration);
+            0
+        }
+        Command::Landmarks {
+            count,
+            strategy,
+            output,
+        } => {
+            let start = Instant::now();
+            let tables = LandmarkTables::new(network, *count, *strategy, LANDMARK_SEED);
+            println!("time to compute {} landmarks {:?}", tables.landmarks().len(), start.elapsed());
+            match tables.save(output) {
+                Ok(()) => 0,
+                Err(e) => {
+                    eprintln!("couldn't save landmarks to {}: {}", output, e);
+                    EXIT_PREPROCESSING_FAILED
+                }
             }
-            Ok(Event::Eof) => break,
-            _ => (),
         }
-        buf.clear();
+        Command::Save { output } => {
+            let start = Instant::now();
+            match network.save(output, profile_name) {
+                Ok(()) => {
+                    println!("time to save network to {} {:?}", output, start.elapsed());
+                    0
+                }
+                Err(e) => {
+                    eprintln!("couldn't save network to {}: {}", output, e);
+                    EXIT_PREPROCESSING_FAILED
+                }
+            }
+        }
+    }
+}
+
+fn landmark_tables(network: &Network, file: &Option<String>) -> Result<LandmarkTables, Box<dyn std::error::Error>> {
+    match file {
+        Some(file_path) => LandmarkTables::load(file_path, network),
+        None => {
+            let start = Instant::now();
+            let tables = LandmarkTables::new(network, DEFAULT_LANDMARK_COUNT, LandmarkStrategy::Avoid, LANDMARK_SEED);
+            println!("time to compute {} landmarks {:?}", tables.landmarks().len(), start.elapsed());
+            Ok(tables)
+        }
+    }
+}
+
+/// the benchmarks pick random nodes to route between, there are none to pick in an empty network
+fn check_not_empty(network: &Network) -> Option<i32> {
+    if network.node_count() == 0 {
+        eprintln!("network has no nodes to route between");
+        Some(EXIT_PREPROCESSING_FAILED)
+    } else {
+        None
+    }
+}
+
+fn check_nodes_exist(network: &Network, node_ids: &[OSMNodeId]) -> Option<i32> {
+    let missing: Vec<String> = node_ids
+        .iter()
+        .filter(|id| network.get_node(id).is_none())
+        .map(|id| id.to_string())
+        .collect();
+    if missing.is_empty() {
+        None
+    } else {
+        eprintln!("node not found in network: {}", missing.join(", "));
+        Some(EXIT_NODE_NOT_FOUND)
+    }
+}
+
+#[cfg(test)]
+fn from_osm_rutland() -> Network {
+    from_osm_file("data/rutland-latest.osm.xml", &profile::Car, LoadMode::Strict).expect("loading network failed")
+}
+
+/// returns the network and the name of the profile it was built with. Snapshots keep the
+/// profile they were saved with and asking for a different one is an error
+fn from_file(
+    file: &str,
+    profile_name: Option<&str>,
+    mode: LoadMode,
+) -> Result<(Network, String), Box<dyn std::error::Error>> {
+    if file.ends_with(SNAPSHOT_EXTENSION) {
+        let start = Instant::now();
+        let (network, saved_profile) = Network::load(file)?;
+        if let Some(name) = profile_name.filter(|&name| name != saved_profile) {
+            return Err(format!("{} was saved with the {} profile, not {}", file, saved_profile, name).into());
+        }
+        println!(
+            "time to load network snapshot {} with the {} profile {:?}",
+            file,
+            saved_profile,
+            start.elapsed()
+        );
+        Ok((network, saved_profile))
+    } else {
+        // parse_args only accepts known profile names
+        let profile = profile::by_name(profile_name.unwrap_or(DEFAULT_PROFILE)).unwrap();
+        let network = from_osm_file(file, profile.as_ref(), mode)?;
+        Ok((network, profile.name().to_string()))
+    }
+}
+
+fn from_osm_file(file: &str, profile: &dyn Profile, mode: LoadMode) -> Result<Network, Box<dyn std::error::Error>> {
+    println!("process {} with the {} profile", file, profile.name());
+    let start_load_network = Instant::now();
+    let (network, summary) = osm::load_network_with_summary(file, profile, mode).map_err(|e| e.compat())?;
+    println!(
+        "time to load network {} {:?}",
+        file,
+        start_load_network.elapsed()
+    );
+    println!("{}", summary);
+    Ok(network)
+}
+
+#[cfg(test)]
+mod cli_tests {
+    use super::*;
+    use efficient_route_planning_freiburg::network::NetworkBuilder;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn exit_codes() {
+        let run_line = |line: &str| run(&parse_args(&args(line)).unwrap());
+        let route = "--file data/rutland-tiny.osm.xml route --from 18253402";
+        assert_eq!(0, run_line(&format!("{} --to 1917340647", route)));
+        assert_eq!(EXIT_NO_PATH, run_line(&format!("{} --to 1917340647 --max-cost 1", route)));
+        assert_eq!(EXIT_NODE_NOT_FOUND, run_line(&format!("{} --to 999", route)));
+        assert_eq!(EXIT_LOAD_FAILED, run_line("--file data/missing.osm.xml stats"));
+        assert_eq!(EXIT_LOAD_FAILED, run_line("--file data/missing.network stats"));
+    }
+
+    #[test]
+    fn parse_route() {
+        let options = parse_args(&args("route --from 18335097 --to 18327809")).unwrap();
+        assert_eq!(OSM_DATA_FILE, options.file);
+        assert_eq!(None, options.profile);
+        assert!(!options.largest_component);
+        assert!(!options.strict);
+        assert_eq!(
+            Command::Route {
+                from: 18335097,
+                to: 18327809,
+                max_cost: 0,
+                algorithm: Algorithm::Dijkstra,
+                landmarks: None,
+                turn_costs: false,
+            },
+            options.command
+        );
+    }
+
+    #[test]
+    fn parse_file_and_max_cost() {
+        let options = parse_args(&args(
+            "--file data/rutland-tiny.osm.xml --profile foot --largest-component --strict route --to 2 --from 1 --max-cost 15000",
+        ))
+        .unwrap();
+        assert_eq!("data/rutland-tiny.osm.xml", options.file);
+        assert_eq!(Some("foot".to_string()), options.profile);
+        assert!(options.largest_component);
+        assert!(options.strict);
+        assert_eq!(
+            Command::Route {
+                from: 1,
+                to: 2,
+                max_cost: 15000,
+                algorithm: Algorithm::Dijkstra,
+                landmarks: None,
+                turn_costs: false,
+            },
+            options.command
+        );
+    }
+
+    #[test]
+    fn parse_landmarks() {
+        let options = parse_args(&args("landmarks --output l.json --count 8 --strategy farthest")).unwrap();
+        assert_eq!(
+            Command::Landmarks {
+                count: 8,
+                strategy: LandmarkStrategy::Farthest,
+                output: "l.json".to_string(),
+            },
+            options.command
+        );
+
+        let options = parse_args(&args("route --from 1 --to 2 --algorithm alt --landmarks l.json")).unwrap();
+        match options.command {
+            Command::Route { algorithm, landmarks, .. } => {
+                assert_eq!(Algorithm::Alt, algorithm);
+                assert_eq!(Some("l.json".to_string()), landmarks);
+            }
+            other => panic!("expected route, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn save_and_load_snapshot() {
+        assert_eq!(
+            Command::Save {
+                output: "rutland.network".to_string()
+            },
+            parse_args(&args("save --output rutland.network")).unwrap().command
+        );
+        assert!(parse_args(&args("save")).is_err());
+
+        let (network, profile_name) = from_file("data/turn-restriction.osm.xml", Some("bike"), LoadMode::Strict).unwrap();
+        assert_eq!("bike", profile_name);
+        let output = std::env::temp_dir().join("cli_tests_save_and_load_snapshot.network");
+        let output = output.to_str().unwrap().to_string();
+        assert_eq!(0, run_command(&Command::Save { output: output.clone() }, &network, &profile_name));
+
+        // a snapshot keeps its profile and refuses a different one
+        let (loaded, profile_name) = from_file(&output, None, LoadMode::Strict).unwrap();
+        assert_eq!("bike", profile_name);
+        assert!(from_file(&output, Some("bike"), LoadMode::Strict).is_ok());
+        let error = from_file(&output, Some("foot"), LoadMode::Strict).unwrap_err();
+        assert!(error.to_string().contains("saved with the bike profile"));
+        assert_eq!(network.node_count(), loaded.node_count());
+        assert_eq!(network.arc_count(), loaded.arc_count());
+        assert_eq!(network.turn_restriction_count(), loaded.turn_restriction_count());
+    }
+
+    #[test]
+    fn parse_matrix() {
+        assert_eq!(
+            Command::Matrix {
+                sources: vec![1, 2],
+                targets: vec![3],
+                algorithm: Algorithm::Ch,
+            },
+            parse_args(&args("matrix --sources 1,2 --targets 3 --algorithm ch")).unwrap().command
+        );
+        assert!(parse_args(&args("matrix --sources 1,2")).is_err());
+        assert!(parse_args(&args("matrix --sources 1,x --targets 3")).is_err());
+        assert!(parse_args(&args("matrix --sources 1 --targets 3 --algorithm astar")).is_err());
+    }
+
+    #[test]
+    fn parse_arc_flags() {
+        assert_eq!(
+            Command::ArcFlags {
+                partition: Partition::Grid(DEFAULT_REGIONS),
+                queries: DEFAULT_QUERIES,
+            },
+            parse_args(&args("arc-flags")).unwrap().command
+        );
+        assert_eq!(
+            Command::ArcFlags {
+                partition: Partition::Balanced(32),
+                queries: 10,
+            },
+            parse_args(&args("arc-flags --partition balanced --regions 32 --queries 10")).unwrap().command
+        );
+        assert!(parse_args(&args("arc-flags --partition kd")).is_err());
+        assert!(parse_args(&args("arc-flags --regions 0")).is_err());
+
+        let network = from_osm_file("data/rutland-tiny.osm.xml", &profile::Car, LoadMode::Strict).unwrap();
+        let command = Command::ArcFlags {
+            partition: Partition::Balanced(3),
+            queries: 20,
+        };
+        assert_eq!(0, run_command(&command, &network, "car"));
+    }
+
+    #[test]
+    fn parse_tnr() {
+        assert_eq!(
+            Command::TransitNodes {
+                transit_nodes: 16,
+                queries: DEFAULT_QUERIES,
+            },
+            parse_args(&args("tnr --transit-nodes 16")).unwrap().command
+        );
+        assert!(parse_args(&args("tnr --transit-nodes many")).is_err());
+
+        let network = from_osm_file("data/rutland-tiny.osm.xml", &profile::Car, LoadMode::Strict).unwrap();
+        let command = Command::TransitNodes {
+            transit_nodes: 3,
+            queries: 20,
+        };
+        assert_eq!(0, run_command(&command, &network, "car"));
+    }
+
+    #[test]
+    fn benchmarks_on_an_empty_network() {
+        let network = NetworkBuilder::new().build_network().unwrap();
+        let arc_flags = Command::ArcFlags {
+            partition: Partition::Grid(2),
+            queries: 5,
+        };
+        let tnr = Command::TransitNodes {
+            transit_nodes: 3,
+            queries: 5,
+        };
+        assert_eq!(EXIT_PREPROCESSING_FAILED, run_command(&arc_flags, &network, "car"));
+        assert_eq!(EXIT_PREPROCESSING_FAILED, run_command(&tnr, &network, "car"));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse_args(&args("")).is_err());
+        assert!(parse_args(&args("route --from 1")).is_err());
+        assert!(parse_args(&args("one-to-all")).is_err());
+        assert!(parse_args(&args("stats --from abc")).is_err());
+        assert!(parse_args(&args("stats --verbose")).is_err());
+        assert!(parse_args(&args("fly")).is_err());
+        assert!(parse_args(&args("--profile boat stats")).is_err());
+        assert!(parse_args(&args("route --from 1 --to 2 --turn-costs")).is_err());
+        assert!(parse_args(&args("route --from 1 --to 2 --max-cost 10 --algorithm astar")).is_err());
+        assert!(parse_args(&args("route --from 1 --to 2 --algorithm teleport")).is_err());
+        assert!(parse_args(&args("landmarks --count 4")).is_err());
+        assert!(parse_args(&args("landmarks --output l.json --strategy nearest")).is_err());
+    }
+
+    #[test]
+    fn route_exit_codes() {
+        let network = from_osm_file("data/oneway-way.osm.xml", &profile::Car, LoadMode::Strict).unwrap();
+
+        const ONEWAY_START: OSMNodeId = 1019308295;
+        const ONEWAY_END: OSMNodeId = 18253412;
+
+        let route = |from, to, algorithm| Command::Route {
+            from,
+            to,
+            max_cost: 0,
+            algorithm,
+            landmarks: None,
+            turn_costs: false,
+        };
+
+        for &algorithm in &[
+            Algorithm::Dijkstra,
+            Algorithm::Bidirectional,
+            Algorithm::AStar,
+            Algorithm::Alt,
+            Algorithm::Ch,
+            Algorithm::TurnAware,
+        ] {
+            let forwards = route(ONEWAY_START, ONEWAY_END, algorithm);
+            assert_eq!(0, run_command(&forwards, &network, "car"));
+
+            let backwards = route(ONEWAY_END, ONEWAY_START, algorithm);
+            assert_eq!(EXIT_NO_PATH, run_command(&backwards, &network, "car"));
+
+            let missing = route(ONEWAY_START, 1, algorithm);
+            assert_eq!(EXIT_NODE_NOT_FOUND, run_command(&missing, &network, "car"));
+        }
     }
 }
 
@@ -129,6 +976,16 @@ mod rutland_tests {
         assert_eq!(-0.5134241, lat_long.1);
     }
 
+    // data/rutland-latest.osm.pbf is written from the XML by data/make-pbf-fixtures.sh
+    #[test]
+    fn pbf_matches_xml() {
+        let from_xml = from_osm_rutland();
+        let from_pbf = from_osm_file("data/rutland-latest.osm.pbf", &profile::Car, LoadMode::Strict).expect("loading network failed");
+        assert_eq!(from_xml.node_count(), from_pbf.node_count());
+        assert_eq!(from_xml.arc_count(), from_pbf.arc_count());
+        assert_eq!(from_xml.way_count(), from_pbf.way_count());
+    }
+
     #[test]
     fn total_nodes() {
         // TODO can we use a common Network across tests?
@@ -158,7 +1015,7 @@ mod rutland_tests {
         let file = "data/rutland-tiny.osm.xml";
         let xml_string = fs::read_to_string(file).expect("couldn't read osm file");
 
-        let network = load_xml::load_network_from_string(&xml_string).unwrap();
+        let network = osm::load_xml::load_network_from_string(&xml_string, &profile::Car).unwrap();
         // let network = from_osm_rutland();
 
         const START_NODE: OSMNodeId = 18328098;
@@ -166,6 +1023,13 @@ mod rutland_tests {
             .expect("couldn't find chesnut close");
         println!("chestnut close adjacent arcs: {:?}", highways);
         assert_eq!(3, highways.len());
+
+        // every way is two way so each arc has a twin going back
+        let into_start = network.bwd_arcs_from_node(&START_NODE).unwrap();
+        assert_eq!(3, into_start.len());
+        for arc in into_start {
+            assert!(highways.iter().any(|h| h.head_node == arc.head_node && h.distance == arc.distance));
+        }
     }
 
     #[test]
@@ -179,17 +1043,206 @@ mod rutland_tests {
             .is_none());
     }
 
+    #[test]
+    fn astar_settles_fewer_nodes() {
+        let rutland_graph: Network = from_osm_rutland();
+
+        let oakham_braunston_road: OSMNodeId = 18335097;
+        let uppingham_queens_road: OSMNodeId = 18327809;
+
+        let dijkstra_result = dijkstra::search_dijkstra(oakham_braunston_road, uppingham_queens_road, &rutland_graph, 0, true);
+        let astar_result = astar::search_astar(oakham_braunston_road, uppingham_queens_road, &rutland_graph, true);
+
+        let dijkstra_entry = dijkstra_result.entry.unwrap();
+        let astar_entry = astar_result.entry.unwrap();
+        assert_eq!(dijkstra_entry.cost, astar_entry.cost);
+        assert_eq!(dijkstra_entry.report_traversed_ways(), astar_entry.report_traversed_ways());
+        assert!(astar_result.settled_nodes < dijkstra_result.settled_nodes);
+    }
+
+    #[test]
+    fn alt_matches_dijkstra() {
+        let rutland_graph: Network = from_osm_rutland();
+        let tables = LandmarkTables::new(&rutland_graph, DEFAULT_LANDMARK_COUNT, LandmarkStrategy::Avoid, LANDMARK_SEED);
+
+        let oakham_braunston_road: OSMNodeId = 18335097;
+        let uppingham_queens_road: OSMNodeId = 18327809;
+
+        let dijkstra_result = dijkstra::search_dijkstra(oakham_braunston_road, uppingham_queens_road, &rutland_graph, 0, false);
+        let alt_result = alt::search_alt(oakham_braunston_road, uppingham_queens_road, &rutland_graph, &tables, false);
+
+        assert_eq!(dijkstra_result.entry.unwrap().cost, alt_result.entry.unwrap().cost);
+        assert!(alt_result.settled_nodes < dijkstra_result.settled_nodes);
+    }
+
+    #[test]
+    fn ch_matches_dijkstra_on_random_pairs() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let rutland_graph: Network = from_osm_rutland();
+        let hierarchy = ContractionHierarchy::new(&rutland_graph);
+        let node_count = rutland_graph.node_count();
+
+        let mut rng = StdRng::seed_from_u64(2019);
+        for _ in 0..100 {
+            let source = rutland_graph.node_at(rng.gen_range(0..node_count)).id;
+            let target = rutland_graph.node_at(rng.gen_range(0..node_count)).id;
+
+            let expected = dijkstra::run_dijsktra(source, target, &rutland_graph, 0, true);
+            let actual = ch::run_ch_query(source, target, &rutland_graph, &hierarchy);
+            match (expected, actual) {
+                (Some(e), Some(a)) => {
+                    assert_eq!(e.cost, a.cost, "cost from {} to {}", source, target);
+                    assert_eq!(e.distance, a.distance, "distance from {} to {}", source, target);
+                }
+                (None, None) => (),
+                (e, a) => panic!("from {} to {} dijkstra found {:?}, ch found {:?}", source, target, e.map(|e| e.cost), a.map(|a| a.cost)),
+            }
+        }
+    }
+
+    #[test]
+    fn bidirectional_matches_dijkstra_on_random_pairs() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let rutland_graph: Network = from_osm_rutland();
+        let node_count = rutland_graph.node_count();
+
+        let mut rng = StdRng::seed_from_u64(2019);
+        let mut dijkstra_settled = 0;
+        let mut bidirectional_settled = 0;
+        for _ in 0..50 {
+            let source = rutland_graph.node_at(rng.gen_range(0..node_count)).id;
+            let target = rutland_graph.node_at(rng.gen_range(0..node_count)).id;
+
+            let expected = dijkstra::search_dijkstra(source, target, &rutland_graph, 0, false);
+            let actual = dijkstra::search_bidirectional_dijkstra(source, target, &rutland_graph);
+            assert_eq!(
+                expected.entry.map(|e| e.cost),
+                actual.path.map(|p| p.cost),
+                "cost from {} to {}",
+                source,
+                target
+            );
+            dijkstra_settled += expected.settled_nodes;
+            bidirectional_settled += actual.settled_nodes;
+        }
+        assert!(bidirectional_settled < dijkstra_settled);
+    }
+
+    #[test]
+    fn largest_component_always_has_a_path() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let mut rutland_graph: Network = from_osm_rutland();
+        let node_count = rutland_graph.node_count();
+        let arc_count = rutland_graph.arc_count();
+
+        let report = rutland_graph.reduce_to_largest_component();
+        assert!(report.removed_nodes > 0);
+        assert_eq!(node_count - report.removed_nodes, rutland_graph.node_count());
+        assert_eq!(arc_count - report.removed_arcs, rutland_graph.arc_count());
+
+        let node_count = rutland_graph.node_count();
+        let mut rng = StdRng::seed_from_u64(2019);
+        for _ in 0..20 {
+            let source = rutland_graph.node_at(rng.gen_range(0..node_count)).id;
+            let target = rutland_graph.node_at(rng.gen_range(0..node_count)).id;
+            assert!(dijkstra::run_dijsktra(source, target, &rutland_graph, 0, false).is_some());
+        }
+    }
+
     #[test]
     fn read_oneway() {
         let file = "data/oneway-way.osm.xml";
         let xml_string = fs::read_to_string(file).expect("couldn't read osm file");
 
-        let network = load_xml::load_network_from_string(&xml_string).unwrap();
+        let network = osm::load_xml::load_network_from_string(&xml_string, &profile::Car).unwrap();
 
         const A_NODE: OSMNodeId = 1917341728;
+        const PREVIOUS_NODE: OSMNodeId = 1019308270;
+        const FIRST_NODE: OSMNodeId = 1019308295;
+        const LAST_NODE: OSMNodeId = 18253412;
 
         // println!("network: {}", &network.to_json().unwrap());
         assert_eq!(12, network.arc_count());
         assert_eq!(1, network.fwd_arcs_from_node(&A_NODE).unwrap().len());
+
+        assert_eq!(12, network.reverse_arc_count());
+        let into_a = network.bwd_arcs_from_node(&A_NODE).unwrap();
+        assert_eq!(1, into_a.len());
+        assert_eq!(PREVIOUS_NODE, network.node_at(into_a.get(0).head_node).id);
+        assert!(network.bwd_arcs_from_node(&FIRST_NODE).unwrap().is_empty());
+        assert!(network.fwd_arcs_from_node(&LAST_NODE).unwrap().is_empty());
+        assert_eq!(1, network.bwd_arcs_from_node(&LAST_NODE).unwrap().len());
+
+        let load_fixture = |file: &str, profile: &dyn Profile| {
+            let xml_string = fs::read_to_string(file).expect("couldn't read osm file");
+            osm::load_xml::load_network_from_string(&xml_string, profile).unwrap()
+        };
+        let with_node_order = |network: &Network| {
+            network.arc_count() == 12
+                && network.fwd_arcs_from_node(&FIRST_NODE).unwrap().len() == 1
+                && network.fwd_arcs_from_node(&LAST_NODE).unwrap().is_empty()
+        };
+        let against_node_order = |network: &Network| {
+            network.arc_count() == 12
+                && network.fwd_arcs_from_node(&FIRST_NODE).unwrap().is_empty()
+                && network.fwd_arcs_from_node(&LAST_NODE).unwrap().len() == 1
+        };
+
+        for file in &[
+            "data/oneway-true.osm.xml",
+            "data/oneway-one.osm.xml",
+            "data/oneway-roundabout.osm.xml",
+            "data/oneway-motorway.osm.xml",
+        ] {
+            assert!(with_node_order(&load_fixture(file, &profile::Car)), "{}", file);
+        }
+        for file in &["data/oneway-minus-one.osm.xml", "data/oneway-reverse.osm.xml"] {
+            let network = load_fixture(file, &profile::Car);
+            assert!(against_node_order(&network), "{}", file);
+            let into_a = network.bwd_arcs_from_node(&A_NODE).unwrap();
+            assert_eq!(1, into_a.len());
+            assert_eq!(1019308334, network.node_at(into_a.get(0).head_node).id);
+        }
+
+        let contraflow = "data/oneway-bicycle.osm.xml";
+        assert!(with_node_order(&load_fixture(contraflow, &profile::Car)));
+        assert_eq!(24, load_fixture(contraflow, &profile::Bike).arc_count());
+        assert_eq!(24, load_fixture("data/oneway-way.osm.xml", &profile::Foot).arc_count());
+    }
+
+    #[test]
+    fn turn_restrictions() {
+        let file = "data/turn-restriction.osm.xml";
+        let xml_string = fs::read_to_string(file).expect("couldn't read osm file");
+        let load = |profile: &dyn Profile| osm::load_xml::load_network_from_string(&xml_string, profile).unwrap();
+
+        // the via way restriction is skipped and the last one doesn't apply to cars
+        let network = load(&profile::Car);
+        assert_eq!(2, network.turn_restriction_count());
+        assert_eq!(3, load(&profile::Bike).turn_restriction_count());
+        assert_eq!(0, load(&profile::Foot).turn_restriction_count());
+
+        let ignoring_restrictions = dijkstra::run_dijsktra(1, 4, &network, 0, true).unwrap();
+        assert_eq!("West Street->North Street", ignoring_restrictions.report_traversed_ways());
+
+        // no left turn from West Street into North Street
+        let turn_aware = dijkstra::run_turn_aware_dijkstra(1, 4, &network, None).unwrap();
+        assert_eq!("West Street->East Street->Loop Road", turn_aware.report_traversed_ways());
+        assert!(turn_aware.cost > ignoring_restrictions.cost);
+
+        // only left from North Street into East Street
+        let turn_aware = dijkstra::run_turn_aware_dijkstra(4, 1, &network, None).unwrap();
+        assert_eq!("Loop Road->East Street->West Street", turn_aware.report_traversed_ways());
+
+        // unrestricted routes are the same as plain dijkstra
+        let expected = dijkstra::run_dijsktra(3, 1, &network, 0, false).unwrap();
+        assert_eq!(expected.cost, dijkstra::run_turn_aware_dijkstra(3, 1, &network, None).unwrap().cost);
+        assert_eq!(0, dijkstra::run_turn_aware_dijkstra(2, 2, &network, None).unwrap().cost);
     }
 }
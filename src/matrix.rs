@@ -0,0 +1,213 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ch::ContractionHierarchy;
+use crate::dijkstra::{settle_nodes, DijkstraWorkspace};
+use crate::network::{Direction, Network, NodeIndex, OSMNodeId};
+
+/// the cheapest path between a source and a target of a matrix
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatrixEntry {
+    Reachable { cost: u64, distance: u64 },
+    // no path, or the source or target isn't in the network
+    Unreachable,
+}
+
+/// the cost and distance from every source to every target, a row per source
+#[derive(Clone, Debug)]
+pub struct DistanceMatrix {
+    sources: Vec<OSMNodeId>,
+    targets: Vec<OSMNodeId>,
+    entries: Vec<MatrixEntry>,
+}
+
+impl DistanceMatrix {
+    fn new(sources: &[OSMNodeId], targets: &[OSMNodeId]) -> DistanceMatrix {
+        DistanceMatrix {
+            sources: sources.to_vec(),
+            targets: targets.to_vec(),
+            entries: vec![MatrixEntry::Unreachable; sources.len() * targets.len()],
+        }
+    }
+
+    pub fn sources(&self) -> &[OSMNodeId] {
+        &self.sources
+    }
+
+    pub fn targets(&self) -> &[OSMNodeId] {
+        &self.targets
+    }
+
+    /// the entry for the source and target at these positions in the lists the matrix was built from
+    pub fn get(&self, source: usize, target: usize) -> MatrixEntry {
+        self.entries[source * self.targets.len() + target]
+    }
+
+    pub fn row(&self, source: usize) -> &[MatrixEntry] {
+        let width = self.targets.len();
+        &self.entries[source * width..(source + 1) * width]
+    }
+
+    fn set(&mut self, source: usize, target: usize, entry: MatrixEntry) {
+        let width = self.targets.len();
+        self.entries[source * width + target] = entry;
+    }
+
+    // keeps the cheaper of the entry and the one already there
+    fn improve(&mut self, source: usize, target: usize, cost: u64, distance: u64) {
+        let improves = match self.get(source, target) {
+            MatrixEntry::Reachable { cost: existing, .. } => cost < existing,
+            MatrixEntry::Unreachable => true,
+        };
+        if improves {
+            self.set(source, target, MatrixEntry::Reachable { cost, distance });
+        }
+    }
+}
+
+/// the costs and distances from every source to every target. With a contraction hierarchy
+/// a backward upward search from each target leaves its costs in buckets at the nodes it
+/// reaches, then a forward upward search from each source scans the buckets of the nodes
+/// it reaches. Without one a dijkstra runs from each source, or backwards from each target
+/// when there are fewer targets, stopping once the other side is settled. Buckets don't pay
+/// without a hierarchy, with no node order to search upwards in every backward search would
+/// settle the whole network and leave a bucket entry per target at every node
+pub fn many_to_many(
+    sources: &[OSMNodeId],
+    targets: &[OSMNodeId],
+    network: &Network,
+    hierarchy: Option<&ContractionHierarchy>,
+) -> DistanceMatrix {
+    let mut matrix = DistanceMatrix::new(sources, targets);
+    let source_indexes = node_indexes(sources, network);
+    let target_indexes = node_indexes(targets, network);
+    match hierarchy {
+        Some(hierarchy) => bucket_search(&mut matrix, &source_indexes, &target_indexes, hierarchy),
+        None => dijkstra_search(&mut matrix, &source_indexes, &target_indexes, network),
+    }
+    matrix
+}
+
+fn node_indexes(ids: &[OSMNodeId], network: &Network) -> Vec<Option<NodeIndex>> {
+    ids.iter()
+        .map(|id| {
+            let index = network.node_index(id);
+            if index.is_none() {
+                debug!("many to many. node {} not in network", id);
+            }
+            index
+        })
+        .collect()
+}
+
+fn bucket_search(
+    matrix: &mut DistanceMatrix,
+    sources: &[Option<NodeIndex>],
+    targets: &[Option<NodeIndex>],
+    hierarchy: &ContractionHierarchy,
+) {
+    // the target column, cost and distance of every backward search that reached a node
+    let mut buckets: HashMap<NodeIndex, Vec<(usize, u64, u64)>> = HashMap::new();
+    for (column, target) in targets.iter().enumerate() {
+        if let Some(target) = *target {
+            for (node, cost, distance) in hierarchy.upward_search(target, Direction::Backward, |_| false) {
+                buckets.entry(node).or_default().push((column, cost, distance));
+            }
+        }
+    }
+
+    for (row, source) in sources.iter().enumerate() {
+        if let Some(source) = *source {
+            for (node, cost, distance) in hierarchy.upward_search(source, Direction::Forward, |_| false) {
+                for &(column, target_cost, target_distance) in buckets.get(&node).into_iter().flatten() {
+                    matrix.improve(row, column, cost + target_cost, distance + target_distance);
+                }
+            }
+        }
+    }
+}
+
+fn dijkstra_search(
+    matrix: &mut DistanceMatrix,
+    sources: &[Option<NodeIndex>],
+    targets: &[Option<NodeIndex>],
+    network: &Network,
+) {
+    let mut workspace = DijkstraWorkspace::for_network(network);
+    let backward = targets.len() < sources.len();
+    let (starts, ends, direction) = if backward {
+        (targets, sources, Direction::Backward)
+    } else {
+        (sources, targets, Direction::Forward)
+    };
+    let end_set: HashSet<NodeIndex> = ends.iter().flatten().cloned().collect();
+
+    for (i, start) in starts.iter().enumerate() {
+        let start = match *start {
+            Some(start) => start,
+            None => continue,
+        };
+        let table = settle_nodes(&mut workspace, start, network, direction, Some(&end_set));
+        for (j, end) in ends.iter().enumerate() {
+            let end = match *end {
+                Some(end) => end,
+                None => continue,
+            };
+            if let (Some(cost), Some(distance)) = (table.cost(end), table.distance(end)) {
+                let (row, column) = if backward { (j, i) } else { (i, j) };
+                matrix.set(row, column, MatrixEntry::Reachable { cost, distance });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod matrix_test {
+    use super::*;
+    use crate::dijkstra::run_dijsktra;
+    use crate::test_networks::{grid_id, make_grid_network, make_oneway_network};
+
+    fn assert_matches_dijkstra(matrix: &DistanceMatrix, network: &Network) {
+        for (row, &source) in matrix.sources().iter().enumerate() {
+            for (column, &target) in matrix.targets().iter().enumerate() {
+                let expected = match run_dijsktra(source, target, network, 0, false) {
+                    Some(entry) => MatrixEntry::Reachable {
+                        cost: entry.cost,
+                        distance: entry.distance,
+                    },
+                    None => MatrixEntry::Unreachable,
+                };
+                assert_eq!(expected, matrix.get(row, column), "from {} to {}", source, target);
+            }
+        }
+    }
+
+    #[test]
+    fn matches_dijkstra() {
+        let network = make_grid_network();
+        let hierarchy = ContractionHierarchy::new(&network);
+        let depots = [grid_id(0, 0), grid_id(4, 7), grid_id(9, 2)];
+        let customers = [grid_id(9, 9), grid_id(1, 1), grid_id(4, 7), grid_id(5, 0), grid_id(0, 9)];
+
+        for &(sources, targets) in &[(&depots[..], &customers[..]), (&customers[..], &depots[..])] {
+            let with_ch = many_to_many(sources, targets, &network, Some(&hierarchy));
+            let without_ch = many_to_many(sources, targets, &network, None);
+            assert_eq!(targets.len(), with_ch.row(sources.len() - 1).len());
+            assert_matches_dijkstra(&with_ch, &network);
+            assert_matches_dijkstra(&without_ch, &network);
+        }
+    }
+
+    #[test]
+    fn unreachable_pairs() {
+        let network = make_oneway_network();
+        let hierarchy = ContractionHierarchy::new(&network);
+        for hierarchy in &[Some(&hierarchy), None] {
+            let matrix = many_to_many(&[1, 3, 999], &[3, 1], &network, *hierarchy);
+            assert_eq!(MatrixEntry::Reachable { cost: 10, distance: 8 }, matrix.get(0, 0));
+            assert_eq!(MatrixEntry::Reachable { cost: 0, distance: 0 }, matrix.get(0, 1));
+            assert_eq!(MatrixEntry::Unreachable, matrix.get(1, 1));
+            assert_eq!(&[MatrixEntry::Unreachable, MatrixEntry::Unreachable], matrix.row(2));
+            assert_matches_dijkstra(&matrix, &network);
+        }
+    }
+}
@@ -1,15 +1,29 @@
 use serde::{Deserialize, Serialize};
 
+use crate::scc;
+
 use std::collections::{HashMap, HashSet};
+use std::error;
 use std::hash::{Hash, Hasher};
+use std::iter::Zip;
+use std::ops::Range;
+use std::slice;
 
 #[cfg(test)]
 use serde_json::Result;
 
+mod snapshot;
+
 pub type OSMNodeId = u64;
 pub type NodeIndex = usize;
 pub type OSMWayId = u64;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
 pub const DEGREE_CONV: f64 = 10_000_000.0;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -32,7 +46,7 @@ impl Hash for Node {
 impl Node {
     pub fn new(id: OSMNodeId, lat: f64, long: f64) -> Node {
         Node {
-            id: id,
+            id,
             latitude: degrees_to_i32(lat),
             longitude: degrees_to_i32(long),
         }
@@ -47,7 +61,7 @@ pub fn degrees_to_i32(degrees: f64) -> i32 {
     (degrees * DEGREE_CONV) as i32
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Arc<T> {
     pub head_node: T,
     pub distance: u64,
@@ -61,12 +75,49 @@ pub struct WayInfo {
     pub name: Option<String>,
 }
 
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+pub enum RestrictionKind {
+    /// the turn from from_way to to_way is banned
+    No,
+    /// the turn from from_way to to_way is the only one allowed
+    Only,
+}
+
+/// a turn restriction from an OSM relation with a via node
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct TurnRestriction {
+    pub from_way: OSMWayId,
+    pub via: OSMNodeId,
+    pub to_way: OSMWayId,
+    pub kind: RestrictionKind,
+}
+
+impl TurnRestriction {
+    /// whether the restriction bans turning from one way onto another at its via node
+    pub fn bans(&self, from_way: OSMWayId, to_way: OSMWayId) -> bool {
+        self.from_way == from_way
+            && match self.kind {
+                RestrictionKind::No => self.to_way == to_way,
+                RestrictionKind::Only => self.to_way != to_way,
+            }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct NetworkBuilder {
     all_nodes: HashMap<OSMNodeId, Node>,
     used_nodes: HashSet<OSMNodeId>,
     way_info: HashMap<OSMWayId, WayInfo>,
     pub adjacent_arcs: HashMap<OSMNodeId, Vec<Arc<OSMNodeId>>>,
+    #[serde(default)]
+    turn_restrictions: Vec<TurnRestriction>,
+    #[serde(default)]
+    traffic_signals: HashSet<OSMNodeId>,
+}
+impl Default for NetworkBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 impl NetworkBuilder {
     pub fn new() -> NetworkBuilder {
@@ -75,6 +126,8 @@ impl NetworkBuilder {
             used_nodes: HashSet::new(),
             way_info: HashMap::new(),
             adjacent_arcs: HashMap::new(),
+            turn_restrictions: vec![],
+            traffic_signals: HashSet::new(),
         }
     }
 
@@ -104,47 +157,64 @@ impl NetworkBuilder {
         self.way_info.insert(way_info.id, way_info);
     }
 
+    pub fn insert_turn_restriction(&mut self, restriction: TurnRestriction) {
+        self.turn_restrictions.push(restriction);
+    }
+
+    pub fn insert_traffic_signal(&mut self, node_id: OSMNodeId) {
+        self.traffic_signals.insert(node_id);
+    }
+
     pub fn total_arcs(&self) -> usize {
         self.adjacent_arcs.values().map(|v| v.len()).sum()
     }
 
     pub fn build_network(self) -> Option<Network> {
-        println!("build_network");
-
-        println!("total adjacent_arcs keys {}, total used nodes {}", self.adjacent_arcs.len(), self.used_nodes.len());
+        debug!("total adjacent_arcs keys {}, total used nodes {}", self.adjacent_arcs.len(), self.used_nodes.len());
 
         let nodes_to_keep: Vec<&OSMNodeId> = if self.used_nodes.is_empty() { self.adjacent_arcs.keys().collect() } else { self.used_nodes.iter().collect() };
         let node_vec: Vec<Node> = nodes_to_keep.iter().map(|id| self.all_nodes.get(id).unwrap().clone()).collect();
         let with_index: HashMap<OSMNodeId, NodeIndex> = node_vec.iter().map(|n| n.id).zip(0..).collect();
 
         // build NodeIndex ordered graphs
-        let mut forward_graph: Vec<Vec<Arc<NodeIndex>>> = vec![];
-        for node in node_vec.iter() {
-            let adjacent = self.adjacent_arcs.get(&node.id);
-            let fwd_arcs: Vec<Arc<NodeIndex>> = match adjacent {
-                Some(arcs) => {
-                    arcs.iter().map(|a| {
-                        let head_node_index = with_index.get(&a.head_node)
-                        .and_then(|index| Some(Arc { 
-                            head_node: *index,  
-                            distance: a.distance,
-                            cost: a.cost,
-                            part_of_way: a.part_of_way,
-                            })).unwrap();
-                            head_node_index
-                    }).collect()
-                },
-                None => vec![]
-            };
-            forward_graph.push(fwd_arcs);
-        };
+        let indexed_arcs = node_vec.iter().enumerate().flat_map(|(tail, node)| {
+            let adjacent = self.adjacent_arcs.get(&node.id).map(Vec::as_slice).unwrap_or(&[]);
+            let with_index = &with_index;
+            adjacent.iter().map(move |a| {
+                let arc = Arc {
+                    head_node: with_index[&a.head_node],
+                    distance: a.distance,
+                    cost: a.cost,
+                    part_of_way: a.part_of_way,
+                };
+                (tail, arc)
+            })
+        });
+        let forward_graph = AdjacencyArray::from_arcs(node_vec.len(), indexed_arcs);
+
+        let reverse_graph = forward_graph.reversed();
+
+        let min_cost_per_metre = min_cost_per_metre(&forward_graph);
+
+        // restrictions via nodes that aren't in the network can never apply
+        let mut turn_restrictions: HashMap<NodeIndex, Vec<TurnRestriction>> = HashMap::new();
+        for restriction in self.turn_restrictions {
+            if let Some(&via) = with_index.get(&restriction.via) {
+                turn_restrictions.entry(via).or_default().push(restriction);
+            }
+        }
+
+        let traffic_signals = self.traffic_signals.iter().filter_map(|id| with_index.get(id).cloned()).collect();
 
         Some(Network {
             node_indexes: with_index,
             nodes: node_vec,
-            forward_graph: forward_graph,
-            reverse_graph: vec![],
+            forward_graph,
+            reverse_graph,
             way_info: self.way_info,
+            turn_restrictions,
+            traffic_signals,
+            min_cost_per_metre,
         })
     }
 
@@ -154,13 +224,22 @@ impl NetworkBuilder {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReductionReport {
+    pub removed_nodes: usize,
+    pub removed_arcs: usize,
+}
+
 #[derive(Debug)]
 pub struct Network {
     pub node_indexes: HashMap<OSMNodeId, NodeIndex>, 
-    pub forward_graph: Vec<Vec<Arc<NodeIndex>>>,
-    reverse_graph: Vec<Vec<Arc<NodeIndex>>>,
+    forward_graph: AdjacencyArray,
+    reverse_graph: AdjacencyArray,
     nodes: Vec<Node>,
     way_info: HashMap<OSMWayId, WayInfo>,
+    turn_restrictions: HashMap<NodeIndex, Vec<TurnRestriction>>,
+    traffic_signals: HashSet<NodeIndex>,
+    min_cost_per_metre: f64,
 }
 
 impl Network {
@@ -168,24 +247,321 @@ impl Network {
         self.node_indexes.get(node_id).and_then(|&index| self.nodes.get(index))
     }
 
+    pub fn node_index(&self, node_id: &OSMNodeId) -> Option<NodeIndex> {
+        self.node_indexes.get(node_id).cloned()
+    }
+
+    pub fn node_at(&self, index: NodeIndex) -> &Node {
+        &self.nodes[index]
+    }
+
+    pub fn fwd_arcs(&self, index: NodeIndex) -> Arcs<'_> {
+        self.forward_graph.arcs(index)
+    }
+
+    /// positions of the node's forward arcs among all the forward arcs, for data kept per arc
+    pub fn fwd_arc_ids(&self, index: NodeIndex) -> Range<usize> {
+        self.forward_graph.arc_ids(index)
+    }
+
+    /// arcs into the node, with head_node set to the tail of the original arc
+    pub fn bwd_arcs(&self, index: NodeIndex) -> Arcs<'_> {
+        self.reverse_graph.arcs(index)
+    }
+
+    pub fn arcs(&self, index: NodeIndex, direction: Direction) -> Arcs<'_> {
+        match direction {
+            Direction::Forward => self.fwd_arcs(index),
+            Direction::Backward => self.bwd_arcs(index),
+        }
+    }
+
+    /// the lowest cost of any arc per metre of its length. Any path covering a
+    /// straight line distance costs at least that distance times this factor
+    pub fn min_cost_per_metre(&self) -> f64 {
+        self.min_cost_per_metre
+    }
+
     pub fn get_way_info(&self, arc: &Arc<NodeIndex>) -> Option<&WayInfo> {
         self.way_info.get(&arc.part_of_way)
     }
 
     pub fn arc_count(&self) -> usize {
-        self.forward_graph.iter().map(|v| v.len()).sum()
+        self.forward_graph.arc_count()
     }
 
     pub fn node_count(&self) -> usize {
         self.nodes.len()
     }
 
-    pub fn fwd_arcs_from_node(&self, node_id: &OSMNodeId) -> Option<&Vec<Arc<NodeIndex>>> {
-        let res = self.node_indexes.get(node_id).and_then(|&index| self.forward_graph.get(index));
-        res
+    pub fn way_count(&self) -> usize {
+        self.way_info.len()
+    }
+
+    pub fn reverse_arc_count(&self) -> usize {
+        self.reverse_graph.arc_count()
+    }
+
+    pub fn turn_restriction_count(&self) -> usize {
+        self.turn_restrictions.values().map(|v| v.len()).sum()
+    }
+
+    pub fn traffic_signal_count(&self) -> usize {
+        self.traffic_signals.len()
+    }
+
+    pub fn has_traffic_signals(&self, index: NodeIndex) -> bool {
+        self.traffic_signals.contains(&index)
+    }
+
+    /// whether a path may continue from the arc arriving at via onto the arc leaving it
+    pub fn is_turn_allowed(&self, from: &Arc<NodeIndex>, via: NodeIndex, to: &Arc<NodeIndex>) -> bool {
+        match self.turn_restrictions.get(&via) {
+            Some(restrictions) => !restrictions.iter().any(|r| r.bans(from.part_of_way, to.part_of_way)),
+            None => true,
+        }
+    }
+
+    /// writes the network to a binary snapshot that load reads back far faster than
+    /// parsing the OSM file it came from, along with the name of the profile it was built with
+    pub fn save(&self, file_path: &str, profile: &str) -> std::result::Result<(), Box<dyn error::Error>> {
+        snapshot::save(self, file_path, profile)
+    }
+
+    /// reads a snapshot written by save and the name of its profile, refusing files with a
+    /// different format version
+    pub fn load(file_path: &str) -> std::result::Result<(Network, String), Box<dyn error::Error>> {
+        snapshot::load(file_path)
+    }
+
+    pub fn fwd_arcs_from_node(&self, node_id: &OSMNodeId) -> Option<Arcs<'_>> {
+        self.node_indexes.get(node_id).map(|&index| self.forward_graph.arcs(index))
+    }
+
+    /// drops every node outside the largest strongly connected component, so that
+    /// every remaining node can reach every other
+    pub fn reduce_to_largest_component(&mut self) -> ReductionReport {
+        let keep = scc::largest_component(self);
+        self.retain_nodes(&keep)
+    }
+
+    /// drops the nodes not flagged to keep along with any arcs touching them. The kept
+    /// nodes are given new NodeIndexes in their existing order
+    pub fn retain_nodes(&mut self, keep: &[bool]) -> ReductionReport {
+        let node_count = self.node_count();
+        let arc_count = self.arc_count();
+
+        let mut new_indexes: Vec<Option<NodeIndex>> = vec![None; node_count];
+        let mut next_index = 0;
+        for (old_index, new_index) in new_indexes.iter_mut().enumerate() {
+            if keep[old_index] {
+                *new_index = Some(next_index);
+                next_index += 1;
+            }
+        }
+
+        let old_restrictions = std::mem::take(&mut self.turn_restrictions);
+        self.turn_restrictions = old_restrictions
+            .into_iter()
+            .filter_map(|(via, restrictions)| new_indexes[via].map(|new_via| (new_via, restrictions)))
+            .collect();
+        let old_signals = std::mem::take(&mut self.traffic_signals);
+        self.traffic_signals = old_signals.into_iter().filter_map(|index| new_indexes[index]).collect();
+
+        let old_nodes = std::mem::take(&mut self.nodes);
+        self.nodes = old_nodes
+            .into_iter()
+            .zip(new_indexes.iter())
+            .filter(|(_, new_index)| new_index.is_some())
+            .map(|(node, _)| node)
+            .collect();
+        let kept_arcs = self.forward_graph.iter().filter_map(|(tail, arc)| {
+            match (new_indexes[tail], new_indexes[arc.head_node]) {
+                (Some(tail), Some(head_node)) => Some((tail, Arc { head_node, ..arc })),
+                _ => None,
+            }
+        });
+        self.forward_graph = AdjacencyArray::from_arcs(next_index, kept_arcs);
+
+        self.node_indexes = self.nodes.iter().map(|n| n.id).zip(0..).collect();
+        self.reverse_graph = self.forward_graph.reversed();
+        let used_ways: HashSet<OSMWayId> = self.forward_graph.iter().map(|(_, arc)| arc.part_of_way).collect();
+        self.way_info.retain(|id, _| used_ways.contains(id));
+        self.min_cost_per_metre = min_cost_per_metre(&self.forward_graph);
+
+        let report = ReductionReport {
+            removed_nodes: node_count - self.node_count(),
+            removed_arcs: arc_count - self.arc_count(),
+        };
+        info!("removed {} nodes and {} arcs", report.removed_nodes, report.removed_arcs);
+        report
+    }
+
+    /// arcs into the node, with head_node set to the node each arc starts from
+    pub fn bwd_arcs_from_node(&self, node_id: &OSMNodeId) -> Option<Arcs<'_>> {
+        self.node_indexes.get(node_id).map(|&index| self.reverse_graph.arcs(index))
     }
 }
 
+/// the arcs of every node ordered by the node they leave, with an array each for their
+/// heads, distances, costs and way ids so that a search relaxing arcs only reads the fields
+/// it uses. An offset array gives where each node's arcs start, the arcs of node i are at
+/// offsets[i]..offsets[i + 1] in every field array
+#[derive(Clone, Debug, Default, PartialEq)]
+struct AdjacencyArray {
+    offsets: Vec<usize>,
+    heads: Vec<NodeIndex>,
+    distances: Vec<u64>,
+    costs: Vec<u64>,
+    way_ids: Vec<OSMWayId>,
+}
+
+impl AdjacencyArray {
+    /// from (tail, arc) pairs in any order, arcs leaving the same node keep their order
+    fn from_arcs<I: IntoIterator<Item = (NodeIndex, Arc<NodeIndex>)>>(node_count: usize, arcs: I) -> AdjacencyArray {
+        let mut arcs: Vec<(NodeIndex, Arc<NodeIndex>)> = arcs.into_iter().collect();
+        // a stable sort keeps the order of arcs leaving the same node
+        arcs.sort_by_key(|&(tail, _)| tail);
+
+        let mut offsets = vec![0; node_count + 1];
+        for (tail, _) in &arcs {
+            offsets[tail + 1] += 1;
+        }
+        for i in 0..node_count {
+            offsets[i + 1] += offsets[i];
+        }
+        AdjacencyArray {
+            offsets,
+            heads: arcs.iter().map(|(_, arc)| arc.head_node).collect(),
+            distances: arcs.iter().map(|(_, arc)| arc.distance).collect(),
+            costs: arcs.iter().map(|(_, arc)| arc.cost).collect(),
+            way_ids: arcs.iter().map(|(_, arc)| arc.part_of_way).collect(),
+        }
+    }
+
+    fn arcs(&self, index: NodeIndex) -> Arcs<'_> {
+        let ids = self.arc_ids(index);
+        Arcs {
+            heads: &self.heads[ids.clone()],
+            distances: &self.distances[ids.clone()],
+            costs: &self.costs[ids.clone()],
+            way_ids: &self.way_ids[ids],
+        }
+    }
+
+    fn arc_ids(&self, index: NodeIndex) -> Range<usize> {
+        self.offsets[index]..self.offsets[index + 1]
+    }
+
+    fn node_count(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    fn arc_count(&self) -> usize {
+        self.heads.len()
+    }
+
+    /// every arc with the node it leaves
+    fn iter(&self) -> impl Iterator<Item = (NodeIndex, Arc<NodeIndex>)> + '_ {
+        (0..self.node_count()).flat_map(move |tail| self.arcs(tail).iter().map(move |arc| (tail, arc)))
+    }
+
+    /// the same arcs turned around, with head_node set to the node each arc started from
+    fn reversed(&self) -> AdjacencyArray {
+        let reversed = self.iter().map(|(tail, arc)| (arc.head_node, Arc { head_node: tail, ..arc }));
+        AdjacencyArray::from_arcs(self.node_count(), reversed)
+    }
+}
+
+/// the arcs leaving one node, read from the field arrays of an AdjacencyArray. Arcs are
+/// put together on the way out so callers that want every field get an Arc
+#[derive(Clone, Copy, Debug)]
+pub struct Arcs<'a> {
+    heads: &'a [NodeIndex],
+    distances: &'a [u64],
+    costs: &'a [u64],
+    way_ids: &'a [OSMWayId],
+}
+
+impl<'a> Arcs<'a> {
+    pub fn len(&self) -> usize {
+        self.heads.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heads.is_empty()
+    }
+
+    /// the arc at a position among the node's arcs, panics past the end like slice indexing
+    pub fn get(&self, position: usize) -> Arc<NodeIndex> {
+        Arc {
+            head_node: self.heads[position],
+            distance: self.distances[position],
+            cost: self.costs[position],
+            part_of_way: self.way_ids[position],
+        }
+    }
+
+    pub fn heads(&self) -> &'a [NodeIndex] {
+        self.heads
+    }
+
+    pub fn distances(&self) -> &'a [u64] {
+        self.distances
+    }
+
+    pub fn costs(&self) -> &'a [u64] {
+        self.costs
+    }
+
+    pub fn iter(&self) -> ArcIter<'a> {
+        ArcIter(self.heads.iter().zip(self.distances).zip(self.costs).zip(self.way_ids))
+    }
+}
+
+impl<'a> IntoIterator for Arcs<'a> {
+    type Item = Arc<NodeIndex>;
+    type IntoIter = ArcIter<'a>;
+
+    fn into_iter(self) -> ArcIter<'a> {
+        self.iter()
+    }
+}
+
+type ArcFields<'a> = Zip<Zip<Zip<slice::Iter<'a, NodeIndex>, slice::Iter<'a, u64>>, slice::Iter<'a, u64>>, slice::Iter<'a, OSMWayId>>;
+
+#[derive(Clone, Debug)]
+pub struct ArcIter<'a>(ArcFields<'a>);
+
+impl<'a> Iterator for ArcIter<'a> {
+    type Item = Arc<NodeIndex>;
+
+    fn next(&mut self) -> Option<Arc<NodeIndex>> {
+        self.0.next().map(|(((&head_node, &distance), &cost), &part_of_way)| Arc {
+            head_node,
+            distance,
+            cost,
+            part_of_way,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for ArcIter<'a> {}
+
+fn min_cost_per_metre(graph: &AdjacencyArray) -> f64 {
+    graph
+        .iter()
+        .map(|(_, arc)| arc)
+        .filter(|arc| arc.distance > 0)
+        .map(|arc| arc.cost as f64 / arc.distance as f64)
+        .fold(None, |min: Option<f64>, ratio| Some(min.map_or(ratio, |m| m.min(ratio))))
+        .unwrap_or(0.0)
+}
+
 #[test]
 fn serialize() {
     let mut network = NetworkBuilder::new();
@@ -202,7 +578,7 @@ fn serialize() {
         },
     );
 
-    let toml = serde_json::to_string(&network).unwrap();
+    let toml = network.to_json().unwrap();
     println!("{}", &toml);
 
     let n2: NetworkBuilder = serde_json::from_str(&toml).unwrap();
@@ -259,4 +635,57 @@ fn build_network() {
     assert!(node.is_some());
     assert_eq!(5, node.unwrap().id);
 
+    // arc 3->5 has a cost of 2 over a distance of 2 and all other arcs are the same
+    assert_eq!(1.0, network.min_cost_per_metre());
+
+    assert_eq!(network.arc_count(), network.reverse_arc_count());
+    let tails_into = |id: OSMNodeId| -> Vec<(OSMNodeId, OSMWayId)> {
+        let mut tails: Vec<(OSMNodeId, OSMWayId)> = network
+            .bwd_arcs_from_node(&id)
+            .unwrap()
+            .iter()
+            .map(|a| (network.node_at(a.head_node).id, a.part_of_way))
+            .collect();
+        tails.sort();
+        tails
+    };
+    assert_eq!(vec![(2, 25), (3, 35)], tails_into(5));
+    assert_eq!(vec![(2, 21), (3, 31), (4, 41)], tails_into(1));
+    assert!(network.bwd_arcs_from_node(&6).is_none());
+
+}
+
+#[test]
+fn turn_restriction_bans() {
+    let no_left = TurnRestriction { from_way: 1, via: 10, to_way: 2, kind: RestrictionKind::No };
+    assert!(no_left.bans(1, 2));
+    assert!(!no_left.bans(1, 3));
+    assert!(!no_left.bans(3, 2));
+
+    let only_straight = TurnRestriction { from_way: 1, via: 10, to_way: 2, kind: RestrictionKind::Only };
+    assert!(!only_straight.bans(1, 2));
+    assert!(only_straight.bans(1, 3));
+    assert!(only_straight.bans(1, 1));
+    assert!(!only_straight.bans(3, 1));
+}
+
+#[test]
+fn adjacency_array() {
+    let arc = |head_node, part_of_way| Arc {
+        head_node,
+        distance: 1,
+        cost: 1,
+        part_of_way,
+    };
+    let graph = AdjacencyArray::from_arcs(4, vec![(2, arc(0, 20)), (0, arc(1, 1)), (2, arc(1, 21)), (0, arc(2, 2))]);
+    assert_eq!(vec![0, 2, 2, 4, 4], graph.offsets);
+    assert_eq!(vec![1, 2], graph.arcs(0).iter().map(|a| a.part_of_way).collect::<Vec<_>>());
+    assert!(graph.arcs(1).is_empty());
+    assert_eq!(vec![20, 21], graph.arcs(2).iter().map(|a| a.part_of_way).collect::<Vec<_>>());
+    assert!(graph.arcs(3).is_empty());
+
+    let reversed = graph.reversed();
+    assert_eq!(4, reversed.arc_count());
+    let into_one: Vec<(NodeIndex, OSMWayId)> = reversed.arcs(1).iter().map(|a| (a.head_node, a.part_of_way)).collect();
+    assert_eq!(vec![(0, 1), (2, 21)], into_one);
 }
@@ -0,0 +1,405 @@
+// a binary snapshot of a built network, little endian throughout. A header of magic, format
+// version, profile name and the node, arc, way, turn restriction and traffic signal counts is followed by
+// min cost per metre, node ids, latitudes and longitudes as flat arrays, the forward and
+// reverse graphs as offset and per field arc arrays, way info, turn restrictions and traffic signals
+
+use std::collections::{HashMap, HashSet};
+use std::error;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use super::{AdjacencyArray, Network, Node, NodeIndex, RestrictionKind, TurnRestriction, WayInfo};
+
+const MAGIC: &[u8; 8] = b"ERPFNET\0";
+/// bump whenever the layout changes, older snapshots are refused rather than misread
+const FORMAT_VERSION: u32 = 3;
+
+/// u32::MAX length marks a way without a name
+const NO_NAME: u32 = u32::MAX;
+
+/// profile names longer than this are refused
+const MAX_PROFILE_NAME: u32 = 64;
+/// OSM limits tag values to 255 characters, this leaves room for multi byte ones
+const MAX_WAY_NAME: u32 = 1024;
+
+pub fn save(network: &Network, file_path: &str, profile: &str) -> Result<(), Box<dyn error::Error>> {
+    let mut writer = BufWriter::new(File::create(file_path)?);
+    write_network(network, profile, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn load(file_path: &str) -> Result<(Network, String), Box<dyn error::Error>> {
+    let file = File::open(file_path)?;
+    let length = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let loaded = read_network(&mut reader, length)?;
+    let mut rest = [0u8; 1];
+    if reader.read(&mut rest)? != 0 {
+        return Err(invalid_data("trailing data after network"));
+    }
+    Ok(loaded)
+}
+
+fn write_network<W: Write>(network: &Network, profile: &str, w: &mut W) -> io::Result<()> {
+    let mut way_ids: Vec<_> = network.way_info.keys().cloned().collect();
+    way_ids.sort_unstable();
+    let mut restriction_vias: Vec<_> = network.turn_restrictions.keys().cloned().collect();
+    restriction_vias.sort_unstable();
+    let mut signals: Vec<_> = network.traffic_signals.iter().cloned().collect();
+    signals.sort_unstable();
+
+    if profile.len() > MAX_PROFILE_NAME as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "profile name too long"));
+    }
+
+    w.write_all(MAGIC)?;
+    write_u32(w, FORMAT_VERSION)?;
+    write_u32(w, profile.len() as u32)?;
+    w.write_all(profile.as_bytes())?;
+    write_u64(w, network.nodes.len() as u64)?;
+    write_u64(w, network.arc_count() as u64)?;
+    write_u64(w, way_ids.len() as u64)?;
+    write_u64(w, network.turn_restriction_count() as u64)?;
+    write_u64(w, signals.len() as u64)?;
+    w.write_all(&network.min_cost_per_metre.to_le_bytes())?;
+
+    for node in &network.nodes {
+        write_u64(w, node.id)?;
+    }
+    for node in &network.nodes {
+        w.write_all(&node.latitude.to_le_bytes())?;
+    }
+    for node in &network.nodes {
+        w.write_all(&node.longitude.to_le_bytes())?;
+    }
+
+    write_graph(w, &network.forward_graph)?;
+    write_graph(w, &network.reverse_graph)?;
+
+    for id in way_ids {
+        write_u64(w, id)?;
+        match &network.way_info[&id].name {
+            Some(name) if name.len() > MAX_WAY_NAME as usize => {
+                let message = format!("way {} has a name longer than {} bytes", id, MAX_WAY_NAME);
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+            }
+            Some(name) => {
+                write_u32(w, name.len() as u32)?;
+                w.write_all(name.as_bytes())?;
+            }
+            None => write_u32(w, NO_NAME)?,
+        }
+    }
+
+    for via in restriction_vias {
+        for restriction in &network.turn_restrictions[&via] {
+            write_u64(w, via as u64)?;
+            write_u64(w, restriction.from_way)?;
+            write_u64(w, restriction.via)?;
+            write_u64(w, restriction.to_way)?;
+            let kind = match restriction.kind {
+                RestrictionKind::No => 0,
+                RestrictionKind::Only => 1,
+            };
+            w.write_all(&[kind])?;
+        }
+    }
+
+    for index in signals {
+        write_u64(w, index as u64)?;
+    }
+    Ok(())
+}
+
+/// length is the size of the whole snapshot, the counts in the header must fit in it
+fn read_network<R: Read>(r: &mut R, length: u64) -> Result<(Network, String), Box<dyn error::Error>> {
+    let mut magic = [0u8; 8];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("not a network snapshot"));
+    }
+    let version = read_u32(r)?;
+    if version != FORMAT_VERSION {
+        return Err(invalid_data(&format!(
+            "network snapshot is version {}, expected version {}",
+            version, FORMAT_VERSION
+        )));
+    }
+    let name_length = read_u32(r)?;
+    if name_length > MAX_PROFILE_NAME {
+        return Err(invalid_data("profile name too long"));
+    }
+    let mut name = vec![0u8; name_length as usize];
+    r.read_exact(&mut name)?;
+    let profile = String::from_utf8(name)?;
+
+    let node_count = read_count(r)?;
+    let arc_count = read_count(r)?;
+    let way_count = read_count(r)?;
+    let restriction_count = read_count(r)?;
+    let signal_count = read_count(r)?;
+    let min_cost_per_metre = f64::from_le_bytes(read_array(r)?);
+
+    // the smallest file these counts could come from, so that a corrupt header can't ask
+    // for allocations larger than the file
+    let header_size = (MAGIC.len() + 4 + 4) as u64 + u64::from(name_length) + 6 * 8;
+    let graph_size = (node_count as u64 + 1) * 8 + arc_count as u64 * GRAPH_ARC_SIZE;
+    let smallest_size = header_size
+        + node_count as u64 * (8 + 4 + 4)
+        + 2 * graph_size
+        + way_count as u64 * (8 + 4)
+        + restriction_count as u64 * (4 * 8 + 1)
+        + signal_count as u64 * 8;
+    if smallest_size > length {
+        return Err(invalid_data(&format!(
+            "header counts need at least {} bytes but the snapshot has {}",
+            smallest_size, length
+        )));
+    }
+
+    let ids = (0..node_count).map(|_| read_u64(r)).collect::<io::Result<Vec<_>>>()?;
+    let latitudes = (0..node_count).map(|_| read_i32(r)).collect::<io::Result<Vec<_>>>()?;
+    let longitudes = (0..node_count).map(|_| read_i32(r)).collect::<io::Result<Vec<_>>>()?;
+    let nodes: Vec<Node> = ids
+        .iter()
+        .zip(latitudes)
+        .zip(longitudes)
+        .map(|((&id, latitude), longitude)| Node { id, latitude, longitude })
+        .collect();
+    let node_indexes: HashMap<_, _> = ids.into_iter().zip(0..).collect();
+    if node_indexes.len() != node_count {
+        return Err(invalid_data("duplicate node ids"));
+    }
+
+    let forward_graph = read_graph(r, node_count, arc_count)?;
+    let reverse_graph = read_graph(r, node_count, arc_count)?;
+
+    let mut way_info = HashMap::with_capacity(way_count);
+    for _ in 0..way_count {
+        let id = read_u64(r)?;
+        let name = match read_u32(r)? {
+            NO_NAME => None,
+            length if length > MAX_WAY_NAME => return Err(invalid_data("way name too long")),
+            length => {
+                let mut bytes = vec![0u8; length as usize];
+                r.read_exact(&mut bytes)?;
+                Some(String::from_utf8(bytes)?)
+            }
+        };
+        way_info.insert(id, WayInfo { id, name });
+    }
+
+    let mut turn_restrictions: HashMap<NodeIndex, Vec<TurnRestriction>> = HashMap::new();
+    for _ in 0..restriction_count {
+        let via_index = read_index(r, node_count)?;
+        let from_way = read_u64(r)?;
+        let via = read_u64(r)?;
+        let to_way = read_u64(r)?;
+        let kind = match read_array::<_, 1>(r)?[0] {
+            0 => RestrictionKind::No,
+            1 => RestrictionKind::Only,
+            other => return Err(invalid_data(&format!("unknown restriction kind {}", other))),
+        };
+        turn_restrictions.entry(via_index).or_default().push(TurnRestriction {
+            from_way,
+            via,
+            to_way,
+            kind,
+        });
+    }
+
+    let traffic_signals = (0..signal_count)
+        .map(|_| read_index(r, node_count))
+        .collect::<Result<HashSet<_>, _>>()?;
+
+    let network = Network {
+        node_indexes,
+        forward_graph,
+        reverse_graph,
+        nodes,
+        way_info,
+        turn_restrictions,
+        traffic_signals,
+        min_cost_per_metre,
+    };
+    Ok((network, profile))
+}
+
+/// head node, distance, cost and way id of an arc
+const GRAPH_ARC_SIZE: u64 = 4 + 3 * 8;
+
+/// the offset array of node_count + 1 entries followed by the heads, distances, costs and way ids
+fn write_graph<W: Write>(w: &mut W, graph: &AdjacencyArray) -> io::Result<()> {
+    for &offset in &graph.offsets {
+        write_u64(w, offset as u64)?;
+    }
+    for &head_node in &graph.heads {
+        write_u32(w, head_node as u32)?;
+    }
+    for values in [&graph.distances, &graph.costs, &graph.way_ids] {
+        for &value in values {
+            write_u64(w, value)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_graph<R: Read>(r: &mut R, node_count: usize, arc_count: usize) -> Result<AdjacencyArray, Box<dyn error::Error>> {
+    let offsets = (0..=node_count)
+        .map(|_| read_u64(r).map(|offset| offset as usize))
+        .collect::<io::Result<Vec<_>>>()?;
+    let in_order = offsets.windows(2).all(|pair| pair[0] <= pair[1]);
+    if offsets[0] != 0 || !in_order || offsets[node_count] != arc_count {
+        return Err(invalid_data("bad adjacency offsets"));
+    }
+
+    let mut heads = Vec::with_capacity(arc_count);
+    for _ in 0..arc_count {
+        let head_node = read_u32(r)? as NodeIndex;
+        if head_node >= node_count {
+            return Err(invalid_data("arc head out of range"));
+        }
+        heads.push(head_node);
+    }
+    let mut read_values = || (0..arc_count).map(|_| read_u64(r)).collect::<io::Result<Vec<_>>>();
+    let distances = read_values()?;
+    let costs = read_values()?;
+    let way_ids = read_values()?;
+    Ok(AdjacencyArray { offsets, heads, distances, costs, way_ids })
+}
+
+fn write_u32<W: Write>(w: &mut W, value: u32) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn write_u64<W: Write>(w: &mut W, value: u64) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn read_array<R: Read, const N: usize>(r: &mut R) -> io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    r.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    read_array(r).map(u32::from_le_bytes)
+}
+
+fn read_i32<R: Read>(r: &mut R) -> io::Result<i32> {
+    read_array(r).map(i32::from_le_bytes)
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    read_array(r).map(u64::from_le_bytes)
+}
+
+// counts must fit the u32 head_node is written as
+fn read_count<R: Read>(r: &mut R) -> Result<usize, Box<dyn error::Error>> {
+    let count = read_u64(r)?;
+    if count > u64::from(u32::MAX) {
+        return Err(invalid_data(&format!("count {} too large", count)));
+    }
+    Ok(count as usize)
+}
+
+fn read_index<R: Read>(r: &mut R, node_count: usize) -> Result<NodeIndex, Box<dyn error::Error>> {
+    let index = read_u64(r)? as usize;
+    if index >= node_count {
+        return Err(invalid_data("node index out of range"));
+    }
+    Ok(index)
+}
+
+fn invalid_data(message: &str) -> Box<dyn error::Error> {
+    Box::new(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+#[cfg(test)]
+mod snapshot_test {
+    use super::*;
+    use crate::osm::load_xml::load_network_from_file;
+    use crate::osm::profile::Car;
+    use std::env;
+
+    #[test]
+    fn save_and_load() {
+        let network = load_network_from_file("data/turn-restriction.osm.xml", &Car).unwrap();
+        let file = env::temp_dir().join("snapshot_test_save_and_load.network");
+        let file = file.to_str().unwrap();
+        network.save(file, "car").unwrap();
+        let (loaded, profile) = Network::load(file).unwrap();
+        assert_eq!("car", profile);
+
+        assert_eq!(network.node_indexes, loaded.node_indexes);
+        let coordinates = |n: &Network| n.nodes.iter().map(|node| node.lat_long_f64()).collect::<Vec<_>>();
+        assert_eq!(coordinates(&network), coordinates(&loaded));
+        assert_eq!(network.forward_graph, loaded.forward_graph);
+        assert_eq!(network.reverse_graph, loaded.reverse_graph);
+        assert_eq!(network.way_info, loaded.way_info);
+        assert_eq!(network.turn_restrictions, loaded.turn_restrictions);
+        assert_eq!(network.traffic_signals, loaded.traffic_signals);
+        assert_eq!(network.min_cost_per_metre, loaded.min_cost_per_metre);
+
+        let route = crate::dijkstra::run_turn_aware_dijkstra(1, 4, &loaded, None).unwrap();
+        assert_eq!("West Street->East Street->Loop Road", route.report_traversed_ways());
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let network = load_network_from_file("data/rutland-tiny.osm.xml", &Car).unwrap();
+        let mut bytes = vec![];
+        write_network(&network, "bike", &mut bytes).unwrap();
+        let read = |bytes: &[u8]| read_network(&mut &bytes[..], bytes.len() as u64);
+        assert_eq!("bike", read(&bytes).unwrap().1);
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = b'X';
+        assert!(read(&wrong_magic).is_err());
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[MAGIC.len()] = FORMAT_VERSION as u8 + 1;
+        let error = read(&wrong_version).unwrap_err();
+        assert!(error.to_string().contains("version"));
+
+        let mut long_name = bytes.clone();
+        long_name[MAGIC.len() + 4] = 200;
+        assert!(read(&long_name).is_err());
+
+        // a node count the file can't hold is refused before anything is allocated
+        let mut huge_count = bytes.clone();
+        let node_count_at = MAGIC.len() + 4 + 4 + "bike".len();
+        huge_count[node_count_at..node_count_at + 8].copy_from_slice(&u64::from(u32::MAX).to_le_bytes());
+        let error = read(&huge_count).unwrap_err();
+        assert!(error.to_string().contains("bytes"));
+
+        let truncated = &bytes[..bytes.len() - 1];
+        assert!(read(truncated).is_err());
+
+        let xml = std::fs::read("data/rutland-tiny.osm.xml").unwrap();
+        assert!(read(&xml).is_err());
+    }
+
+    #[test]
+    fn way_names_are_bounded() {
+        let mut network = load_network_from_file("data/turn-restriction.osm.xml", &Car).unwrap();
+        let way_id = *network.way_info.keys().next().unwrap();
+        let name = "x".repeat(MAX_WAY_NAME as usize);
+        network.way_info.get_mut(&way_id).unwrap().name = Some(name.clone());
+        let mut bytes = vec![];
+        write_network(&network, "car", &mut bytes).unwrap();
+        let read = |bytes: &[u8]| read_network(&mut &bytes[..], bytes.len() as u64);
+        assert!(read(&bytes).is_ok());
+
+        // the length in front of the name
+        let name_at = bytes.windows(name.len()).position(|w| w == name.as_bytes()).unwrap();
+        bytes[name_at - 4..name_at].copy_from_slice(&(MAX_WAY_NAME + 1).to_le_bytes());
+        let error = read(&bytes).unwrap_err();
+        assert!(error.to_string().contains("way name"));
+
+        assert!(write_network(&network, &"p".repeat(65), &mut vec![]).is_err());
+        network.way_info.get_mut(&way_id).unwrap().name = Some(name + "x");
+        assert!(write_network(&network, "car", &mut vec![]).is_err());
+    }
+}
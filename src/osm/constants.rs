@@ -1,4 +1,6 @@
-pub const HIGHWAY_ROAD_TYPES: [&'static str; 13] = [
+use std::ops::RangeInclusive;
+
+pub const HIGHWAY_ROAD_TYPES: [&str; 13] = [
     "motorway",
     "trunk",
     "primary",
@@ -13,3 +15,84 @@ pub const HIGHWAY_ROAD_TYPES: [&'static str; 13] = [
     "tertiary_link",
     "service",
 ];
+
+/// typical speeds in km/h on each road class when a way has no usable maxspeed tag
+pub const HIGHWAY_DEFAULT_SPEEDS_KMH: [(&str, f64); 13] = [
+    ("motorway", 110.0),
+    ("trunk", 90.0),
+    ("primary", 80.0),
+    ("secondary", 70.0),
+    ("tertiary", 60.0),
+    ("unclassified", 50.0),
+    ("residential", 30.0),
+    ("motorway_link", 70.0),
+    ("trunk_link", 60.0),
+    ("primary_link", 50.0),
+    ("secondary_link", 50.0),
+    ("tertiary_link", 40.0),
+    ("service", 20.0),
+];
+
+pub const KMH_PER_MPH: f64 = 1.609_344;
+
+/// the coordinates a node may have, in degrees
+pub const LATITUDE_RANGE: RangeInclusive<f64> = -90.0..=90.0;
+pub const LONGITUDE_RANGE: RangeInclusive<f64> = -180.0..=180.0;
+
+/// highway classes open to bicycles unless tagged otherwise and their cycling speeds
+pub const BICYCLE_HIGHWAY_SPEEDS_KMH: [(&str, f64); 19] = [
+    ("trunk", 20.0),
+    ("primary", 20.0),
+    ("secondary", 20.0),
+    ("tertiary", 18.0),
+    ("unclassified", 18.0),
+    ("residential", 18.0),
+    ("trunk_link", 20.0),
+    ("primary_link", 20.0),
+    ("secondary_link", 20.0),
+    ("tertiary_link", 18.0),
+    ("service", 15.0),
+    ("living_street", 10.0),
+    ("cycleway", 18.0),
+    ("path", 12.0),
+    ("track", 12.0),
+    ("bridleway", 10.0),
+    // only used with an explicit bicycle tag
+    ("footway", 10.0),
+    ("pedestrian", 10.0),
+    ("steps", 2.0),
+];
+
+/// highway classes bicycles may only use when a tag allows them
+pub const BICYCLE_PERMISSION_ONLY_TYPES: [&str; 3] = ["footway", "pedestrian", "steps"];
+
+/// highway classes open to pedestrians unless tagged otherwise and their walking speeds
+pub const FOOT_HIGHWAY_SPEEDS_KMH: [(&str, f64); 21] = [
+    ("trunk", 5.0),
+    ("primary", 5.0),
+    ("secondary", 5.0),
+    ("tertiary", 5.0),
+    ("unclassified", 5.0),
+    ("residential", 5.0),
+    ("trunk_link", 5.0),
+    ("primary_link", 5.0),
+    ("secondary_link", 5.0),
+    ("tertiary_link", 5.0),
+    ("service", 5.0),
+    ("living_street", 5.0),
+    ("pedestrian", 5.0),
+    ("footway", 5.0),
+    ("cycleway", 5.0),
+    ("path", 5.0),
+    ("track", 5.0),
+    ("bridleway", 5.0),
+    ("steps", 3.0),
+    ("corridor", 5.0),
+    ("platform", 5.0),
+];
+
+/// access tag values that close a way to the traffic they apply to
+pub const ACCESS_DENIED_VALUES: [&str; 4] = ["no", "private", "agricultural", "forestry"];
+
+/// highway classes that are oneway unless tagged otherwise
+pub const IMPLIED_ONEWAY_TYPES: [&str; 2] = ["motorway", "motorway_link"];
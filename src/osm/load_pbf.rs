@@ -0,0 +1,580 @@
+use flate2::read::ZlibDecoder;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read};
+
+use crate::network::{Network, Node};
+use crate::osm::constants::{LATITUDE_RANGE, LONGITUDE_RANGE};
+use crate::osm::loader::{ElementHandler, NetworkLoader, RelationMember, WayNodeScan};
+use crate::osm::{LoadError, LoadMode, LoadSummary};
+use crate::osm::profile::{Profile, Tags};
+
+/// the features a file may require, anything else can't be read
+const SUPPORTED_FEATURES: [&str; 2] = ["OsmSchema-V0.6", "DenseNodes"];
+
+/// blobs are limited to 32MB uncompressed by the format
+const MAX_BLOB_SIZE: usize = 32 * 1024 * 1024;
+const MAX_BLOB_HEADER_SIZE: usize = 64 * 1024;
+
+pub fn load_network_from_file(file_path: &str, profile: &dyn Profile) -> Result<Network, LoadError> {
+    load_network_with_summary(file_path, profile, LoadMode::Strict).map(|(network, _)| network)
+}
+
+/// load a network reading the file twice, first for the nodes the accepted ways use and
+/// then for only those nodes, the ways and the relations. Corrupt data always fails, the
+/// mode only decides what happens to missing node references
+pub fn load_network_with_summary(
+    file_path: &str,
+    profile: &dyn Profile,
+    mode: LoadMode,
+) -> Result<(Network, LoadSummary), LoadError> {
+    let mut scan = WayNodeScan::new(profile);
+    read_elements(BufReader::new(File::open(file_path)?), &mut scan)?;
+    let mut loader = NetworkLoader::new(profile, scan.finish());
+    read_elements(BufReader::new(File::open(file_path)?), &mut loader)?;
+    loader.finish(mode)
+}
+
+pub fn load_network_from_bytes(pbf: &[u8], profile: &dyn Profile) -> Result<Network, LoadError> {
+    let mut scan = WayNodeScan::new(profile);
+    read_elements(pbf, &mut scan)?;
+    let mut loader = NetworkLoader::new(profile, scan.finish());
+    read_elements(pbf, &mut loader)?;
+    loader.finish(LoadMode::Strict).map(|(network, _)| network)
+}
+
+fn read_elements<R: Read>(mut reader: R, handler: &mut dyn ElementHandler) -> Result<(), LoadError> {
+    debug!("enter read_elements");
+
+    while let Some((blob_type, data)) = read_blob(&mut reader)? {
+        match blob_type.as_str() {
+            "OSMHeader" => check_header(&data)?,
+            "OSMData" => read_primitive_block(&data, handler)?,
+            other => debug!("skipping blob of type {}", other),
+        }
+    }
+    Ok(())
+}
+
+/// the type and uncompressed contents of a blob
+type Blob = (String, Vec<u8>);
+
+/// the next blob, None at the end of the file. The file may only end before the size of a
+/// blob, not part way through it
+fn read_blob<R: Read>(reader: &mut R) -> Result<Option<Blob>, LoadError> {
+    let mut size = [0u8; 4];
+    let mut filled = 0;
+    while filled < size.len() {
+        match reader.read(&mut size[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => return Err(invalid_data("file ends inside a blob size")),
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+            Err(e) => return Err(LoadError::Io(e)),
+        }
+    }
+    let header_size = u32::from_be_bytes(size) as usize;
+    if header_size > MAX_BLOB_HEADER_SIZE {
+        return Err(invalid_data("blob header too large"));
+    }
+    let header = read_bytes(reader, header_size)?;
+
+    let mut blob_type = None;
+    let mut data_size = None;
+    let mut fields = Message::new(&header);
+    while let Some((field, value)) = fields.next_field()? {
+        match (field, value) {
+            (1, Value::Bytes(b)) => {
+                blob_type = Some(String::from_utf8(b.to_vec()).map_err(|_| invalid_data("blob type isn't utf-8"))?)
+            }
+            (3, Value::Varint(v)) => data_size = Some(v as usize),
+            _ => (),
+        }
+    }
+    let (blob_type, data_size) = match (blob_type, data_size) {
+        (Some(t), Some(s)) if s <= MAX_BLOB_SIZE => (t, s),
+        _ => return Err(invalid_data("invalid blob header")),
+    };
+    let blob = read_bytes(reader, data_size)?;
+    Ok(Some((blob_type, decompress_blob(&blob)?)))
+}
+
+fn read_bytes<R: Read>(reader: &mut R, size: usize) -> Result<Vec<u8>, LoadError> {
+    let mut bytes = vec![0u8; size];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// the uncompressed data, which must be the raw_size the blob gives
+fn decompress_blob(blob: &[u8]) -> Result<Vec<u8>, LoadError> {
+    let mut raw_size = None;
+    let mut zlib_data = None;
+    let mut fields = Message::new(blob);
+    while let Some((field, value)) = fields.next_field()? {
+        match (field, value) {
+            (1, Value::Bytes(raw)) => return Ok(raw.to_vec()),
+            (2, Value::Varint(size)) => raw_size = Some(size as usize),
+            (3, Value::Bytes(zlib)) => zlib_data = Some(zlib),
+            (4..=7, _) => return Err(invalid_data("unsupported blob compression")),
+            _ => (),
+        }
+    }
+    let zlib = zlib_data.ok_or_else(|| invalid_data("blob without data"))?;
+    let size = match raw_size {
+        Some(size) if size <= MAX_BLOB_SIZE => size,
+        Some(_) => return Err(invalid_data("blob too large")),
+        None => return Err(invalid_data("compressed blob without raw_size")),
+    };
+    // one byte more than expected is enough to tell the data is too long
+    let mut data = Vec::with_capacity(size);
+    ZlibDecoder::new(zlib).take(size as u64 + 1).read_to_end(&mut data)?;
+    if data.len() != size {
+        return Err(invalid_data("blob data isn't its raw_size"));
+    }
+    Ok(data)
+}
+
+fn check_header(data: &[u8]) -> Result<(), LoadError> {
+    let mut fields = Message::new(data);
+    while let Some((field, value)) = fields.next_field()? {
+        if let (4, Value::Bytes(feature)) = (field, value) {
+            let feature = String::from_utf8_lossy(feature);
+            if !SUPPORTED_FEATURES.contains(&feature.as_ref()) {
+                return Err(invalid_data(&format!("unsupported required feature {}", feature)));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// the string table and coordinate encoding shared by the groups of a block
+struct Block<'a> {
+    strings: Vec<&'a [u8]>,
+    granularity: i64,
+    lat_offset: i64,
+    lon_offset: i64,
+}
+
+impl<'a> Block<'a> {
+    fn string(&self, index: u64) -> Result<String, LoadError> {
+        let bytes = self
+            .strings
+            .get(index as usize)
+            .ok_or_else(|| invalid_data("string index out of range"))?;
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    fn tags(&self, keys: &[u64], vals: &[u64]) -> Result<Tags, LoadError> {
+        keys.iter()
+            .zip(vals)
+            .map(|(&k, &v)| Ok((self.string(k)?, self.string(v)?)))
+            .collect()
+    }
+
+    /// the same coordinate checks as the XML loader, on top of the arithmetic overflowing
+    fn node(&self, id: i64, lat: i64, lon: i64) -> Result<Node, LoadError> {
+        let degrees = |offset: i64, value: i64| {
+            self.granularity
+                .checked_mul(value)
+                .and_then(|nanodegrees| nanodegrees.checked_add(offset))
+                .map(|nanodegrees| 1e-9 * nanodegrees as f64)
+        };
+        match (degrees(self.lat_offset, lat), degrees(self.lon_offset, lon)) {
+            (Some(lat), Some(long)) if LATITUDE_RANGE.contains(&lat) && LONGITUDE_RANGE.contains(&long) => {
+                Ok(Node::new(id as u64, lat, long))
+            }
+            _ => Err(invalid_data(&format!("node {} has coordinates out of range", id))),
+        }
+    }
+}
+
+fn read_primitive_block(data: &[u8], handler: &mut dyn ElementHandler) -> Result<(), LoadError> {
+    let mut block = Block {
+        strings: vec![],
+        granularity: 100,
+        lat_offset: 0,
+        lon_offset: 0,
+    };
+    let mut groups = vec![];
+    let mut fields = Message::new(data);
+    while let Some((field, value)) = fields.next_field()? {
+        match (field, value) {
+            (1, Value::Bytes(table)) => {
+                let mut strings = Message::new(table);
+                while let Some((field, value)) = strings.next_field()? {
+                    if let (1, Value::Bytes(s)) = (field, value) {
+                        block.strings.push(s);
+                    }
+                }
+            }
+            (2, Value::Bytes(group)) => groups.push(group),
+            (17, Value::Varint(v)) => block.granularity = v as i64,
+            (19, Value::Varint(v)) => block.lat_offset = v as i64,
+            (20, Value::Varint(v)) => block.lon_offset = v as i64,
+            _ => (),
+        }
+    }
+
+    // the string table can follow the groups
+    for group in groups {
+        let mut fields = Message::new(group);
+        while let Some((field, value)) = fields.next_field()? {
+            match (field, value) {
+                (1, Value::Bytes(node)) => read_node(node, &block, handler)?,
+                (2, Value::Bytes(dense)) => read_dense_nodes(dense, &block, handler)?,
+                (3, Value::Bytes(way)) => read_way(way, &block, handler)?,
+                (4, Value::Bytes(relation)) => read_relation(relation, &block, handler)?,
+                _ => (),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_node(data: &[u8], block: &Block, handler: &mut dyn ElementHandler) -> Result<(), LoadError> {
+    let (mut id, mut lat, mut lon) = (0, 0, 0);
+    let (mut keys, mut vals) = (vec![], vec![]);
+    let mut fields = Message::new(data);
+    while let Some((field, value)) = fields.next_field()? {
+        match field {
+            1 => id = zigzag(value.varint()?),
+            2 => value.append_varints(&mut keys)?,
+            3 => value.append_varints(&mut vals)?,
+            8 => lat = zigzag(value.varint()?),
+            9 => lon = zigzag(value.varint()?),
+            _ => (),
+        }
+    }
+    handler.add_node(block.node(id, lat, lon)?, &block.tags(&keys, &vals)?);
+    Ok(())
+}
+
+fn read_dense_nodes(data: &[u8], block: &Block, handler: &mut dyn ElementHandler) -> Result<(), LoadError> {
+    let (mut ids, mut lats, mut lons, mut keys_vals) = (vec![], vec![], vec![], vec![]);
+    let mut fields = Message::new(data);
+    while let Some((field, value)) = fields.next_field()? {
+        match field {
+            1 => value.append_varints(&mut ids)?,
+            8 => value.append_varints(&mut lats)?,
+            9 => value.append_varints(&mut lons)?,
+            10 => value.append_varints(&mut keys_vals)?,
+            _ => (),
+        }
+    }
+    if ids.len() != lats.len() || ids.len() != lons.len() {
+        return Err(invalid_data("dense nodes of different lengths"));
+    }
+
+    // keys and values for each node in turn, each node's ending with a 0
+    let mut keys_vals = keys_vals.into_iter();
+    let (ids, lats, lons) = (deltas(&ids)?, deltas(&lats)?, deltas(&lons)?);
+    for i in 0..ids.len() {
+        let mut tags = Tags::new();
+        while let Some(key) = keys_vals.next().filter(|&k| k != 0) {
+            let value = keys_vals.next().ok_or_else(|| invalid_data("dense node key without value"))?;
+            tags.insert(block.string(key)?, block.string(value)?);
+        }
+        handler.add_node(block.node(ids[i], lats[i], lons[i])?, &tags);
+    }
+    Ok(())
+}
+
+fn read_way(data: &[u8], block: &Block, handler: &mut dyn ElementHandler) -> Result<(), LoadError> {
+    let mut id = 0;
+    let (mut keys, mut vals, mut refs) = (vec![], vec![], vec![]);
+    let mut fields = Message::new(data);
+    while let Some((field, value)) = fields.next_field()? {
+        match field {
+            1 => id = value.varint()?,
+            2 => value.append_varints(&mut keys)?,
+            3 => value.append_varints(&mut vals)?,
+            8 => value.append_varints(&mut refs)?,
+            _ => (),
+        }
+    }
+    let way_nodes: Vec<u64> = deltas(&refs)?.into_iter().map(|r| r as u64).collect();
+    handler.add_way(id, &way_nodes, &block.tags(&keys, &vals)?);
+    Ok(())
+}
+
+fn read_relation(data: &[u8], block: &Block, handler: &mut dyn ElementHandler) -> Result<(), LoadError> {
+    let (mut keys, mut vals, mut roles, mut member_ids, mut types) = (vec![], vec![], vec![], vec![], vec![]);
+    let mut fields = Message::new(data);
+    while let Some((field, value)) = fields.next_field()? {
+        match field {
+            2 => value.append_varints(&mut keys)?,
+            3 => value.append_varints(&mut vals)?,
+            8 => value.append_varints(&mut roles)?,
+            9 => value.append_varints(&mut member_ids)?,
+            10 => value.append_varints(&mut types)?,
+            _ => (),
+        }
+    }
+    if roles.len() != member_ids.len() || roles.len() != types.len() {
+        return Err(invalid_data("relation members of different lengths"));
+    }
+
+    let mut members = vec![];
+    for ((id, role), kind) in deltas(&member_ids)?.into_iter().zip(roles).zip(types) {
+        let kind = match kind {
+            0 => "node",
+            1 => "way",
+            _ => "relation",
+        };
+        members.push(RelationMember {
+            kind: kind.to_string(),
+            id: id as u64,
+            role: block.string(role)?,
+        });
+    }
+    handler.add_relation(&block.tags(&keys, &vals)?, &members);
+    Ok(())
+}
+
+/// running totals of zigzag encoded differences
+fn deltas(values: &[u64]) -> Result<Vec<i64>, LoadError> {
+    let mut total = 0i64;
+    values
+        .iter()
+        .map(|&v| {
+            total = total
+                .checked_add(zigzag(v))
+                .ok_or_else(|| invalid_data("delta coded value overflows"))?;
+            Ok(total)
+        })
+        .collect()
+}
+
+fn zigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+fn invalid_data(message: &str) -> LoadError {
+    LoadError::InvalidPbf(message.to_string())
+}
+
+/// a field value of a protocol buffers message, fixed width values are skipped
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+impl<'a> Value<'a> {
+    fn varint(&self) -> Result<u64, LoadError> {
+        match self {
+            Value::Varint(v) => Ok(*v),
+            _ => Err(invalid_data("expected a varint")),
+        }
+    }
+
+    /// repeated integers may be packed into one field or be one field each
+    fn append_varints(&self, values: &mut Vec<u64>) -> Result<(), LoadError> {
+        match self {
+            Value::Varint(v) => values.push(*v),
+            Value::Bytes(packed) => {
+                let mut message = Message::new(packed);
+                while !message.is_empty() {
+                    values.push(message.read_varint()?);
+                }
+            }
+            Value::Fixed => return Err(invalid_data("expected varints")),
+        }
+        Ok(())
+    }
+}
+
+/// reads the fields of a protocol buffers message in order
+struct Message<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Message<'a> {
+    fn new(data: &'a [u8]) -> Message<'a> {
+        Message { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn next_field(&mut self) -> Result<Option<(u64, Value<'a>)>, LoadError> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        let key = self.read_varint()?;
+        let value = match key & 7 {
+            0 => Value::Varint(self.read_varint()?),
+            1 => {
+                self.take(8)?;
+                Value::Fixed
+            }
+            2 => {
+                let length = self.read_varint()? as usize;
+                Value::Bytes(self.take(length)?)
+            }
+            5 => {
+                self.take(4)?;
+                Value::Fixed
+            }
+            _ => return Err(invalid_data("unsupported wire type")),
+        };
+        Ok(Some((key >> 3, value)))
+    }
+
+    fn read_varint(&mut self) -> Result<u64, LoadError> {
+        let mut value = 0u64;
+        for (i, &byte) in self.data.iter().enumerate().take(10) {
+            value |= u64::from(byte & 0x7f) << (7 * i);
+            if byte & 0x80 == 0 {
+                self.data = &self.data[i + 1..];
+                return Ok(value);
+            }
+        }
+        Err(invalid_data("truncated varint"))
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], LoadError> {
+        if length > self.data.len() {
+            return Err(invalid_data("truncated message"));
+        }
+        let (taken, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(taken)
+    }
+}
+
+#[cfg(test)]
+mod load_pbf_test {
+    use super::*;
+    use crate::osm::load_xml;
+    use crate::osm::profile::{self, Car};
+
+    #[test]
+    fn protobuf_fields() {
+        // field 1 varint 150, field 2 the string "ab", field 3 packed 1 and 300
+        let data = [0x08, 0x96, 0x01, 0x12, 0x02, b'a', b'b', 0x1a, 0x03, 0x01, 0xac, 0x02];
+        let mut message = Message::new(&data);
+        let (field, value) = message.next_field().unwrap().unwrap();
+        assert_eq!((1, 150), (field, value.varint().unwrap()));
+        match message.next_field().unwrap().unwrap() {
+            (2, Value::Bytes(b)) => assert_eq!(b"ab", b),
+            _ => panic!("expected bytes"),
+        }
+        let mut packed = vec![];
+        message.next_field().unwrap().unwrap().1.append_varints(&mut packed).unwrap();
+        assert_eq!(vec![1, 300], packed);
+        assert!(message.next_field().unwrap().is_none());
+
+        assert_eq!(vec![-1, 1, 2], deltas(&[1, 4, 2]).unwrap());
+        // i64::MAX then 1 more
+        assert!(deltas(&[u64::MAX - 1, 2]).is_err());
+        assert!(Message::new(&[0x80]).read_varint().is_err());
+    }
+
+    #[test]
+    fn pbf_matches_xml() {
+        for name in &["rutland-tiny", "turn-restriction"] {
+            for profile_name in &["car", "bike", "foot"] {
+                let profile = profile::by_name(profile_name).unwrap();
+                let xml = format!("data/{}.osm.xml", name);
+                let pbf = format!("data/{}.osm.pbf", name);
+                let from_xml = load_xml::load_network_from_file(&xml, profile.as_ref()).unwrap();
+                let from_pbf = load_network_from_file(&pbf, profile.as_ref()).unwrap();
+
+                assert_eq!(from_xml.node_count(), from_pbf.node_count(), "{} {}", name, profile_name);
+                assert_eq!(from_xml.arc_count(), from_pbf.arc_count(), "{} {}", name, profile_name);
+                assert_eq!(from_xml.way_count(), from_pbf.way_count(), "{} {}", name, profile_name);
+                assert_eq!(from_xml.turn_restriction_count(), from_pbf.turn_restriction_count());
+                assert_eq!(from_xml.traffic_signal_count(), from_pbf.traffic_signal_count());
+            }
+        }
+    }
+
+    // the other fixtures are written by data/xml-to-pbf.py, these by osmium
+    #[test]
+    #[ignore = "needs the osmium fixtures from data/make-pbf-fixtures.sh"]
+    fn osmium_pbf_matches_xml() {
+        for name in &["rutland-tiny", "turn-restriction"] {
+            for profile_name in &["car", "bike", "foot"] {
+                let profile = profile::by_name(profile_name).unwrap();
+                let from_xml = load_xml::load_network_from_file(&format!("data/{}.osm.xml", name), profile.as_ref()).unwrap();
+                let pbf = format!("data/{}.osmium.osm.pbf", name);
+                let from_pbf = load_network_from_file(&pbf, profile.as_ref()).unwrap();
+
+                assert_eq!(from_xml.node_count(), from_pbf.node_count(), "{} {}", name, profile_name);
+                assert_eq!(from_xml.arc_count(), from_pbf.arc_count(), "{} {}", name, profile_name);
+                assert_eq!(from_xml.way_count(), from_pbf.way_count(), "{} {}", name, profile_name);
+                assert_eq!(from_xml.turn_restriction_count(), from_pbf.turn_restriction_count());
+                assert_eq!(from_xml.traffic_signal_count(), from_pbf.traffic_signal_count());
+            }
+        }
+    }
+
+    #[test]
+    fn routes_match_xml() {
+        let from_xml = load_xml::load_network_from_file("data/turn-restriction.osm.xml", &Car).unwrap();
+        let from_pbf = load_network_from_file("data/turn-restriction.osm.pbf", &Car).unwrap();
+        for &(from, to) in &[(1, 4), (4, 1), (1, 3)] {
+            let xml_route = crate::dijkstra::run_turn_aware_dijkstra(from, to, &from_xml, None).unwrap();
+            let pbf_route = crate::dijkstra::run_turn_aware_dijkstra(from, to, &from_pbf, None).unwrap();
+            assert_eq!(xml_route.cost, pbf_route.cost);
+            assert_eq!(xml_route.report_traversed_ways(), pbf_route.report_traversed_ways());
+        }
+    }
+
+    #[test]
+    fn coordinates_must_be_in_range() {
+        let block = Block {
+            strings: vec![],
+            granularity: 100,
+            lat_offset: 0,
+            lon_offset: 0,
+        };
+        let node = block.node(1, 526_000_000, -7_000_000).unwrap();
+        assert_eq!((526_000_000, -7_000_000), (node.latitude, node.longitude));
+        assert!(block.node(1, 900_000_001, 0).is_err());
+        assert!(block.node(1, 0, -1_800_000_001).is_err());
+        assert!(block.node(1, i64::MAX / 10, 0).is_err());
+        let offset = Block { lat_offset: i64::MAX, ..block };
+        assert!(offset.node(1, 1, 0).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let pbf = std::fs::read("data/rutland-tiny.osm.pbf").unwrap();
+        assert!(load_network_from_bytes(&pbf[..pbf.len() - 10], &Car).is_err());
+        // the start of another blob's size
+        for extra in 1..=3 {
+            let mut bytes = pbf.clone();
+            bytes.extend(std::iter::repeat_n(0, extra));
+            assert!(load_network_from_bytes(&bytes, &Car).is_err(), "{} extra bytes", extra);
+        }
+    }
+
+    #[test]
+    fn blob_sizes_must_match() {
+        use flate2::write::ZlibEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+        encoder.write_all(b"hello").unwrap();
+        let zlib = encoder.finish().unwrap();
+        // field 2 raw_size and field 3 zlib_data
+        let blob = |raw_size: &[u8]| {
+            let mut blob = vec![0x10];
+            blob.extend_from_slice(raw_size);
+            blob.push(0x1a);
+            blob.push(zlib.len() as u8);
+            blob.extend_from_slice(&zlib);
+            blob
+        };
+        assert_eq!(b"hello".to_vec(), decompress_blob(&blob(&[5])).unwrap());
+        assert!(decompress_blob(&blob(&[4])).is_err());
+        assert!(decompress_blob(&blob(&[6])).is_err());
+        // 64MB
+        assert!(decompress_blob(&blob(&[0x80, 0x80, 0x80, 0x20])).is_err());
+        let without_raw_size = [&[0x1a, zlib.len() as u8][..], &zlib].concat();
+        assert!(decompress_blob(&without_raw_size).is_err());
+    }
+}
@@ -1,310 +1,316 @@
-use failure;
-use failure::Fail;
-use quick_xml::events::attributes::Attribute;
 use quick_xml::events::{BytesStart, Event};
 use quick_xml::Reader;
-use std::error;
 use std::io::BufRead;
-use std::io::ErrorKind;
+use std::str::FromStr;
 
 use crate::network;
-use crate::network::{Arc, Network, NetworkBuilder, Node, OSMNodeId, OSMWayId, WayInfo};
-use crate::utils;
+use crate::network::{Network, Node, OSMNodeId, OSMWayId};
 
-use crate::osm::constants;
+use crate::osm::constants::{LATITUDE_RANGE, LONGITUDE_RANGE};
+use crate::osm::loader::{ElementHandler, NetworkLoader, RelationMember, WayNodeScan};
+use crate::osm::profile::{Profile, Tags};
+use crate::osm::{skip_element, LoadError, LoadMode, LoadSummary};
 
-pub fn load_network_from_file(file_path: &str) -> Result<Network, Box<dyn error::Error>> {
-    let reader = Reader::from_file(&file_path).map_err(|e| e.compat())?;
-    load_network(reader).ok_or(Box::new(std::io::Error::new(
-        ErrorKind::Other,
-        "failed to load network",
-    )))
+pub fn load_network_from_file(file_path: &str, profile: &dyn Profile) -> Result<Network, LoadError> {
+    load_network_with_summary(file_path, profile, LoadMode::Strict).map(|(network, _)| network)
 }
 
-#[cfg(test)]
-pub fn load_network_from_string(xml_string: &str) -> Option<Network> {
-    let reader = Reader::from_str(xml_string);
-    load_network(reader)
+/// load a network reading the file twice, first for the nodes the accepted ways use and
+/// then for only those nodes, the ways and the relations
+pub fn load_network_with_summary(
+    file_path: &str,
+    profile: &dyn Profile,
+    mode: LoadMode,
+) -> Result<(Network, LoadSummary), LoadError> {
+    load_network(|| Reader::from_file(file_path).map_err(|e| xml_error(e, 0)), profile, mode)
+}
+
+pub fn load_network_from_string(xml_string: &str, profile: &dyn Profile) -> Result<Network, LoadError> {
+    load_network_from_string_with_summary(xml_string, profile, LoadMode::Strict).map(|(network, _)| network)
+}
+
+pub fn load_network_from_string_with_summary(
+    xml_string: &str,
+    profile: &dyn Profile,
+    mode: LoadMode,
+) -> Result<(Network, LoadSummary), LoadError> {
+    load_network(|| Ok(Reader::from_str(xml_string)), profile, mode)
+}
+
+fn load_network<B: BufRead, F: Fn() -> Result<Reader<B>, LoadError>>(
+    open: F,
+    profile: &dyn Profile,
+    mode: LoadMode,
+) -> Result<(Network, LoadSummary), LoadError> {
+    let mut scan = WayNodeScan::new(profile);
+    read_elements(open()?, &mut scan, mode)?;
+    let mut loader = NetworkLoader::new(profile, scan.finish());
+    let skipped = read_elements(open()?, &mut loader, mode)?;
+    let (network, mut summary) = loader.finish(mode)?;
+    summary.skipped_elements = skipped;
+    Ok((network, summary))
 }
 
-fn load_network<B: BufRead>(mut reader: Reader<B>) -> Option<Network> {
+/// pass every element to the handler, returning how many bad elements a lenient read skipped.
+/// Syntax errors always fail, the rest of the file can't be trusted after one
+fn read_elements<B: BufRead>(
+    mut reader: Reader<B>,
+    handler: &mut dyn ElementHandler,
+    mode: LoadMode,
+) -> Result<usize, LoadError> {
     let mut buf = Vec::new();
+    let mut skipped = 0;
+
     let mut in_way = false;
-    let mut way_is_highway = false;
-    let mut way_is_oneway = false;
-    let mut way_name = None;
     let mut way_id = 0;
-
     let mut way_nodes = vec![];
 
-    let mut graph = NetworkBuilder::new();
+    // a node with child tags is added once all its tags are read
+    let mut in_node = false;
+    let mut tagged_node = None;
+
+    let mut in_relation = false;
+    let mut relation_members = vec![];
+
+    // the tags of the current node, way or relation
+    let mut tags = Tags::new();
+    // the first problem with the current node, way or relation
+    let mut bad_element = None;
 
-    println!("enter load_network");
+    debug!("enter read_elements");
 
     loop {
+        let position = reader.buffer_position();
         match reader.read_event(&mut buf) {
             Ok(Event::Start(ref e)) => match e.name() {
                 b"way" => {
                     in_way = true;
-                    way_id = get_attribute(e, "id").unwrap().parse::<u64>().unwrap();
+                    match parse_attribute::<OSMWayId>(e, "id", position) {
+                        Ok(id) => way_id = id,
+                        Err(error) => bad_element = Some(error),
                     }
+                }
+                b"relation" => in_relation = true,
                 b"node" => {
-                    let n = extract_node(e).unwrap();
-                    graph.insert_node(n);
+                    in_node = true;
+                    match extract_node(e, position) {
+                        Ok(node) => tagged_node = Some(node),
+                        Err(error) => bad_element = Some(error),
+                    }
                 }
                 _ => (),
             },
-            Ok(Event::Empty(ref e)) => {
-                match e.name() {
-                    b"nd" if in_way => {
-                        // println!("<nd> found in way");
-                        // TODO attributes() returns an iterator. need to find the "ref" attribute
-                        let node_ref = e.attributes().next().unwrap();
-                        let val: Option<OSMNodeId> = match &node_ref {
-                            Ok(Attribute {
-                                key: b"ref",
-                                value: v,
-                            }) => {
-                                let s = String::from_utf8(v.to_vec()).unwrap();
-                                let id: OSMNodeId = s.parse().unwrap();
-                                Some(id)
-                            }
-                            _ => None,
-                        };
-                        match val {
-                            Some(node_id) => way_nodes.push(node_id),
-                            _ => (),
-                        };
+            Ok(Event::Empty(ref e)) => match e.name() {
+                b"nd" if in_way => match parse_attribute::<OSMNodeId>(e, "ref", position) {
+                    Ok(node_id) => way_nodes.push(node_id),
+                    Err(error) => {
+                        bad_element.get_or_insert(error);
                     }
-                    b"tag" if in_way => {
-                        way_is_highway |= is_road(e);
-                        way_is_oneway |= is_oneway(e);
-                        match get_name(e) {
-                            Some(name) => way_name = Some(name),
-                            _ => (),
+                },
+                b"tag" if in_way || in_relation || in_node => {
+                    match (get_attribute(e, "k", position), get_attribute(e, "v", position)) {
+                        (Ok(Some(key)), Ok(Some(value))) => {
+                            tags.insert(key, value);
+                        }
+                        (Err(error), _) | (_, Err(error)) => {
+                            bad_element.get_or_insert(error);
                         }
+                        _ => (),
                     }
-                    b"node" => {
-                        let n = extract_node(e).unwrap();
-                        graph.insert_node(n);
+                }
+                b"member" if in_relation => match extract_member(e, position) {
+                    Ok(Some(member)) => relation_members.push(member),
+                    Ok(None) => (),
+                    Err(error) => {
+                        bad_element.get_or_insert(error);
                     }
-                    _ => (),
+                },
+                b"node" => match extract_node(e, position) {
+                    Ok(node) => handler.add_node(node, &Tags::new()),
+                    Err(error) => skip_element(error, mode, &mut skipped)?,
+                },
+                _ => (),
+            },
+            Ok(Event::End(ref e)) if e.name() == b"way" => {
+                match bad_element.take() {
+                    Some(error) => skip_element(error, mode, &mut skipped)?,
+                    None => handler.add_way(way_id, &way_nodes, &tags),
                 }
+                in_way = false;
+                tags.clear();
+                way_nodes.clear();
             }
-            Ok(Event::End(ref e)) => {
-                match e.name() {
-                    b"way" => {
-                        // TODO create arcs here including forward and reverse
-                        if way_is_highway {
-                            let arcs = create_arcs(
-                                &graph,
-                                &way_nodes,
-                                way_is_oneway,
-                                way_id,
-                            );
-                            for (k, v) in arcs.iter() {
-                                graph.insert_arc(*k, v.to_owned());
-                            }
-                            graph.insert_way_info(WayInfo { id: way_id, name: way_name })
-                        }
-                        in_way = false;
-                        way_is_highway = false;
-                        way_is_oneway = false;
-                        way_name = None;
-                        way_nodes.clear();
-                    }
-                    _ => (),
+            Ok(Event::End(ref e)) if e.name() == b"node" => {
+                match (bad_element.take(), tagged_node.take()) {
+                    (Some(error), _) => skip_element(error, mode, &mut skipped)?,
+                    (None, Some(node)) => handler.add_node(node, &tags),
+                    (None, None) => (),
+                }
+                in_node = false;
+                tags.clear();
+            }
+            Ok(Event::End(ref e)) if e.name() == b"relation" => {
+                match bad_element.take() {
+                    Some(error) => skip_element(error, mode, &mut skipped)?,
+                    None => handler.add_relation(&tags, &relation_members),
                 }
+                in_relation = false;
+                tags.clear();
+                relation_members.clear();
             }
             Ok(Event::Eof) => break,
+            Err(e) => return Err(xml_error(e, reader.buffer_position())),
             _ => (),
         }
         buf.clear();
     }
-    println!("about to call build_network");
-
-    println!(
-        "read network with {} outbound arcs ",
-        &graph.adjacent_arcs.len()
-    );
+    Ok(skipped)
+}
 
-    let network = graph.build_network();
-    // if network.is_some() {
-    //     println!("read network with {} nodes", &network.unwrap().node_count());
-    // }
-    network
+fn xml_error(error: quick_xml::Error, position: usize) -> LoadError {
+    match error {
+        quick_xml::Error::Io(e) => LoadError::Io(e),
+        other => LoadError::XmlSyntax {
+            position,
+            message: other.to_string(),
+        },
+    }
 }
 
-fn extract_node(tag: &BytesStart) -> Result<Node, Box<dyn error::Error>> {
-    let mut id = 0;
-    let mut lat = 0.0;
-    let mut long = 0.0;
-    for tag in tag.attributes() {
-        match tag.map_err(|e| e.compat())? {
-            Attribute {
-                key: b"id",
-                value: v,
-            } => id = utils::bytes_to_string(v)?.parse::<OSMNodeId>()?,
-            Attribute {
-                key: b"lat",
-                value: v,
-            } => lat = utils::bytes_to_string(v)?.parse::<f64>()?,
-            Attribute {
-                key: b"lon",
-                value: v,
-            } => long = utils::bytes_to_string(v)?.parse::<f64>()?,
-            _ => (),
-        }
+fn extract_node(tag: &BytesStart, position: usize) -> Result<Node, LoadError> {
+    let id = parse_attribute::<OSMNodeId>(tag, "id", position)?;
+    let lat = parse_attribute::<f64>(tag, "lat", position)?;
+    let long = parse_attribute::<f64>(tag, "lon", position)?;
+    if !LATITUDE_RANGE.contains(&lat) {
+        return Err(bad_attribute(tag, "lat", &lat.to_string(), position));
     }
-    // if id == 0 || lat == 0.0 || long == 0.0 {
-    if id == 0 {
-        println!(
-            "problem extracting node id {}, lat {}, long {}",
-            id, lat, long
-        );
-        Err(Box::new(std::io::Error::new(
-            ErrorKind::Other,
-            "invalid node",
-        )))
-    } else {
-        Ok(Node {
-            id: id,
-            latitude: network::degrees_to_i32(lat),
-            longitude: network::degrees_to_i32(long),
-        })
+    if !LONGITUDE_RANGE.contains(&long) {
+        return Err(bad_attribute(tag, "lon", &long.to_string(), position));
     }
+    Ok(Node {
+        id,
+        latitude: network::degrees_to_i32(lat),
+        longitude: network::degrees_to_i32(long),
+    })
 }
 
-fn create_arcs(
-    partial_network: &NetworkBuilder,
-    way_nodes: &Vec<OSMNodeId>,
-    is_oneway: bool,
-    way_id: OSMWayId,
-) -> Vec<(OSMNodeId, Arc<OSMNodeId>)> {
-    let mut way_iter = way_nodes.iter().peekable();
-
-    let mut arcs = vec![];
-    while let Some(node) = way_iter.next() {
-        let maybe_next = way_iter.peek();
-        match maybe_next {
-            Some(next) => {
-                let from = partial_network.get_node(node);
-                let to = partial_network.get_node(next);
-                match (from, to) {
-                    (Some(f), Some(t)) => {
-                        let dist = calculate_distance(f, t);
-                        let cost = calculate_cost(dist);
+// a member without a type, ref or role can't be part of a restriction, it's ignored
+fn extract_member(tag: &BytesStart, position: usize) -> Result<Option<RelationMember>, LoadError> {
+    let kind = get_attribute(tag, "type", position)?;
+    let role = get_attribute(tag, "role", position)?;
+    let id = match get_attribute(tag, "ref", position)? {
+        Some(value) => Some(
+            value
+                .parse::<u64>()
+                .map_err(|_| bad_attribute(tag, "ref", &value, position))?,
+        ),
+        None => None,
+    };
+    Ok(match (kind, id, role) {
+        (Some(kind), Some(id), Some(role)) => Some(RelationMember { kind, id, role }),
+        _ => None,
+    })
+}
 
-                        arcs.push((
-                            f.id,
-                            Arc {
-                                head_node: t.id,
-                                cost: cost,
-                                distance: dist,
-                                part_of_way: way_id,
-                            },
-                        ));
+/// a required attribute parsed to T
+fn parse_attribute<T: FromStr>(tag: &BytesStart, key: &str, position: usize) -> Result<T, LoadError> {
+    let value = get_attribute(tag, key, position)?.unwrap_or_default();
+    value.parse::<T>().map_err(|_| bad_attribute(tag, key, &value, position))
+}
 
-                        if !is_oneway {
-                            arcs.push((
-                                t.id,
-                                Arc {
-                                    head_node: f.id,
-                                    cost: cost,
-                                    distance: dist,
-                                    part_of_way: way_id,
-                                },
-                            ));
-                        }
-                    }
-                    _ => (),
+fn get_attribute(tag: &BytesStart, key: &str, position: usize) -> Result<Option<String>, LoadError> {
+    for attribute in tag.attributes() {
+        let attribute = attribute.map_err(|e| xml_error(e, position))?;
+        if attribute.key == key.as_bytes() {
+            let value = attribute
+                .unescaped_value()
+                .ok()
+                .and_then(|value| String::from_utf8(value.to_vec()).ok());
+            return match value {
+                Some(value) => Ok(Some(value)),
+                None => {
+                    let raw = String::from_utf8_lossy(&attribute.value).into_owned();
+                    Err(bad_attribute(tag, key, &raw, position))
                 }
-            }
-            None => (),
+            };
         }
     }
-    arcs
-}
-
-fn is_road(tag: &BytesStart) -> bool {
-    let highway_val = osm_tag_value(tag, "highway");
-    match highway_val {
-        Some(v) => constants::HIGHWAY_ROAD_TYPES.contains(&v.as_str()),
-        None => false,
-    }
+    Ok(None)
 }
 
-fn is_oneway(tag: &BytesStart) -> bool {
-    let one_way_val = osm_tag_value(tag, "oneway");
-    match one_way_val {
-        Some(ref val) if val == "yes" => true,
-        _ => false,
+fn bad_attribute(tag: &BytesStart, key: &str, value: &str, position: usize) -> LoadError {
+    LoadError::BadAttribute {
+        element: String::from_utf8_lossy(tag.name()).into_owned(),
+        attribute: key.to_string(),
+        value: value.to_string(),
+        position,
     }
 }
 
-fn get_name(tag: &BytesStart) -> Option<String> {
-    osm_tag_value(tag, "name")
-}
-
-fn osm_tag_value(tag: &BytesStart, key_to_match: &str) -> Option<String> {
-    let osm_key = "k".as_bytes();
-    let osm_value = "v".as_bytes();
+#[cfg(test)]
+mod load_xml_test {
+    use super::*;
+    use crate::osm::profile::Car;
 
-    let bkey_to_match = key_to_match.as_bytes();
-    let mut value = None;
+    // the second way has a bad node reference and the last node a bad latitude
+    const BAD_ELEMENTS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osm version="0.6">
+  <node id="1" lat="52.600" lon="-0.700"/>
+  <node id="2" lat="52.600" lon="-0.699"/>
+  <node id="3" lat="52.601" lon="-0.699"/>
+  <way id="10">
+    <nd ref="1"/>
+    <nd ref="2"/>
+    <tag k="highway" v="residential"/>
+  </way>
+  <way id="20">
+    <nd ref="2"/>
+    <nd ref="three"/>
+    <tag k="highway" v="residential"/>
+  </way>
+  <node id="4" lat="north" lon="-0.699"/>
+</osm>"#;
 
-    let mut found_key_to_match = false;
-    for attribute in tag.attributes() {
-        match attribute {
-            Ok(ref attr) if attr.key == osm_key => {
-                if attr.unescaped_value().unwrap() == bkey_to_match {
-                    found_key_to_match = true;
-                }
+    #[test]
+    fn strict_load_fails_on_bad_attribute() {
+        match load_network_from_string(BAD_ELEMENTS, &Car) {
+            Err(LoadError::BadAttribute {
+                element,
+                attribute,
+                value,
+                position,
+            }) => {
+                assert_eq!(("nd", "ref", "three"), (element.as_str(), attribute.as_str(), value.as_str()));
+                assert_eq!(BAD_ELEMENTS.find(r#"<nd ref="three"/>"#), Some(position));
             }
-            Ok(ref attr) if attr.key == osm_value => {
-                value = String::from_utf8(
-                    attr.unescaped_value()
-                        .unwrap()
-                        .iter()
-                        .map(|u| *u)
-                        .collect::<Vec<_>>(),
-                )
-                .ok();
-            }
-            _ => (),
+            other => panic!("expected a bad attribute, got {:?}", other.map(|n| n.node_count())),
         }
     }
-    if found_key_to_match {
-        value
-    } else {
-        None
+
+    #[test]
+    fn lenient_load_skips_bad_elements() {
+        let (network, summary) = load_network_from_string_with_summary(BAD_ELEMENTS, &Car, LoadMode::Lenient).unwrap();
+        assert_eq!(2, network.node_count());
+        assert_eq!(2, network.arc_count());
+        assert_eq!(2, summary.skipped_elements);
     }
-}
 
-fn get_attribute(tag: &BytesStart, key: &str) -> Option<String> {
-    for attribute in tag.attributes() {
-        match attribute {
-            Ok(ref attr) if attr.key == key.as_bytes() => {
-                let value = String::from_utf8(
-                    attr.unescaped_value()
-                        .unwrap()
-                        .iter()
-                        .map(|u| *u)
-                        .collect::<Vec<_>>(),
-                )
-                .ok();
-                return value
+    #[test]
+    fn syntax_errors_give_position() {
+        let xml = r#"<osm><node id="1" lat="52.6" lon="-0.7"></way></osm>"#;
+        for mode in &[LoadMode::Strict, LoadMode::Lenient] {
+            match load_network_from_string_with_summary(xml, &Car, *mode) {
+                Err(LoadError::XmlSyntax { position, .. }) => assert!(position > 0),
+                other => panic!("expected a syntax error, got {:?}", other.map(|(_, summary)| summary)),
             }
-            _ => (),
         }
-    };
-    None
-}
-
-fn calculate_distance(a: &Node, b: &Node) -> (u64) {
-    utils::haversine_distance_metres(a.lat_long_f64(), b.lat_long_f64())
-}
+    }
 
-fn calculate_cost(distance: u64) -> (u64) {
-    distance
+    #[test]
+    fn missing_file_is_io_error() {
+        match load_network_from_file("data/no-such-file.osm.xml", &Car) {
+            Err(LoadError::Io(_)) => (),
+            other => panic!("expected an io error, got {:?}", other.map(|n| n.node_count())),
+        }
+    }
 }
@@ -0,0 +1,327 @@
+use std::collections::HashSet;
+
+use crate::network::{
+    Arc, Network, NetworkBuilder, Node, OSMNodeId, OSMWayId, RestrictionKind, TurnRestriction, WayInfo,
+};
+use crate::osm::profile::{Access, Profile, Tags};
+use crate::osm::{LoadError, LoadMode, LoadSummary};
+use crate::utils;
+
+/// a member of an OSM relation, kind is "node", "way" or "relation"
+#[derive(Debug)]
+pub(crate) struct RelationMember {
+    pub kind: String,
+    pub id: u64,
+    pub role: String,
+}
+
+/// receives the elements of an OSM file in file order, whichever format it was read from
+pub(crate) trait ElementHandler {
+    fn add_node(&mut self, node: Node, tags: &Tags);
+    fn add_way(&mut self, way_id: OSMWayId, way_nodes: &[OSMNodeId], tags: &Tags);
+    fn add_relation(&mut self, tags: &Tags, members: &[RelationMember]);
+}
+
+/// the first pass over a file, collects the ids of the nodes on ways the profile accepts
+pub(crate) struct WayNodeScan<'p> {
+    profile: &'p dyn Profile,
+    referenced_nodes: HashSet<OSMNodeId>,
+}
+
+impl<'p> WayNodeScan<'p> {
+    pub fn new(profile: &'p dyn Profile) -> WayNodeScan<'p> {
+        WayNodeScan {
+            profile,
+            referenced_nodes: HashSet::new(),
+        }
+    }
+
+    pub fn finish(self) -> HashSet<OSMNodeId> {
+        info!("ways reference {} nodes", self.referenced_nodes.len());
+        self.referenced_nodes
+    }
+}
+
+impl<'p> ElementHandler for WayNodeScan<'p> {
+    fn add_node(&mut self, _node: Node, _tags: &Tags) {}
+
+    fn add_way(&mut self, _way_id: OSMWayId, way_nodes: &[OSMNodeId], tags: &Tags) {
+        if self.profile.accepts_way(tags) {
+            self.referenced_nodes.extend(way_nodes);
+        }
+    }
+
+    fn add_relation(&mut self, _tags: &Tags, _members: &[RelationMember]) {}
+}
+
+/// an accepted way waiting for its arcs to be created once every node has been read
+struct PendingWay {
+    id: OSMWayId,
+    nodes: Vec<OSMNodeId>,
+    access: Access,
+    speed_kmh: f64,
+}
+
+/// the second pass over a file, builds the network for a profile keeping only the nodes
+/// found by the first pass. Arcs are created when loading finishes so nodes can come
+/// before or after the ways that use them
+pub(crate) struct NetworkLoader<'p> {
+    profile: &'p dyn Profile,
+    referenced_nodes: HashSet<OSMNodeId>,
+    graph: NetworkBuilder,
+    pending_ways: Vec<PendingWay>,
+    summary: LoadSummary,
+}
+
+impl<'p> NetworkLoader<'p> {
+    pub fn new(profile: &'p dyn Profile, referenced_nodes: HashSet<OSMNodeId>) -> NetworkLoader<'p> {
+        let summary = LoadSummary {
+            referenced_nodes: referenced_nodes.len(),
+            ..LoadSummary::default()
+        };
+        NetworkLoader {
+            profile,
+            referenced_nodes,
+            graph: NetworkBuilder::new(),
+            pending_ways: vec![],
+            summary,
+        }
+    }
+
+    /// a strict load fails on the first missing node, a lenient one drops the segments
+    /// to missing nodes and counts them
+    pub fn finish(mut self, mode: LoadMode) -> Result<(Network, LoadSummary), LoadError> {
+        let mut missing_nodes = HashSet::new();
+        for way in std::mem::take(&mut self.pending_ways) {
+            if mode == LoadMode::Strict {
+                if let Some(&node) = way.nodes.iter().find(|id| self.graph.get_node(id).is_none()) {
+                    return Err(LoadError::MissingReference { way: way.id, node });
+                }
+            }
+            let missing_before = missing_nodes.len();
+            missing_nodes.extend(way.nodes.iter().filter(|id| self.graph.get_node(id).is_none()));
+            if missing_nodes.len() > missing_before {
+                self.summary.ways_with_missing_nodes += 1;
+            }
+
+            let arcs = create_arcs(&self.graph, &way.nodes, way.access, way.speed_kmh, self.profile, way.id);
+            for (k, v) in arcs {
+                self.graph.insert_arc(k, v);
+            }
+        }
+        if !missing_nodes.is_empty() {
+            let mut sample: Vec<&OSMNodeId> = missing_nodes.iter().collect();
+            sample.sort();
+            sample.truncate(10);
+            warn!("ways reference {} nodes missing from the file, including {:?}", missing_nodes.len(), sample);
+        }
+        self.summary.missing_nodes = missing_nodes.len();
+
+        info!(
+            "read network with {} nodes with outbound arcs",
+            &self.graph.adjacent_arcs.len()
+        );
+        info!("{}", self.summary);
+        let summary = self.summary;
+        self.graph
+            .build_network()
+            .map(|network| (network, summary))
+            .ok_or(LoadError::NoNetwork)
+    }
+}
+
+impl<'p> ElementHandler for NetworkLoader<'p> {
+    fn add_node(&mut self, node: Node, tags: &Tags) {
+        self.summary.nodes_read += 1;
+        if !self.referenced_nodes.contains(&node.id) {
+            return;
+        }
+        if tags.get("highway").map(String::as_str) == Some("traffic_signals") {
+            self.graph.insert_traffic_signal(node.id);
+        }
+        self.graph.insert_node(node);
+        self.summary.nodes_stored += 1;
+    }
+
+    fn add_way(&mut self, way_id: OSMWayId, way_nodes: &[OSMNodeId], tags: &Tags) {
+        if !self.profile.accepts_way(tags) {
+            return;
+        }
+        self.pending_ways.push(PendingWay {
+            id: way_id,
+            nodes: way_nodes.to_vec(),
+            access: self.profile.access(tags),
+            speed_kmh: self.profile.speed_kmh(tags),
+        });
+        let name = tags.get("name").cloned();
+        self.graph.insert_way_info(WayInfo { id: way_id, name })
+    }
+
+    fn add_relation(&mut self, tags: &Tags, members: &[RelationMember]) {
+        if !self.profile.obeys_restriction(tags) {
+            return;
+        }
+        match turn_restriction(tags, members) {
+            Some(restriction) => self.graph.insert_turn_restriction(restriction),
+            None => debug!("skipping relation with members {:?}", members),
+        }
+    }
+}
+
+/// a restriction relation with a from way, a via node and a to way. Restrictions via
+/// ways and relations that aren't restrictions give None
+fn turn_restriction(tags: &Tags, members: &[RelationMember]) -> Option<TurnRestriction> {
+    if tags.get("type").map(String::as_str) != Some("restriction") {
+        return None;
+    }
+    let restriction = tags.get("restriction")?;
+    let kind = if restriction.starts_with("no_") {
+        RestrictionKind::No
+    } else if restriction.starts_with("only_") {
+        RestrictionKind::Only
+    } else {
+        return None;
+    };
+
+    let member = |kind: &str, role: &str| {
+        let mut matching = members.iter().filter(|m| m.kind == kind && m.role == role);
+        match (matching.next(), matching.next()) {
+            (Some(m), None) => Some(m.id),
+            _ => None,
+        }
+    };
+    Some(TurnRestriction {
+        from_way: member("way", "from")?,
+        via: member("node", "via")?,
+        to_way: member("way", "to")?,
+        kind,
+    })
+}
+
+fn create_arcs(
+    partial_network: &NetworkBuilder,
+    way_nodes: &[OSMNodeId],
+    access: Access,
+    speed_kmh: f64,
+    profile: &dyn Profile,
+    way_id: OSMWayId,
+) -> Vec<(OSMNodeId, Arc<OSMNodeId>)> {
+    let mut way_iter = way_nodes.iter().peekable();
+
+    let mut arcs = vec![];
+    while let Some(node) = way_iter.next() {
+        if let Some(next) = way_iter.peek() {
+            let from = partial_network.get_node(node);
+            let to = partial_network.get_node(next);
+            if let (Some(f), Some(t)) = (from, to) {
+                let dist = calculate_distance(f, t);
+                let cost = profile.cost(dist, speed_kmh);
+
+                if access.forward {
+                    arcs.push((
+                        f.id,
+                        Arc {
+                            head_node: t.id,
+                            cost,
+                            distance: dist,
+                            part_of_way: way_id,
+                        },
+                    ));
+                }
+                if access.backward {
+                    arcs.push((
+                        t.id,
+                        Arc {
+                            head_node: f.id,
+                            cost,
+                            distance: dist,
+                            part_of_way: way_id,
+                        },
+                    ));
+                }
+            }
+        }
+    }
+    arcs
+}
+
+fn calculate_distance(a: &Node, b: &Node) -> u64 {
+    utils::haversine_distance_metres(a.lat_long_f64(), b.lat_long_f64())
+}
+
+#[cfg(test)]
+mod loader_test {
+    use super::*;
+    use crate::osm::profile::{Car, Foot};
+
+    fn tags(pairs: &[(&str, &str)]) -> Tags {
+        pairs.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    // a road from 1 to 2, a footpath from 2 to 3 and a building outline through 4, 5 and 6
+    fn feed(handler: &mut dyn ElementHandler) {
+        for id in 1..=6 {
+            handler.add_node(Node::new(id, 52.6 + id as f64 * 0.001, -0.7), &Tags::new());
+        }
+        handler.add_way(10, &[1, 2], &tags(&[("highway", "residential")]));
+        handler.add_way(20, &[2, 3], &tags(&[("highway", "footway")]));
+        handler.add_way(30, &[4, 5, 6, 4], &tags(&[("building", "yes")]));
+    }
+
+    fn load(profile: &dyn Profile) -> (Network, LoadSummary) {
+        let mut scan = WayNodeScan::new(profile);
+        feed(&mut scan);
+        let mut loader = NetworkLoader::new(profile, scan.finish());
+        feed(&mut loader);
+        loader.finish(LoadMode::Strict).unwrap()
+    }
+
+    #[test]
+    fn stores_only_referenced_nodes() {
+        let (car, summary) = load(&Car);
+        assert_eq!(2, car.node_count());
+        assert_eq!(
+            LoadSummary {
+                nodes_read: 6,
+                referenced_nodes: 2,
+                nodes_stored: 2,
+                ..LoadSummary::default()
+            },
+            summary
+        );
+
+        let (foot, summary) = load(&Foot);
+        assert_eq!(3, foot.node_count());
+        assert_eq!(4, foot.arc_count());
+        assert_eq!(3, summary.nodes_stored);
+    }
+
+    #[test]
+    fn nodes_after_ways() {
+        let ways_first = |handler: &mut dyn ElementHandler| {
+            handler.add_way(10, &[1, 2, 3], &tags(&[("highway", "residential")]));
+            handler.add_way(20, &[3, 4, 9], &tags(&[("highway", "residential")]));
+            for id in 1..=4 {
+                handler.add_node(Node::new(id, 52.6 + id as f64 * 0.001, -0.7), &Tags::new());
+            }
+        };
+        let mut scan = WayNodeScan::new(&Car);
+        ways_first(&mut scan);
+        let mut loader = NetworkLoader::new(&Car, scan.finish());
+        ways_first(&mut loader);
+        let (network, summary) = loader.finish(LoadMode::Lenient).unwrap();
+
+        // the segment to node 9 is the only one dropped
+        assert_eq!(4, network.node_count());
+        assert_eq!(6, network.arc_count());
+        assert_eq!(1, summary.missing_nodes);
+        assert_eq!(1, summary.ways_with_missing_nodes);
+
+        let mut loader = NetworkLoader::new(&Car, [1, 2, 3, 4, 9].iter().cloned().collect());
+        ways_first(&mut loader);
+        match loader.finish(LoadMode::Strict) {
+            Err(LoadError::MissingReference { way: 20, node: 9 }) => (),
+            other => panic!("expected a missing reference, got {:?}", other.map(|(_, summary)| summary)),
+        }
+    }
+}
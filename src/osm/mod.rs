@@ -1,2 +1,139 @@
+use failure::Fail;
+use std::fmt;
+use std::io;
+
+use crate::network::{Network, OSMNodeId, OSMWayId};
+use crate::osm::profile::Profile;
+
 mod constants;
+mod loader;
+pub mod load_pbf;
 pub mod load_xml;
+pub mod profile;
+pub mod speed;
+
+/// counts from loading a file. Only the nodes on ways the profile accepts are stored, so
+/// nodes_stored is the most nodes held in memory at once. Missing nodes are referenced by
+/// accepted ways but never appear in the file, segments to them are dropped
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LoadSummary {
+    pub nodes_read: usize,
+    pub referenced_nodes: usize,
+    pub nodes_stored: usize,
+    pub missing_nodes: usize,
+    pub ways_with_missing_nodes: usize,
+    pub skipped_elements: usize,
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    /// position is the byte offset into the file
+    XmlSyntax { position: usize, message: String },
+    BadAttribute {
+        element: String,
+        attribute: String,
+        value: String,
+        position: usize,
+    },
+    MissingReference { way: OSMWayId, node: OSMNodeId },
+    InvalidPbf(String),
+    NoNetwork,
+}
+
+impl Fail for LoadError {
+    fn cause(&self) -> Option<&dyn Fail> {
+        match self {
+            LoadError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "i/o error: {}", e),
+            LoadError::XmlSyntax { position, message } => {
+                write!(f, "xml syntax error at byte {}: {}", position, message)
+            }
+            LoadError::BadAttribute {
+                element,
+                attribute,
+                value,
+                position,
+            } => write!(f, "bad {} attribute {:?} on {} at byte {}", attribute, value, element, position),
+            LoadError::MissingReference { way, node } => {
+                write!(f, "way {} references node {} which isn't in the file", way, node)
+            }
+            LoadError::InvalidPbf(message) => write!(f, "invalid pbf data: {}", message),
+            LoadError::NoNetwork => write!(f, "no network could be built"),
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(error: io::Error) -> Self {
+        LoadError::Io(error)
+    }
+}
+
+/// how loading treats elements that can't be read
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoadMode {
+    /// fail on the first bad element or missing node reference
+    Strict,
+    /// skip bad elements and drop segments to missing nodes, counting both in the summary
+    Lenient,
+}
+
+impl fmt::Display for LoadSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "read {} nodes, stored {} of the {} referenced by ways",
+            self.nodes_read, self.nodes_stored, self.referenced_nodes
+        )?;
+        if self.missing_nodes > 0 {
+            write!(
+                f,
+                ", {} nodes missing from {} ways",
+                self.missing_nodes, self.ways_with_missing_nodes
+            )?;
+        }
+        if self.skipped_elements > 0 {
+            write!(f, ", skipped {} bad elements", self.skipped_elements)?;
+        }
+        Ok(())
+    }
+}
+
+/// load a network from an OSM file, PBF when the file name ends in ".pbf" and XML otherwise
+pub fn load_network_from_file(file_path: &str, profile: &dyn Profile) -> Result<Network, LoadError> {
+    load_network_with_summary(file_path, profile, LoadMode::Strict).map(|(network, _)| network)
+}
+
+/// load a network from an OSM file along with counts of what was read
+pub fn load_network_with_summary(
+    file_path: &str,
+    profile: &dyn Profile,
+    mode: LoadMode,
+) -> Result<(Network, LoadSummary), LoadError> {
+    if file_path.ends_with(".pbf") {
+        load_pbf::load_network_with_summary(file_path, profile, mode)
+    } else {
+        load_xml::load_network_with_summary(file_path, profile, mode)
+    }
+}
+
+/// a bad element fails a strict load, a lenient load counts it and carries on
+fn skip_element(error: LoadError, mode: LoadMode, skipped: &mut usize) -> Result<(), LoadError> {
+    match mode {
+        LoadMode::Strict => Err(error),
+        LoadMode::Lenient => {
+            warn!("skipping element: {}", error);
+            *skipped += 1;
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+
+use crate::osm::constants;
+use crate::osm::speed;
+
+/// the tags of an OSM way, key to value
+pub type Tags = HashMap<String, String>;
+
+/// which directions along a way, in node order, may be travelled
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Access {
+    pub forward: bool,
+    pub backward: bool,
+}
+
+impl Access {
+    pub const BOTH: Access = Access {
+        forward: true,
+        backward: true,
+    };
+    pub const FORWARD: Access = Access {
+        forward: true,
+        backward: false,
+    };
+    pub const BACKWARD: Access = Access {
+        forward: false,
+        backward: true,
+    };
+}
+
+/// decides how a mode of travel uses the ways in an OSM file
+pub trait Profile {
+    fn name(&self) -> &'static str;
+
+    /// whether the way becomes part of the network at all
+    fn accepts_way(&self, tags: &Tags) -> bool;
+
+    /// the directions an accepted way can be travelled in
+    fn access(&self, tags: &Tags) -> Access;
+
+    /// the speed along an accepted way
+    fn speed_kmh(&self, tags: &Tags) -> f64;
+
+    /// whether a turn restriction relation applies to this mode of travel
+    fn obeys_restriction(&self, tags: &Tags) -> bool {
+        !is_excepted(tags, self.name())
+    }
+
+    /// the cost of an arc, travel time in milliseconds unless overridden
+    fn cost(&self, distance: u64, speed_kmh: f64) -> u64 {
+        speed::travel_time_millis(distance, speed_kmh)
+    }
+}
+
+pub struct Car;
+pub struct Bike;
+pub struct Foot;
+
+/// the built in profile called name
+pub fn by_name(name: &str) -> Option<Box<dyn Profile>> {
+    match name {
+        "car" => Some(Box::new(Car)),
+        "bike" => Some(Box::new(Bike)),
+        "foot" => Some(Box::new(Foot)),
+        _ => None,
+    }
+}
+
+impl Profile for Car {
+    fn name(&self) -> &'static str {
+        "car"
+    }
+
+    fn accepts_way(&self, tags: &Tags) -> bool {
+        match highway(tags) {
+            Some(highway) => {
+                constants::HIGHWAY_ROAD_TYPES.contains(&highway)
+                    && is_allowed(tags, &["motorcar", "motor_vehicle", "vehicle", "access"], true)
+            }
+            None => false,
+        }
+    }
+
+    fn access(&self, tags: &Tags) -> Access {
+        oneway_access(tags)
+    }
+
+    fn speed_kmh(&self, tags: &Tags) -> f64 {
+        let maxspeed = tags.get("maxspeed").map(String::as_str);
+        speed::way_speed_kmh(highway(tags).unwrap_or(""), maxspeed)
+    }
+}
+
+impl Profile for Bike {
+    fn name(&self) -> &'static str {
+        "bike"
+    }
+
+    fn accepts_way(&self, tags: &Tags) -> bool {
+        match highway(tags) {
+            Some(highway) if class_speed(&constants::BICYCLE_HIGHWAY_SPEEDS_KMH, highway).is_some() => {
+                let open_by_default = !constants::BICYCLE_PERMISSION_ONLY_TYPES.contains(&highway);
+                is_allowed(tags, &["bicycle", "vehicle", "access"], open_by_default)
+            }
+            _ => false,
+        }
+    }
+
+    // cycle contraflows are tagged on the way itself, a oneway:bicycle value we don't
+    // understand is ignored
+    fn access(&self, tags: &Tags) -> Access {
+        let contraflow = tags.get("cycleway").is_some_and(|cycleway| cycleway.starts_with("opposite"));
+        match tags.get("oneway:bicycle").and_then(|value| oneway_value(value)) {
+            Some(access) => access,
+            None if contraflow => Access::BOTH,
+            None => oneway_access(tags),
+        }
+    }
+
+    fn speed_kmh(&self, tags: &Tags) -> f64 {
+        highway(tags)
+            .and_then(|highway| class_speed(&constants::BICYCLE_HIGHWAY_SPEEDS_KMH, highway))
+            .unwrap_or(constants::BICYCLE_HIGHWAY_SPEEDS_KMH[0].1)
+    }
+}
+
+impl Profile for Foot {
+    fn name(&self) -> &'static str {
+        "foot"
+    }
+
+    fn accepts_way(&self, tags: &Tags) -> bool {
+        match highway(tags) {
+            Some(highway) if class_speed(&constants::FOOT_HIGHWAY_SPEEDS_KMH, highway).is_some() => {
+                is_allowed(tags, &["foot", "access"], true)
+            }
+            _ => false,
+        }
+    }
+
+    // oneway and turn restrictions are for vehicles
+    fn access(&self, _tags: &Tags) -> Access {
+        Access::BOTH
+    }
+
+    fn obeys_restriction(&self, _tags: &Tags) -> bool {
+        false
+    }
+
+    fn speed_kmh(&self, tags: &Tags) -> f64 {
+        highway(tags)
+            .and_then(|highway| class_speed(&constants::FOOT_HIGHWAY_SPEEDS_KMH, highway))
+            .unwrap_or(constants::FOOT_HIGHWAY_SPEEDS_KMH[0].1)
+    }
+}
+
+fn highway(tags: &Tags) -> Option<&str> {
+    tags.get("highway").map(String::as_str)
+}
+
+// the except tag lists the vehicles a restriction doesn't apply to, separated by ';'
+fn is_excepted(tags: &Tags, profile_name: &str) -> bool {
+    let vehicle = match profile_name {
+        "car" => "motorcar",
+        "bike" => "bicycle",
+        other => other,
+    };
+    tags.get("except")
+        .is_some_and(|except| except.split(';').any(|v| v.trim() == vehicle))
+}
+
+fn class_speed(speeds: &[(&str, f64)], highway: &str) -> Option<f64> {
+    speeds.iter().find(|(class, _)| *class == highway).map(|&(_, speed)| speed)
+}
+
+// the most specific of the access keys present decides, keys are ordered most specific first
+fn is_allowed(tags: &Tags, access_keys: &[&str], default: bool) -> bool {
+    access_keys
+        .iter()
+        .find_map(|key| tags.get(*key))
+        .map_or(default, |value| !constants::ACCESS_DENIED_VALUES.contains(&value.as_str()))
+}
+
+/// the directions a oneway tag value allows, None for values that aren't a direction
+fn oneway_value(value: &str) -> Option<Access> {
+    match value {
+        "yes" | "true" | "1" => Some(Access::FORWARD),
+        "-1" | "reverse" => Some(Access::BACKWARD),
+        "no" | "false" | "0" => Some(Access::BOTH),
+        _ => None,
+    }
+}
+
+// an explicit oneway tag wins, otherwise roundabouts and motorways are oneway
+fn oneway_access(tags: &Tags) -> Access {
+    if let Some(access) = tags.get("oneway").and_then(|value| oneway_value(value)) {
+        return access;
+    }
+    let roundabout = tags.get("junction").is_some_and(|junction| junction == "roundabout");
+    let motorway = match highway(tags) {
+        Some(highway) => constants::IMPLIED_ONEWAY_TYPES.contains(&highway),
+        None => false,
+    };
+    if roundabout || motorway {
+        Access::FORWARD
+    } else {
+        Access::BOTH
+    }
+}
+
+#[cfg(test)]
+mod profile_test {
+    use super::*;
+    use crate::osm::load_xml::load_network_from_string;
+
+    fn tags(pairs: &[(&str, &str)]) -> Tags {
+        pairs.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn way_acceptance() {
+        let footway = tags(&[("highway", "footway")]);
+        let shared_footway = tags(&[("highway", "footway"), ("bicycle", "yes")]);
+        let motorway = tags(&[("highway", "motorway")]);
+        let private_road = tags(&[("highway", "residential"), ("access", "private")]);
+        let private_with_foot_access = tags(&[("highway", "track"), ("access", "private"), ("foot", "permissive")]);
+
+        assert!(!Car.accepts_way(&footway));
+        assert!(!Bike.accepts_way(&footway));
+        assert!(Foot.accepts_way(&footway));
+
+        assert!(Bike.accepts_way(&shared_footway));
+
+        assert!(Car.accepts_way(&motorway));
+        assert!(!Bike.accepts_way(&motorway));
+        assert!(!Foot.accepts_way(&motorway));
+
+        assert!(!Car.accepts_way(&private_road));
+        assert!(!Foot.accepts_way(&private_road));
+
+        assert!(Foot.accepts_way(&private_with_foot_access));
+        assert!(!Bike.accepts_way(&private_with_foot_access));
+
+        assert!(!Foot.accepts_way(&tags(&[("name", "Not A Road")])));
+    }
+
+    #[test]
+    fn directions_and_speeds() {
+        let oneway = tags(&[("highway", "primary"), ("oneway", "yes"), ("maxspeed", "30 mph")]);
+        assert_eq!(Access::FORWARD, Car.access(&oneway));
+        assert_eq!(Access::FORWARD, Bike.access(&oneway));
+        assert_eq!(Access::BOTH, Foot.access(&oneway));
+
+        assert_eq!(30.0 * constants::KMH_PER_MPH, Car.speed_kmh(&oneway));
+        assert_eq!(20.0, Bike.speed_kmh(&oneway));
+        assert_eq!(5.0, Foot.speed_kmh(&oneway));
+    }
+
+    #[test]
+    fn oneway_values() {
+        let road = |pairs: &[(&str, &str)]| {
+            let mut way = tags(pairs);
+            way.entry("highway".to_string()).or_insert_with(|| "residential".to_string());
+            way
+        };
+        assert_eq!(Access::BOTH, Car.access(&road(&[])));
+        assert_eq!(Access::FORWARD, Car.access(&road(&[("oneway", "yes")])));
+        assert_eq!(Access::FORWARD, Car.access(&road(&[("oneway", "true")])));
+        assert_eq!(Access::FORWARD, Car.access(&road(&[("oneway", "1")])));
+        assert_eq!(Access::BACKWARD, Car.access(&road(&[("oneway", "-1")])));
+        assert_eq!(Access::BACKWARD, Car.access(&road(&[("oneway", "reverse")])));
+        assert_eq!(Access::BOTH, Car.access(&road(&[("oneway", "no")])));
+        assert_eq!(Access::BOTH, Car.access(&road(&[("oneway", "reversible")])));
+        assert_eq!(Access::FORWARD, Car.access(&road(&[("junction", "roundabout")])));
+        assert_eq!(Access::FORWARD, Car.access(&road(&[("highway", "motorway")])));
+        assert_eq!(Access::FORWARD, Car.access(&road(&[("highway", "motorway_link")])));
+        assert_eq!(Access::BOTH, Car.access(&road(&[("highway", "motorway"), ("oneway", "no")])));
+
+        let contraflow = road(&[("oneway", "yes"), ("oneway:bicycle", "no")]);
+        assert_eq!(Access::FORWARD, Car.access(&contraflow));
+        assert_eq!(Access::BOTH, Bike.access(&contraflow));
+        assert_eq!(Access::BOTH, Bike.access(&road(&[("oneway", "yes"), ("cycleway", "opposite_lane")])));
+        assert_eq!(Access::FORWARD, Bike.access(&road(&[("oneway:bicycle", "yes")])));
+        assert_eq!(Access::FORWARD, Bike.access(&road(&[("oneway", "yes"), ("oneway:bicycle", "designated")])));
+        assert_eq!(Access::BOTH, Bike.access(&road(&[("oneway:bicycle", "designated")])));
+        assert_eq!(Access::BACKWARD, Bike.access(&road(&[("junction", "roundabout"), ("oneway", "-1")])));
+    }
+
+    #[test]
+    fn profile_names() {
+        for name in &["car", "bike", "foot"] {
+            assert_eq!(*name, by_name(name).unwrap().name());
+        }
+        assert!(by_name("boat").is_none());
+    }
+
+    #[test]
+    fn one_file_many_networks() {
+        // a road between 1 and 2 and a footpath between 2 and 3
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osm version="0.6">
+  <node id="1" lat="52.600" lon="-0.700"/>
+  <node id="2" lat="52.600" lon="-0.699"/>
+  <node id="3" lat="52.601" lon="-0.699"/>
+  <way id="10">
+    <nd ref="1"/>
+    <nd ref="2"/>
+    <tag k="highway" v="residential"/>
+    <tag k="oneway" v="yes"/>
+  </way>
+  <way id="20">
+    <nd ref="2"/>
+    <nd ref="3"/>
+    <tag k="highway" v="footway"/>
+  </way>
+</osm>"#;
+        let car = load_network_from_string(xml, &Car).unwrap();
+        assert_eq!(1, car.arc_count());
+        assert_eq!(1, car.way_count());
+
+        let bike = load_network_from_string(xml, &Bike).unwrap();
+        assert_eq!(1, bike.arc_count());
+
+        let foot = load_network_from_string(xml, &Foot).unwrap();
+        assert_eq!(4, foot.arc_count());
+        assert_eq!(2, foot.way_count());
+        let walk = crate::dijkstra::run_dijsktra(3, 1, &foot, 0, false).unwrap();
+        assert_eq!(speed::travel_time_millis(walk.distance, 5.0), walk.cost);
+    }
+}
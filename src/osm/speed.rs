@@ -0,0 +1,169 @@
+use crate::osm::constants;
+
+/// speed used for highway classes missing from the defaults table
+const FALLBACK_SPEED_KMH: f64 = 30.0;
+
+const NATIONAL_SINGLE_MPH: f64 = 60.0;
+const NATIONAL_DUAL_MPH: f64 = 70.0;
+
+/// no road is signed faster than this, higher values are tagging mistakes
+const MAX_SPEED_KMH: f64 = 300.0;
+
+/// the typical speed on a road class, if it is one we route on
+pub fn default_speed_kmh(highway: &str) -> Option<f64> {
+    constants::HIGHWAY_DEFAULT_SPEEDS_KMH
+        .iter()
+        .find(|(class, _)| *class == highway)
+        .map(|&(_, speed)| speed)
+}
+
+/// parse a maxspeed tag value into km/h. Plain numbers are km/h, "mph" values are
+/// converted and national limits depend on the road class. Values that don't give a
+/// speed, like "none" or "signals", return None
+pub fn parse_maxspeed(value: &str, highway: &str) -> Option<f64> {
+    // multiple values are separated by ';', use the first
+    let value = value.split(';').next().unwrap_or("").trim();
+    let lower = value.to_lowercase();
+    // country prefixed values like GB:nsl_single or UK:national
+    let limit = lower.rsplit(':').next().unwrap_or("");
+    match limit {
+        "national" => return Some(national_limit_kmh(national_limit_mph(highway), highway)),
+        "nsl_single" => return Some(national_limit_kmh(NATIONAL_SINGLE_MPH, highway)),
+        "nsl_dual" | "motorway" => return Some(national_limit_kmh(NATIONAL_DUAL_MPH, highway)),
+        _ => (),
+    }
+
+    let (number, factor) = if let Some(mph) = lower.strip_suffix("mph") {
+        (mph, constants::KMH_PER_MPH)
+    } else if let Some(kmh) = lower.strip_suffix("km/h").or_else(|| lower.strip_suffix("kmh")) {
+        (kmh, 1.0)
+    } else {
+        (lower.as_str(), 1.0)
+    };
+    match number.trim().parse::<f64>() {
+        Ok(speed) if speed > 0.0 && speed.is_finite() && speed * factor <= MAX_SPEED_KMH => Some(speed * factor),
+        _ => None,
+    }
+}
+
+/// the national limit that applies on the road class when the tag doesn't say which
+fn national_limit_mph(highway: &str) -> f64 {
+    match highway {
+        "motorway" | "motorway_link" | "trunk" => NATIONAL_DUAL_MPH,
+        _ => NATIONAL_SINGLE_MPH,
+    }
+}
+
+/// the national limit is a ceiling rather than a typical speed so it never raises the
+/// class default
+fn national_limit_kmh(limit_mph: f64, highway: &str) -> f64 {
+    let limit = limit_mph * constants::KMH_PER_MPH;
+    default_speed_kmh(highway).map_or(limit, |default| default.min(limit))
+}
+
+/// the speed to travel along a way, from its maxspeed tag when it has a usable one
+pub fn way_speed_kmh(highway: &str, maxspeed: Option<&str>) -> f64 {
+    maxspeed
+        .and_then(|value| parse_maxspeed(value, highway))
+        .or_else(|| default_speed_kmh(highway))
+        .unwrap_or(FALLBACK_SPEED_KMH)
+}
+
+/// milliseconds to cover the distance, rounded up so that no arc is free
+pub fn travel_time_millis(distance_metres: u64, speed_kmh: f64) -> u64 {
+    (distance_metres as f64 * 3600.0 / speed_kmh).ceil() as u64
+}
+
+#[cfg(test)]
+mod speed_test {
+    use super::*;
+    use crate::osm::load_xml::load_network_from_string;
+
+    #[test]
+    fn every_road_type_has_a_default_speed() {
+        for highway in constants::HIGHWAY_ROAD_TYPES.iter() {
+            assert!(default_speed_kmh(highway).is_some(), "no speed for {}", highway);
+        }
+        assert_eq!(None, default_speed_kmh("footway"));
+    }
+
+    #[test]
+    fn maxspeed_values() {
+        assert_eq!(Some(50.0), parse_maxspeed("50", "primary"));
+        assert_eq!(Some(50.0), parse_maxspeed("50 km/h", "primary"));
+        assert_eq!(Some(20.0), parse_maxspeed("20;30", "residential"));
+        assert_eq!(Some(30.0 * constants::KMH_PER_MPH), parse_maxspeed("30 mph", "residential"));
+        assert_eq!(Some(40.0 * constants::KMH_PER_MPH), parse_maxspeed("40mph", "residential"));
+        assert_eq!(Some(60.0 * constants::KMH_PER_MPH), parse_maxspeed("GB:nsl_single", "living_street"));
+        assert_eq!(Some(70.0 * constants::KMH_PER_MPH), parse_maxspeed("UK:nsl_dual", "living_street"));
+        assert_eq!(None, parse_maxspeed("none", "motorway"));
+        assert_eq!(None, parse_maxspeed("signals", "motorway"));
+        assert_eq!(None, parse_maxspeed("fast", "primary"));
+        assert_eq!(None, parse_maxspeed("inf", "motorway"));
+        assert_eq!(None, parse_maxspeed("infinity mph", "motorway"));
+        assert_eq!(None, parse_maxspeed("NaN", "motorway"));
+        assert_eq!(None, parse_maxspeed("1e9", "motorway"));
+        assert_eq!(None, parse_maxspeed("200 mph", "motorway"));
+        assert_eq!(Some(300.0), parse_maxspeed("300", "motorway"));
+    }
+
+    #[test]
+    fn national_limit_never_exceeds_class_default() {
+        assert_eq!(Some(110.0), parse_maxspeed("national", "motorway"));
+        assert_eq!(Some(30.0), parse_maxspeed("GB:national", "residential"));
+        assert_eq!(Some(60.0 * constants::KMH_PER_MPH), parse_maxspeed("national", "living_street"));
+        assert_eq!(Some(30.0), parse_maxspeed("GB:nsl_single", "residential"));
+        assert_eq!(Some(60.0), parse_maxspeed("GB:nsl_single", "tertiary"));
+        assert_eq!(Some(90.0), parse_maxspeed("UK:nsl_dual", "trunk"));
+        assert_eq!(Some(110.0), parse_maxspeed("GB:motorway", "motorway"));
+    }
+
+    #[test]
+    fn way_speeds() {
+        assert_eq!(80.0, way_speed_kmh("primary", None));
+        assert_eq!(80.0, way_speed_kmh("primary", Some("variable")));
+        assert_eq!(40.0, way_speed_kmh("primary", Some("40")));
+        assert_eq!(FALLBACK_SPEED_KMH, way_speed_kmh("living_street", None));
+    }
+
+    #[test]
+    fn travel_times() {
+        assert_eq!(3600, travel_time_millis(100, 100.0));
+        assert_eq!(7200, travel_time_millis(100, 50.0));
+        assert_eq!(2000, travel_time_millis(60, 108.0));
+        assert_eq!(0, travel_time_millis(0, 50.0));
+    }
+
+    #[test]
+    fn faster_road_is_preferred() {
+        // a direct residential street and a longer detour along a primary road
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osm version="0.6">
+  <node id="1" lat="52.600" lon="-0.700"/>
+  <node id="2" lat="52.600" lon="-0.690"/>
+  <node id="3" lat="52.602" lon="-0.695"/>
+  <way id="10">
+    <nd ref="1"/>
+    <nd ref="2"/>
+    <tag k="highway" v="residential"/>
+    <tag k="maxspeed" v="20 mph"/>
+  </way>
+  <way id="20">
+    <nd ref="1"/>
+    <nd ref="3"/>
+    <nd ref="2"/>
+    <tag k="highway" v="primary"/>
+    <tag k="maxspeed" v="national"/>
+  </way>
+</osm>"#;
+        let network = load_network_from_string(xml, &crate::osm::profile::Car).unwrap();
+
+        let to_index = network.node_index(&2).unwrap();
+        let direct = network.fwd_arcs_from_node(&1).unwrap().iter().find(|a| a.head_node == to_index).unwrap();
+        assert_eq!(travel_time_millis(direct.distance, 20.0 * constants::KMH_PER_MPH), direct.cost);
+
+        let fastest = crate::dijkstra::run_dijsktra(1, 2, &network, 0, true).unwrap();
+        assert!(fastest.distance > direct.distance);
+        assert!(fastest.cost < direct.cost);
+    }
+}
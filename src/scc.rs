@@ -0,0 +1,168 @@
+use crate::network::{Network, NodeIndex};
+
+const UNASSIGNED: usize = usize::MAX;
+
+/// Kosaraju's algorithm: a depth first search over the forward arcs orders the nodes by
+/// finishing time, then searches over the reverse arcs in reverse finishing order each
+/// collect one strongly connected component. Returns the component of every NodeIndex.
+pub fn strongly_connected_components(network: &Network) -> Vec<usize> {
+    let node_count = network.node_count();
+
+    let mut visited = vec![false; node_count];
+    let mut finish_order = Vec::with_capacity(node_count);
+    for start in 0..node_count {
+        if visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        // node and how many of its arcs have been followed
+        let mut stack: Vec<(NodeIndex, usize)> = vec![(start, 0)];
+        while let Some((node, next_arc)) = stack.last_mut() {
+            let arcs = network.fwd_arcs(*node);
+            if *next_arc < arcs.len() {
+                let head = arcs.heads()[*next_arc];
+                *next_arc += 1;
+                if !visited[head] {
+                    visited[head] = true;
+                    stack.push((head, 0));
+                }
+            } else {
+                finish_order.push(*node);
+                stack.pop();
+            }
+        }
+    }
+
+    let mut components = vec![UNASSIGNED; node_count];
+    let mut component_count = 0;
+    for &start in finish_order.iter().rev() {
+        if components[start] != UNASSIGNED {
+            continue;
+        }
+        components[start] = component_count;
+        let mut stack = vec![start];
+        while let Some(node) = stack.pop() {
+            for arc in network.bwd_arcs(node) {
+                if components[arc.head_node] == UNASSIGNED {
+                    components[arc.head_node] = component_count;
+                    stack.push(arc.head_node);
+                }
+            }
+        }
+        component_count += 1;
+    }
+    components
+}
+
+/// the number of nodes in each component, indexed by component
+pub fn component_sizes(components: &[usize]) -> Vec<usize> {
+    let component_count = components.iter().max().map_or(0, |&c| c + 1);
+    let mut sizes = vec![0; component_count];
+    for &component in components {
+        sizes[component] += 1;
+    }
+    sizes
+}
+
+/// whether each node is in the largest strongly connected component
+pub fn largest_component(network: &Network) -> Vec<bool> {
+    let components = strongly_connected_components(network);
+    let sizes = component_sizes(&components);
+    let largest = (0..sizes.len()).max_by_key(|&c| sizes[c]);
+    components.iter().map(|&c| Some(c) == largest).collect()
+}
+
+#[cfg(test)]
+mod scc_test {
+    use super::*;
+    use crate::dijkstra::run_dijsktra;
+    use crate::network::{NetworkBuilder, OSMNodeId};
+    use crate::test_networks::{grid_id, make_grid_network, GRID_SIZE};
+
+    // 1, 2 and 3 form a loop, 4 can only be left, 5 can only be entered and 6 <-> 7 are
+    // a separate two way street
+    fn make_fragmented_network() -> Network {
+        let network_json = r#"{
+        "all_nodes":{
+            "1": {"id": 1, "latitude": 0, "longitude": 0},
+            "2": {"id": 2, "latitude": 0, "longitude": 0},
+            "3": {"id": 3, "latitude": 0, "longitude": 0},
+            "4": {"id": 4, "latitude": 0, "longitude": 0},
+            "5": {"id": 5, "latitude": 0, "longitude": 0},
+            "6": {"id": 6, "latitude": 0, "longitude": 0},
+            "7": {"id": 7, "latitude": 0, "longitude": 0}
+        },
+        "used_nodes":[1, 2, 3, 4, 5, 6, 7],
+        "way_info":{
+            "10": { "id": 10, "name": "Loop" },
+            "20": { "id": 20, "name": "Slip Road" },
+            "30": { "id": 30, "name": "Service Road" }
+        },
+        "adjacent_arcs":{
+            "1": [{"head_node": 2, "distance": 1, "cost": 1, "part_of_way": 10}, {"head_node": 5, "distance": 4, "cost": 4, "part_of_way": 20}],
+            "2": [{"head_node": 3, "distance": 1, "cost": 1, "part_of_way": 10}],
+            "3": [{"head_node": 1, "distance": 1, "cost": 1, "part_of_way": 10}],
+            "4": [{"head_node": 3, "distance": 2, "cost": 2, "part_of_way": 20}],
+            "6": [{"head_node": 7, "distance": 5, "cost": 5, "part_of_way": 30}],
+            "7": [{"head_node": 6, "distance": 5, "cost": 5, "part_of_way": 30}]
+        }
+    }
+    "#;
+        NetworkBuilder::from_json(network_json).unwrap().build_network().unwrap()
+    }
+
+    fn component_of(network: &Network, components: &[usize], id: OSMNodeId) -> usize {
+        components[network.node_index(&id).unwrap()]
+    }
+
+    #[test]
+    fn components() {
+        let network = make_fragmented_network();
+        let components = strongly_connected_components(&network);
+
+        let loop_component = component_of(&network, &components, 1);
+        assert_eq!(loop_component, component_of(&network, &components, 2));
+        assert_eq!(loop_component, component_of(&network, &components, 3));
+        assert_eq!(component_of(&network, &components, 6), component_of(&network, &components, 7));
+
+        let mut sizes = component_sizes(&components);
+        sizes.sort();
+        assert_eq!(vec![1, 1, 2, 3], sizes);
+    }
+
+    #[test]
+    fn reduce_fragmented_network() {
+        let mut network = make_fragmented_network();
+        let report = network.reduce_to_largest_component();
+
+        assert_eq!(4, report.removed_nodes);
+        assert_eq!(4, report.removed_arcs);
+        assert_eq!(3, network.node_count());
+        assert_eq!(3, network.arc_count());
+        assert_eq!(3, network.reverse_arc_count());
+        for id in &[4, 5, 6, 7] {
+            assert!(network.get_node(id).is_none());
+        }
+        for index in 0..network.node_count() {
+            assert_eq!(Some(index), network.node_index(&network.node_at(index).id));
+        }
+        assert_eq!(2, run_dijsktra(3, 2, &network, 0, false).unwrap().cost);
+    }
+
+    #[test]
+    fn grid_is_one_component() {
+        let mut network = make_grid_network();
+        let components = strongly_connected_components(&network);
+        assert_eq!(vec![(GRID_SIZE * GRID_SIZE) as usize], component_sizes(&components));
+
+        let arc_count = network.arc_count();
+        let report = network.reduce_to_largest_component();
+        assert_eq!(0, report.removed_nodes);
+        assert_eq!(0, report.removed_arcs);
+        assert_eq!(arc_count, network.arc_count());
+        assert_eq!(
+            run_dijsktra(grid_id(0, 0), grid_id(9, 9), &make_grid_network(), 0, false).unwrap().cost,
+            run_dijsktra(grid_id(0, 0), grid_id(9, 9), &network, 0, false).unwrap().cost
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use crate::network::{Arc, Network, NetworkBuilder, Node, OSMNodeId};
+use crate::utils;
+
+pub const GRID_SIZE: u64 = 10;
+
+pub fn grid_id(row: u64, col: u64) -> OSMNodeId {
+    row * GRID_SIZE + col + 1
+}
+
+// a square grid of streets roughly 100m apart. Horizontal streets cost twice as much per
+// metre as vertical ones and every third horizontal street is one way heading east
+pub fn make_grid_network() -> Network {
+    let mut builder = NetworkBuilder::new();
+    for row in 0..GRID_SIZE {
+        for col in 0..GRID_SIZE {
+            builder.insert_node(Node::new(
+                grid_id(row, col),
+                52.6 + row as f64 * 0.0009,
+                -0.7 + col as f64 * 0.0015,
+            ));
+        }
+    }
+
+    let mut connect = |from: OSMNodeId, to: OSMNodeId, cost_factor: u64, oneway: bool| {
+        let distance = utils::haversine_distance_metres(
+            builder.get_node(&from).unwrap().lat_long_f64(),
+            builder.get_node(&to).unwrap().lat_long_f64(),
+        );
+        let arcs: &[(OSMNodeId, OSMNodeId)] = if oneway {
+            &[(from, to)]
+        } else {
+            &[(from, to), (to, from)]
+        };
+        for &(tail, head) in arcs {
+            builder.insert_arc(
+                tail,
+                Arc {
+                    head_node: head,
+                    distance,
+                    cost: distance * cost_factor,
+                    part_of_way: from,
+                },
+            );
+        }
+    };
+    for row in 0..GRID_SIZE {
+        for col in 0..GRID_SIZE {
+            if col + 1 < GRID_SIZE {
+                connect(grid_id(row, col), grid_id(row, col + 1), 2, row % 3 == 1);
+            }
+            if row + 1 < GRID_SIZE {
+                connect(grid_id(row, col), grid_id(row + 1, col), 1, false);
+            }
+        }
+    }
+    builder.build_network().unwrap()
+}
+
+// nodes 1, 2 and 3 joined by one way 10 in that direction, so nothing reaches 1 and 3
+// reaches nothing
+pub fn make_oneway_network() -> Network {
+    let mut builder = NetworkBuilder::new();
+    for (id, coordinate) in [(1, 0), (2, 10), (3, 20)] {
+        builder.insert_node(Node {
+            id,
+            latitude: coordinate,
+            longitude: coordinate,
+        });
+    }
+    for (tail, head, distance, cost) in [(1, 2, 3, 4), (2, 3, 5, 6)] {
+        builder.insert_arc(
+            tail,
+            Arc {
+                head_node: head,
+                distance,
+                cost,
+                part_of_way: 10,
+            },
+        );
+    }
+    builder.build_network().unwrap()
+}
@@ -0,0 +1,314 @@
+use failure::Fail;
+use std::fmt;
+
+use crate::dijkstra::{DijkstraWorkspace, Entry, PrunedSearch, SearchResult};
+use crate::network::{Network, OSMNodeId};
+
+/// the length of the period every travel time function repeats over, in milliseconds
+pub const DAY: u64 = 86_400_000;
+
+/// a departure of a timetabled arc, in milliseconds after midnight, and the time it takes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Connection {
+    pub departure: u64,
+    pub duration: u64,
+}
+
+/// how long an arc takes depending on when it's entered. Times of day are milliseconds
+/// after midnight and each function repeats every day
+#[derive(Clone, Debug, PartialEq)]
+pub enum TravelTimeFunction {
+    Constant(u64),
+    // (time of day, travel time) points in time order, interpolated between neighbouring
+    // points and from the last point to the first one of the next day
+    PiecewiseLinear(Vec<(u64, u64)>),
+    // connections in departure order, entering the arc means waiting for the next one
+    Timetable(Vec<Connection>),
+}
+
+// what's wrong with a function, the time is where FIFO fails
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Problem {
+    Unsorted,
+    NotFifo(u64),
+}
+
+impl TravelTimeFunction {
+    /// the arc's cost scaled by a factor interpolated between (time of day, factor) points,
+    /// such as a speed profile slowing roads down in the rush hour
+    pub fn scaled(cost: u64, factors: &[(u64, f64)]) -> TravelTimeFunction {
+        if factors.is_empty() {
+            return TravelTimeFunction::Constant(cost);
+        }
+        let points = factors.iter().map(|&(time, factor)| (time, (cost as f64 * factor).round() as u64));
+        TravelTimeFunction::PiecewiseLinear(points.collect())
+    }
+
+    /// the time it takes to get to the end of the arc entering it at this time, None for a
+    /// timetable with no connections
+    pub fn travel_time(&self, time: u64) -> Option<u64> {
+        let time_of_day = time % DAY;
+        match self {
+            TravelTimeFunction::Constant(cost) => Some(*cost),
+            TravelTimeFunction::PiecewiseLinear(points) => {
+                let last = *points.last()?;
+                let next = points.partition_point(|&(point_time, _)| point_time <= time_of_day);
+                // the neighbouring points around the time, on the day before or after at the ends
+                let (t0, f0) = if next == 0 {
+                    (last.0 as i64 - DAY as i64, last.1 as i64)
+                } else {
+                    (points[next - 1].0 as i64, points[next - 1].1 as i64)
+                };
+                let (t1, f1) = match points.get(next) {
+                    Some(&(t1, f1)) => (t1 as i64, f1 as i64),
+                    None => (points[0].0 as i64 + DAY as i64, points[0].1 as i64),
+                };
+                // rounding down keeps the arrival times in order
+                let offset = ((f1 - f0) * (time_of_day as i64 - t0)).div_euclid(t1 - t0);
+                Some((f0 + offset) as u64)
+            }
+            TravelTimeFunction::Timetable(connections) => {
+                let first = *connections.first()?;
+                let next = connections.partition_point(|connection| connection.departure < time_of_day);
+                Some(match connections.get(next) {
+                    Some(connection) => connection.departure - time_of_day + connection.duration,
+                    None => first.departure + DAY - time_of_day + first.duration,
+                })
+            }
+        }
+    }
+
+    // times must be in order within a day and leaving later must never arrive earlier,
+    // which a linear piece keeps to when it keeps to it at its ends
+    fn check(&self) -> Result<(), Problem> {
+        let arrivals: Vec<(u64, u64)> = match self {
+            TravelTimeFunction::Constant(_) => return Ok(()),
+            TravelTimeFunction::PiecewiseLinear(points) => points.clone(),
+            TravelTimeFunction::Timetable(connections) => connections
+                .iter()
+                .map(|connection| (connection.departure, connection.duration))
+                .collect(),
+        };
+        let strictly = matches!(self, TravelTimeFunction::PiecewiseLinear(_));
+        for pair in arrivals.windows(2) {
+            if pair[1].0 < pair[0].0 || (strictly && pair[1].0 == pair[0].0) {
+                return Err(Problem::Unsorted);
+            }
+        }
+        if arrivals.last().is_some_and(|&(time, _)| time >= DAY) {
+            return Err(Problem::Unsorted);
+        }
+        let next_day = arrivals.first().map(|&(time, travel_time)| (time + DAY, travel_time));
+        for (&(t0, f0), (t1, f1)) in arrivals.iter().zip(arrivals.iter().skip(1).cloned().chain(next_day)) {
+            if t1 + f1 < t0 + f0 {
+                return Err(Problem::NotFifo(t0));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TimeDependentError {
+    ArcCount { arcs: usize, functions: usize },
+    /// times not in order, or past the end of the day
+    Unsorted { arc_id: usize },
+    /// entering the arc at time arrives later than entering it a little after
+    NotFifo { arc_id: usize, time: u64 },
+}
+
+impl Fail for TimeDependentError {}
+
+impl fmt::Display for TimeDependentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TimeDependentError::ArcCount { arcs, functions } => {
+                write!(f, "{} travel time functions for {} arcs", functions, arcs)
+            }
+            TimeDependentError::Unsorted { arc_id } => write!(f, "times of arc {} are out of order", arc_id),
+            TimeDependentError::NotFifo { arc_id, time } => {
+                write!(f, "arc {} isn't FIFO, leaving later than {} ms arrives earlier", arc_id, time)
+            }
+        }
+    }
+}
+
+/// a network with a travel time function for every forward arc in the order of
+/// Network::fwd_arc_ids, in place of the arcs' constant costs. Every function is checked to
+/// be FIFO, so waiting at a node never gets anywhere sooner and dijkstra on arrival times
+/// finds the earliest arrival
+#[derive(Debug)]
+pub struct TimeDependentNetwork {
+    network: Network,
+    functions: Vec<TravelTimeFunction>,
+}
+
+impl TimeDependentNetwork {
+    pub fn new(network: Network, functions: Vec<TravelTimeFunction>) -> Result<TimeDependentNetwork, TimeDependentError> {
+        if functions.len() != network.arc_count() {
+            return Err(TimeDependentError::ArcCount {
+                arcs: network.arc_count(),
+                functions: functions.len(),
+            });
+        }
+        for (arc_id, function) in functions.iter().enumerate() {
+            match function.check() {
+                Ok(()) => (),
+                Err(Problem::Unsorted) => return Err(TimeDependentError::Unsorted { arc_id }),
+                Err(Problem::NotFifo(time)) => return Err(TimeDependentError::NotFifo { arc_id, time }),
+            }
+        }
+        Ok(TimeDependentNetwork { network, functions })
+    }
+
+    /// every arc's cost scaled by the same (time of day, factor) profile
+    pub fn with_profile(network: Network, factors: &[(u64, f64)]) -> Result<TimeDependentNetwork, TimeDependentError> {
+        let functions = (0..network.node_count())
+            .flat_map(|node| network.fwd_arcs(node))
+            .map(|arc| TravelTimeFunction::scaled(arc.cost, factors))
+            .collect();
+        TimeDependentNetwork::new(network, functions)
+    }
+
+    pub fn network(&self) -> &Network {
+        &self.network
+    }
+
+    pub fn function(&self, arc_id: usize) -> &TravelTimeFunction {
+        &self.functions[arc_id]
+    }
+}
+
+pub fn run_td_dijkstra(
+    source: OSMNodeId,
+    target: OSMNodeId,
+    td_network: &TimeDependentNetwork,
+    departure: u64,
+    trace_path: bool,
+) -> Option<Entry<'_>> {
+    search_td_dijkstra(source, target, td_network, departure, trace_path).entry
+}
+
+/// dijkstra leaving the source at the departure time, in milliseconds after midnight of the
+/// first day. An arc's cost is its travel time when the search reaches its tail, and the
+/// entry's cost is the time from departure to arrival
+pub fn search_td_dijkstra(
+    source: OSMNodeId,
+    target: OSMNodeId,
+    td_network: &TimeDependentNetwork,
+    departure: u64,
+    trace_path: bool,
+) -> SearchResult<'_> {
+    let network = &td_network.network;
+    match (network.node_index(&source), network.node_index(&target)) {
+        (Some(source_index), Some(target_index)) => {
+            let search = PrunedSearch {
+                source_index,
+                target_index: Some(target_index),
+                max_distance: 0,
+                trace_path,
+            };
+            let mut workspace = DijkstraWorkspace::for_network(network);
+            search.run(&mut workspace, network, |_| 0, |arc_id, _, cost| {
+                td_network.functions[arc_id].travel_time(departure + cost)
+            })
+        }
+        _ => {
+            debug!("time dependent dijkstra. couldn't find source {} or target {}", source, target);
+            SearchResult {
+                entry: None,
+                settled_nodes: 0,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod time_dependent_test {
+    use super::*;
+    use crate::dijkstra::run_dijsktra;
+    use crate::test_networks::{grid_id, make_grid_network, GRID_SIZE};
+
+    const HOUR: u64 = 3_600_000;
+
+    fn rush_hour() -> Vec<(u64, f64)> {
+        vec![(6 * HOUR, 1.0), (7 * HOUR, 2.0), (9 * HOUR, 2.0), (10 * HOUR, 1.0)]
+    }
+
+    #[test]
+    fn travel_times() {
+        let linear = TravelTimeFunction::PiecewiseLinear(vec![(HOUR, 1000), (3 * HOUR, 3000)]);
+        assert_eq!(Some(1000), linear.travel_time(HOUR));
+        assert_eq!(Some(2000), linear.travel_time(2 * HOUR));
+        // from the last point round to the first one the next day
+        assert_eq!(Some(2000), linear.travel_time(14 * HOUR));
+        assert_eq!(Some(2500), linear.travel_time(DAY + 17 * HOUR / 2));
+
+        let timetable = TravelTimeFunction::Timetable(vec![
+            Connection { departure: HOUR, duration: 600 },
+            Connection { departure: 2 * HOUR, duration: 900 },
+        ]);
+        assert_eq!(Some(900), timetable.travel_time(2 * HOUR));
+        assert_eq!(Some(1000 + 600), timetable.travel_time(HOUR - 1000));
+        assert_eq!(Some(DAY - HOUR - 1 + 600), timetable.travel_time(2 * HOUR + 1));
+        assert_eq!(None, TravelTimeFunction::Timetable(vec![]).travel_time(0));
+    }
+
+    #[test]
+    fn rejects_functions_that_arent_fifo() {
+        let arc_count = make_grid_network().arc_count();
+        let with_first = |function: TravelTimeFunction| {
+            let mut functions = vec![TravelTimeFunction::Constant(1); arc_count];
+            functions[0] = function;
+            TimeDependentNetwork::new(make_grid_network(), functions).err()
+        };
+
+        assert_eq!(None, with_first(TravelTimeFunction::PiecewiseLinear(vec![(0, 5000), (2000, 3000)])));
+        assert_eq!(
+            Some(TimeDependentError::NotFifo { arc_id: 0, time: 0 }),
+            with_first(TravelTimeFunction::PiecewiseLinear(vec![(0, 5000), (1000, 3000)]))
+        );
+        // overnight into the first point of the next day
+        assert_eq!(
+            Some(TimeDependentError::NotFifo { arc_id: 0, time: DAY - 10 }),
+            with_first(TravelTimeFunction::PiecewiseLinear(vec![(0, 0), (DAY - 10, 100)]))
+        );
+        assert_eq!(
+            Some(TimeDependentError::NotFifo { arc_id: 0, time: HOUR }),
+            with_first(TravelTimeFunction::Timetable(vec![
+                Connection { departure: HOUR, duration: HOUR },
+                Connection { departure: HOUR + 1, duration: 10 },
+            ]))
+        );
+        assert_eq!(
+            Some(TimeDependentError::Unsorted { arc_id: 0 }),
+            with_first(TravelTimeFunction::PiecewiseLinear(vec![(HOUR, 1), (HOUR, 2)]))
+        );
+        assert_eq!(
+            Some(TimeDependentError::ArcCount {
+                arcs: arc_count,
+                functions: 0
+            }),
+            TimeDependentNetwork::new(make_grid_network(), vec![]).err()
+        );
+    }
+
+    #[test]
+    fn rush_hour_costs() {
+        let network = make_grid_network();
+        let td_network = TimeDependentNetwork::with_profile(make_grid_network(), &rush_hour()).unwrap();
+        for &source in &[grid_id(0, 0), grid_id(4, 7)] {
+            for row in 0..GRID_SIZE {
+                for col in 0..GRID_SIZE {
+                    let target = grid_id(row, col);
+                    let expected = run_dijsktra(source, target, &network, 0, false).unwrap().cost;
+                    let night = run_td_dijkstra(source, target, &td_network, 3 * HOUR, false).unwrap();
+                    let rush = run_td_dijkstra(source, target, &td_network, DAY + 8 * HOUR, true).unwrap();
+                    assert_eq!(expected, night.cost, "{} to {}", source, target);
+                    assert_eq!(2 * expected, rush.cost, "{} to {}", source, target);
+                }
+            }
+        }
+        assert!(run_td_dijkstra(grid_id(0, 0), 999_999, &td_network, 0, false).is_none());
+    }
+}
@@ -0,0 +1,353 @@
+use crate::ch::{search_ch, search_ch_path, ContractionHierarchy};
+use crate::dijkstra::Entry;
+use crate::matrix::{self, MatrixEntry};
+use crate::network::{Arc, Direction, Network, NodeIndex, OSMNodeId};
+
+/// a transit node reached by a search from a node, by its position in the transit nodes
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct AccessNode {
+    transit: usize,
+    cost: u64,
+    distance: u64,
+}
+
+/// the cheapest path through transit nodes, leaving the source's access nodes at from and
+/// reaching the target's at to, both by position in the transit nodes
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct TransitPath {
+    cost: u64,
+    distance: u64,
+    from: usize,
+    to: usize,
+}
+
+/// the latitude and longitude range of the nodes a search settled before reaching transit nodes
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Bounds {
+    min_lat: i32,
+    max_lat: i32,
+    min_long: i32,
+    max_long: i32,
+}
+
+impl Bounds {
+    fn overlaps(&self, other: &Bounds) -> bool {
+        self.min_lat <= other.max_lat
+            && other.min_lat <= self.max_lat
+            && self.min_long <= other.max_long
+            && other.min_long <= self.max_long
+    }
+}
+
+/// Transit node routing on a contraction hierarchy. The highest ranked nodes are transit
+/// nodes with a table of the costs between every pair. Each node keeps the transit nodes
+/// its upward searches reach before any other transit node, its access nodes, and the
+/// bounds of the nodes those searches settled on the way. A cheapest path with no transit
+/// node on it goes up to and down from a node that both searches settle, so when the
+/// bounds of the source's and target's searches don't overlap the cheapest path is
+/// through access nodes. Otherwise the query falls back to the contraction hierarchy.
+#[derive(Clone, Debug)]
+pub struct TransitNodeRouting {
+    transit_nodes: Vec<NodeIndex>,
+    // transit_nodes.len() entries per transit node, indexed by position in transit_nodes
+    table: Vec<MatrixEntry>,
+    forward_access: Vec<Vec<AccessNode>>,
+    backward_access: Vec<Vec<AccessNode>>,
+    // None when the search reached only transit nodes
+    forward_bounds: Vec<Option<Bounds>>,
+    backward_bounds: Vec<Option<Bounds>>,
+}
+
+/// the cheapest path found and whether the locality filter sent the query to the
+/// contraction hierarchy. Long range queries look their cost up in the tables and settle
+/// no nodes unless their path is traced, which runs a contraction hierarchy query for each
+/// of its three parts
+pub struct TransitResult<'a> {
+    pub entry: Option<Entry<'a>>,
+    pub local: bool,
+    pub settled_nodes: usize,
+}
+
+impl TransitNodeRouting {
+    pub fn new(network: &Network, hierarchy: &ContractionHierarchy, transit_node_count: usize) -> TransitNodeRouting {
+        let node_count = network.node_count();
+        let transit_node_count = transit_node_count.min(node_count);
+        let mut transit_nodes: Vec<NodeIndex> = (0..node_count)
+            .filter(|&node| hierarchy.rank(node) >= node_count - transit_node_count)
+            .collect();
+        transit_nodes.sort_by_key(|&node| hierarchy.rank(node));
+
+        let mut transit_position = vec![None; node_count];
+        for (position, &node) in transit_nodes.iter().enumerate() {
+            transit_position[node] = Some(position);
+        }
+
+        let transit_ids: Vec<OSMNodeId> = transit_nodes.iter().map(|&node| network.node_at(node).id).collect();
+        let table = matrix::many_to_many(&transit_ids, &transit_ids, network, Some(hierarchy));
+        let table = (0..transit_ids.len()).flat_map(|row| table.row(row).to_vec()).collect();
+
+        let mut tnr = TransitNodeRouting {
+            transit_nodes,
+            table,
+            forward_access: Vec::with_capacity(node_count),
+            backward_access: Vec::with_capacity(node_count),
+            forward_bounds: Vec::with_capacity(node_count),
+            backward_bounds: Vec::with_capacity(node_count),
+        };
+        for node in 0..node_count {
+            let (access, bounds) = access_nodes(node, Direction::Forward, network, hierarchy, &transit_position);
+            tnr.forward_access.push(access);
+            tnr.forward_bounds.push(bounds);
+            let (access, bounds) = access_nodes(node, Direction::Backward, network, hierarchy, &transit_position);
+            tnr.backward_access.push(access);
+            tnr.backward_bounds.push(bounds);
+        }
+
+        let access_count: usize = tnr.forward_access.iter().chain(&tnr.backward_access).map(Vec::len).sum();
+        info!(
+            "{} transit nodes, {:.1} access nodes per node and direction",
+            tnr.transit_nodes.len(),
+            access_count as f64 / (2 * node_count).max(1) as f64
+        );
+        tnr
+    }
+
+    pub fn transit_nodes(&self) -> &[NodeIndex] {
+        &self.transit_nodes
+    }
+
+    /// false when the cheapest path might not pass through a transit node
+    pub fn is_long_range(&self, source_index: NodeIndex, target_index: NodeIndex) -> bool {
+        match (&self.forward_bounds[source_index], &self.backward_bounds[target_index]) {
+            (Some(forward), Some(backward)) => !forward.overlaps(backward),
+            _ => true,
+        }
+    }
+
+    // the cheapest combination of an access node of the source, the table and an access
+    // node of the target, None if no transit node leads from one to the other
+    fn transit_path(&self, source_index: NodeIndex, target_index: NodeIndex) -> Option<TransitPath> {
+        let width = self.transit_nodes.len();
+        let mut best: Option<TransitPath> = None;
+        for from in &self.forward_access[source_index] {
+            for to in &self.backward_access[target_index] {
+                if let MatrixEntry::Reachable { cost, distance } = self.table[from.transit * width + to.transit] {
+                    let total = from.cost + cost + to.cost;
+                    if best.is_none_or(|best| total < best.cost) {
+                        best = Some(TransitPath {
+                            cost: total,
+                            distance: from.distance + distance + to.distance,
+                            from: from.transit,
+                            to: to.transit,
+                        });
+                    }
+                }
+            }
+        }
+        best
+    }
+
+    // the cheapest paths from the source to the first transit node, between the transit
+    // nodes and on to the target add up to the transit path's cost, so joined they are a
+    // cheapest path. Returns the arcs and the nodes the three queries settled
+    fn trace(
+        &self,
+        transit_path: &TransitPath,
+        source_index: NodeIndex,
+        target_index: NodeIndex,
+        hierarchy: &ContractionHierarchy,
+    ) -> (Option<Vec<Arc<NodeIndex>>>, usize) {
+        let stops = [
+            source_index,
+            self.transit_nodes[transit_path.from],
+            self.transit_nodes[transit_path.to],
+            target_index,
+        ];
+        let mut path = Some(vec![]);
+        let mut settled_nodes = 0;
+        for leg in stops.windows(2) {
+            let (arcs, settled) = search_ch_path(leg[0], leg[1], hierarchy);
+            settled_nodes += settled;
+            path = path.zip(arcs).map(|(mut path, arcs)| {
+                path.extend(arcs);
+                path
+            });
+        }
+        (path, settled_nodes)
+    }
+}
+
+// the transit nodes settled by an upward search that stops at transit nodes and the
+// bounds of the other nodes it settled
+fn access_nodes(
+    node: NodeIndex,
+    direction: Direction,
+    network: &Network,
+    hierarchy: &ContractionHierarchy,
+    transit_position: &[Option<usize>],
+) -> (Vec<AccessNode>, Option<Bounds>) {
+    let mut access = vec![];
+    let mut bounds: Option<Bounds> = None;
+    for (settled, cost, distance) in hierarchy.upward_search(node, direction, |n| transit_position[n].is_some()) {
+        match transit_position[settled] {
+            Some(transit) => access.push(AccessNode { transit, cost, distance }),
+            None => {
+                let point = network.node_at(settled);
+                let extended = match bounds {
+                    Some(b) => Bounds {
+                        min_lat: b.min_lat.min(point.latitude),
+                        max_lat: b.max_lat.max(point.latitude),
+                        min_long: b.min_long.min(point.longitude),
+                        max_long: b.max_long.max(point.longitude),
+                    },
+                    None => Bounds {
+                        min_lat: point.latitude,
+                        max_lat: point.latitude,
+                        min_long: point.longitude,
+                        max_long: point.longitude,
+                    },
+                };
+                bounds = Some(extended);
+            }
+        }
+    }
+    (access, bounds)
+}
+
+pub fn run_tnr<'a>(
+    source: OSMNodeId,
+    target: OSMNodeId,
+    network: &'a Network,
+    hierarchy: &ContractionHierarchy,
+    tnr: &TransitNodeRouting,
+    trace_path: bool,
+) -> Option<Entry<'a>> {
+    search_tnr(source, target, network, hierarchy, tnr, trace_path).entry
+}
+
+/// looks the cost up through the access nodes and the transit table for long range
+/// queries and runs a contraction hierarchy query for local ones, whose path is always
+/// traced. The cost matches run_dijsktra's and so does the path when it's traced
+pub fn search_tnr<'a>(
+    source: OSMNodeId,
+    target: OSMNodeId,
+    network: &'a Network,
+    hierarchy: &ContractionHierarchy,
+    tnr: &TransitNodeRouting,
+    trace_path: bool,
+) -> TransitResult<'a> {
+    let (source_index, target_index) = match (network.node_index(&source), network.node_index(&target)) {
+        (Some(s), Some(t)) => (s, t),
+        _ => {
+            debug!("tnr. couldn't find source {} or target {}", source, target);
+            return TransitResult {
+                entry: None,
+                local: false,
+                settled_nodes: 0,
+            };
+        }
+    };
+    if !tnr.is_long_range(source_index, target_index) {
+        let result = search_ch(source, target, network, hierarchy);
+        return TransitResult {
+            entry: result.entry,
+            local: true,
+            settled_nodes: result.settled_nodes,
+        };
+    }
+
+    let (entry, settled_nodes) = match tnr.transit_path(source_index, target_index) {
+        Some(transit_path) if trace_path => {
+            let (path, settled_nodes) = tnr.trace(&transit_path, source_index, target_index, hierarchy);
+            (path.map(|path| Entry::from_path(network, source_index, &path)), settled_nodes)
+        }
+        Some(transit_path) => (
+            Some(Entry::without_path(target_index, transit_path.cost, transit_path.distance)),
+            0,
+        ),
+        None => (None, 0),
+    };
+    TransitResult {
+        entry,
+        local: false,
+        settled_nodes,
+    }
+}
+
+#[cfg(test)]
+mod tnr_test {
+    use super::*;
+    use crate::dijkstra::run_dijsktra;
+    use crate::test_networks::{grid_id, make_grid_network, make_oneway_network, GRID_SIZE};
+
+    #[test]
+    fn same_costs_as_dijkstra() {
+        let network = make_grid_network();
+        let hierarchy = ContractionHierarchy::new(&network);
+        for &transit_node_count in &[0, 1, 10, 30, 100] {
+            let tnr = TransitNodeRouting::new(&network, &hierarchy, transit_node_count);
+            assert_eq!(transit_node_count, tnr.transit_nodes().len());
+            for source in (0..GRID_SIZE * GRID_SIZE).step_by(7).map(|i| i + 1) {
+                for row in 0..GRID_SIZE {
+                    for col in 0..GRID_SIZE {
+                        let target = grid_id(row, col);
+                        let expected = run_dijsktra(source, target, &network, 0, true);
+                        let untraced = run_tnr(source, target, &network, &hierarchy, &tnr, false);
+                        let traced = run_tnr(source, target, &network, &hierarchy, &tnr, true);
+                        let message = format!("{} transit nodes, {} to {}", transit_node_count, source, target);
+                        assert_eq!(expected.as_ref().map(|e| e.cost), untraced.map(|e| e.cost), "{}", message);
+                        let summary = |e: &Entry| (e.cost, e.distance, e.report_traversed_ways());
+                        assert_eq!(expected.as_ref().map(summary), traced.as_ref().map(summary), "{}", message);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn far_queries_use_transit_nodes() {
+        let network = make_grid_network();
+        let hierarchy = ContractionHierarchy::new(&network);
+        let tnr = TransitNodeRouting::new(&network, &hierarchy, 20);
+
+        let (source, target) = (grid_id(0, 0), grid_id(GRID_SIZE - 1, GRID_SIZE - 1));
+        let expected = run_dijsktra(source, target, &network, 0, true).unwrap();
+        // the table holds costs, not paths, so only tracing the path settles nodes
+        let far = search_tnr(source, target, &network, &hierarchy, &tnr, false);
+        assert!(!far.local);
+        assert_eq!(0, far.settled_nodes);
+        let far = far.entry.unwrap();
+        assert_eq!(expected.cost, far.cost);
+        assert_eq!("", far.report_traversed_ways());
+
+        let traced = search_tnr(source, target, &network, &hierarchy, &tnr, true);
+        assert!(!traced.local);
+        assert!(traced.settled_nodes > 0);
+        let traced = traced.entry.unwrap();
+        assert_eq!(expected.cost, traced.cost);
+        assert_eq!(expected.distance, traced.distance);
+        assert_eq!(expected.report_traversed_ways(), traced.report_traversed_ways());
+
+        // a node's search settles the node itself so a query to itself is always local
+        let index = (0..network.node_count()).find(|n| !tnr.transit_nodes().contains(n)).unwrap();
+        let id = network.node_at(index).id;
+        let same = search_tnr(id, id, &network, &hierarchy, &tnr, false);
+        assert!(same.local);
+        assert_eq!(0, same.entry.unwrap().cost);
+    }
+
+    #[test]
+    fn unreachable_targets() {
+        let network = make_oneway_network();
+        let hierarchy = ContractionHierarchy::new(&network);
+        for transit_node_count in 0..=3 {
+            let tnr = TransitNodeRouting::new(&network, &hierarchy, transit_node_count);
+            for trace_path in [false, true] {
+                let cost = run_tnr(1, 3, &network, &hierarchy, &tnr, trace_path).map(|e| e.cost);
+                assert_eq!(Some(10), cost);
+                assert!(run_tnr(3, 1, &network, &hierarchy, &tnr, trace_path).is_none());
+                assert!(run_tnr(1, 999, &network, &hierarchy, &tnr, trace_path).is_none());
+            }
+        }
+    }
+}
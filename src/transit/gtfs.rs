@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::transit::GtfsError;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stop {
+    pub id: String,
+    pub name: String,
+    pub lat: f64,
+    pub long: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Route {
+    pub id: String,
+    /// the short name, or the long name when there's no short one
+    pub name: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trip {
+    pub id: String,
+    pub route_id: String,
+    pub service_id: String,
+}
+
+/// times are seconds after midnight of the service day and can be past 24:00:00
+#[derive(Clone, Debug, PartialEq)]
+pub struct StopTime {
+    pub trip_id: String,
+    pub arrival: u32,
+    pub departure: u32,
+    pub stop_id: String,
+    pub sequence: u32,
+    /// the line of stop_times.txt it was read from
+    pub line: usize,
+}
+
+/// the weekdays a service runs on, monday first, between two YYYYMMDD dates inclusive
+#[derive(Clone, Debug, PartialEq)]
+pub struct Service {
+    pub id: String,
+    pub days: [bool; 7],
+    pub start_date: u32,
+    pub end_date: u32,
+}
+
+impl Service {
+    pub fn runs_on(&self, date: u32) -> bool {
+        self.start_date <= date && date <= self.end_date && weekday(date).is_some_and(|day| self.days[day])
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transfer {
+    pub from_stop_id: String,
+    pub to_stop_id: String,
+    // transfer_type 3, no transfer is possible
+    pub forbidden: bool,
+    pub min_transfer_time: Option<u32>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Feed {
+    pub stops: Vec<Stop>,
+    pub routes: Vec<Route>,
+    pub trips: Vec<Trip>,
+    pub stop_times: Vec<StopTime>,
+    pub services: Vec<Service>,
+    pub transfers: Vec<Transfer>,
+}
+
+impl Feed {
+    /// reads the feed from the .txt files in a directory, transfers.txt is optional
+    pub fn read_dir(dir: &Path) -> Result<Feed, GtfsError> {
+        let mut feed = Feed::default();
+
+        for row in read_table(dir, "stops.txt")?.rows() {
+            feed.stops.push(Stop {
+                id: row.text("stop_id")?,
+                name: row.optional("stop_name").unwrap_or_default(),
+                lat: row.parse("stop_lat")?,
+                long: row.parse("stop_lon")?,
+            });
+        }
+        for row in read_table(dir, "routes.txt")?.rows() {
+            let name = match row.optional("route_short_name").filter(|name| !name.is_empty()) {
+                Some(name) => name,
+                None => row.optional("route_long_name").unwrap_or_default(),
+            };
+            feed.routes.push(Route {
+                id: row.text("route_id")?,
+                name,
+            });
+        }
+        for row in read_table(dir, "trips.txt")?.rows() {
+            feed.trips.push(Trip {
+                id: row.text("trip_id")?,
+                route_id: row.text("route_id")?,
+                service_id: row.text("service_id")?,
+            });
+        }
+        for row in read_table(dir, "stop_times.txt")?.rows() {
+            feed.stop_times.push(StopTime {
+                trip_id: row.text("trip_id")?,
+                arrival: row.time("arrival_time")?,
+                departure: row.time("departure_time")?,
+                stop_id: row.text("stop_id")?,
+                sequence: row.parse("stop_sequence")?,
+                line: row.line,
+            });
+        }
+        for row in read_table(dir, "calendar.txt")?.rows() {
+            let mut days = [false; 7];
+            for (day, column) in ["monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"]
+                .iter()
+                .enumerate()
+            {
+                days[day] = row.parse::<u8>(column)? == 1;
+            }
+            feed.services.push(Service {
+                id: row.text("service_id")?,
+                days,
+                start_date: row.parse("start_date")?,
+                end_date: row.parse("end_date")?,
+            });
+        }
+        if dir.join("transfers.txt").exists() {
+            for row in read_table(dir, "transfers.txt")?.rows() {
+                let min_transfer_time = match row.optional("min_transfer_time").filter(|time| !time.is_empty()) {
+                    Some(_) => Some(row.parse("min_transfer_time")?),
+                    None => None,
+                };
+                feed.transfers.push(Transfer {
+                    from_stop_id: row.text("from_stop_id")?,
+                    to_stop_id: row.text("to_stop_id")?,
+                    forbidden: row.optional("transfer_type").as_deref() == Some("3"),
+                    min_transfer_time,
+                });
+            }
+        }
+        Ok(feed)
+    }
+}
+
+/// seconds after midnight of a H:MM:SS time, hours can go past 23
+pub fn parse_time(time: &str) -> Option<u32> {
+    let mut parts = time.trim().split(':');
+    let hours: u32 = parts.next()?.parse().ok()?;
+    let minutes: u32 = parts.next()?.parse().ok()?;
+    let seconds: u32 = parts.next()?.parse().ok()?;
+    if parts.next().is_some() || minutes > 59 || seconds > 59 {
+        return None;
+    }
+    Some(hours * 3600 + minutes * 60 + seconds)
+}
+
+/// seconds after midnight as HH:MM:SS, the inverse of parse_time
+pub fn format_time(time: u32) -> String {
+    format!("{:02}:{:02}:{:02}", time / 3600, time / 60 % 60, time % 60)
+}
+
+// monday is 0, None for a date that isn't YYYYMMDD
+fn weekday(date: u32) -> Option<usize> {
+    let (year, month, day) = ((date / 10_000) as i64, date / 100 % 100, date % 100);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    // days since 1970-01-01, a thursday, counting march as the first month of the year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let shifted_month = (month as i64 + 9) % 12;
+    let day_of_year = (153 * shifted_month + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    Some((days + 3).rem_euclid(7) as usize)
+}
+
+struct Table {
+    file: &'static str,
+    columns: HashMap<String, usize>,
+    // the line number of each record with its fields
+    records: Vec<(usize, Vec<String>)>,
+}
+
+struct Row<'t> {
+    table: &'t Table,
+    line: usize,
+    fields: &'t [String],
+}
+
+fn read_table(dir: &Path, file: &'static str) -> Result<Table, GtfsError> {
+    let contents = fs::read_to_string(dir.join(file)).map_err(|error| GtfsError::Io {
+        file: file.to_string(),
+        error,
+    })?;
+    let mut lines = contents.trim_start_matches('\u{feff}').lines().enumerate();
+    let columns = match lines.next() {
+        Some((_, header)) => split_record(header)
+            .into_iter()
+            .enumerate()
+            .map(|(position, column)| (column.trim().to_string(), position))
+            .collect(),
+        None => HashMap::new(),
+    };
+    let records = lines
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| (number + 1, split_record(line)))
+        .collect();
+    Ok(Table { file, columns, records })
+}
+
+impl Table {
+    fn rows(&self) -> impl Iterator<Item = Row<'_>> {
+        self.records.iter().map(move |(line, fields)| Row {
+            table: self,
+            line: *line,
+            fields,
+        })
+    }
+}
+
+impl<'t> Row<'t> {
+    // None when the file has no such column
+    fn optional(&self, column: &str) -> Option<String> {
+        let position = *self.table.columns.get(column)?;
+        Some(self.fields.get(position).map(|field| field.trim().to_string()).unwrap_or_default())
+    }
+
+    fn text(&self, column: &str) -> Result<String, GtfsError> {
+        self.optional(column).ok_or_else(|| GtfsError::MissingColumn {
+            file: self.table.file.to_string(),
+            column: column.to_string(),
+        })
+    }
+
+    fn bad_value(&self, column: &str, value: String) -> GtfsError {
+        GtfsError::BadValue {
+            file: self.table.file.to_string(),
+            line: self.line,
+            column: column.to_string(),
+            value,
+        }
+    }
+
+    fn parse<T: std::str::FromStr>(&self, column: &str) -> Result<T, GtfsError> {
+        let value = self.text(column)?;
+        value.parse().map_err(|_| self.bad_value(column, value))
+    }
+
+    fn time(&self, column: &str) -> Result<u32, GtfsError> {
+        let value = self.text(column)?;
+        parse_time(&value).ok_or_else(|| self.bad_value(column, value))
+    }
+}
+
+// the fields of a comma separated line, a quoted field can hold commas and "" for a quote
+fn split_record(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+#[cfg(test)]
+mod gtfs_test {
+    use super::*;
+
+    #[test]
+    fn split_quoted_fields() {
+        assert_eq!(vec!["A", "Oakham, Station", "52.6"], split_record(r#"A,"Oakham, Station",52.6"#));
+        assert_eq!(vec!["say \"hi\"", "", ""], split_record(r#""say ""hi""",,"#));
+    }
+
+    #[test]
+    fn times_and_weekdays() {
+        assert_eq!(Some(8 * 3600 + 5 * 60 + 9), parse_time("8:05:09"));
+        assert_eq!(Some(25 * 3600 + 10 * 60), parse_time(" 25:10:00"));
+        assert_eq!(None, parse_time("08:60:00"));
+        assert_eq!(None, parse_time("08:00"));
+        assert_eq!("25:10:00", format_time(25 * 3600 + 10 * 60));
+        assert_eq!("08:05:09", format_time(parse_time("8:05:09").unwrap()));
+
+        assert_eq!(Some(3), weekday(19700101));
+        assert_eq!(Some(0), weekday(20240115));
+        assert_eq!(Some(3), weekday(20240229));
+        assert_eq!(Some(6), weekday(20001231));
+        assert_eq!(None, weekday(20241301));
+    }
+
+    #[test]
+    fn read_fixture() {
+        let feed = Feed::read_dir(Path::new("data/gtfs-tiny")).unwrap();
+        assert_eq!(4, feed.stops.len());
+        assert_eq!("Oakham, Station", feed.stops[0].name);
+        assert_eq!("1", feed.routes[0].name);
+        assert_eq!(15, feed.stop_times.len());
+        assert_eq!(8 * 3600 + 11 * 60, feed.stop_times[1].departure);
+        assert!(feed.services[0].runs_on(20240115));
+        assert!(!feed.services[0].runs_on(20240120));
+        assert!(!feed.services[0].runs_on(20250115));
+        assert_eq!(Some(900), feed.transfers[1].min_transfer_time);
+    }
+}
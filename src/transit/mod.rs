@@ -0,0 +1,749 @@
+use failure::Fail;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use crate::dijkstra::{search_dijkstra_in, DijkstraWorkspace};
+use crate::network::{Arc, Network, NetworkBuilder, Node, NodeIndex, OSMNodeId, OSMWayId, WayInfo};
+use crate::time_dependent::{run_td_dijkstra, Connection, TimeDependentNetwork, TravelTimeFunction, DAY};
+use crate::utils::haversine_distance_metres;
+
+mod gtfs;
+
+pub use self::gtfs::{format_time, parse_time, Feed};
+
+// part_of_way of the arcs that wait at a stop and of footpaths, a trip's arcs are its
+// position in the feed plus FIRST_TRIP_WAY
+const WAITING_WAY: OSMWayId = 0;
+const WALKING_WAY: OSMWayId = 1;
+const FIRST_TRIP_WAY: OSMWayId = 2;
+
+// the stops at either end and the seconds it takes
+type Footpath = (usize, usize, u32);
+
+#[derive(Debug)]
+pub enum GtfsError {
+    Io { file: String, error: io::Error },
+    MissingColumn { file: String, column: String },
+    /// line is the line number in the file, the header is line 1
+    BadValue {
+        file: String,
+        line: usize,
+        column: String,
+        value: String,
+    },
+    UnknownReference { file: String, column: String, value: String },
+}
+
+impl Fail for GtfsError {
+    fn cause(&self) -> Option<&dyn Fail> {
+        match self {
+            GtfsError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for GtfsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GtfsError::Io { file, error } => write!(f, "i/o error reading {}: {}", file, error),
+            GtfsError::MissingColumn { file, column } => write!(f, "{} has no {} column", file, column),
+            GtfsError::BadValue {
+                file,
+                line,
+                column,
+                value,
+            } => write!(f, "bad {} {:?} in {} at line {}", column, value, file, line),
+            GtfsError::UnknownReference { file, column, value } => {
+                write!(f, "{} in {} refers to {} which isn't in the feed", column, file, value)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum EventKind {
+    // a trip reaching a stop, or a footpath reaching another stop
+    Arrival,
+    Departure,
+    // waiting at a stop for a departure at this time
+    Transfer,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Event {
+    stop: usize,
+    time: u32,
+    kind: EventKind,
+}
+
+/// part of a journey on one trip or one footpath, times are seconds after midnight
+#[derive(Clone, Debug, PartialEq)]
+pub enum Leg {
+    Ride {
+        route: String,
+        trip: String,
+        from_stop: String,
+        to_stop: String,
+        departure: u32,
+        arrival: u32,
+    },
+    Walk {
+        from_stop: String,
+        to_stop: String,
+        departure: u32,
+        arrival: u32,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Journey {
+    pub arrival: u32,
+    pub legs: Vec<Leg>,
+}
+
+/// A time expanded graph of the trips running on one day. Every stop time of a trip is an
+/// arrival and a departure node with an arc between them for staying on board, and an arc
+/// from each departure to the next arrival of the trip. Each departure also has a transfer
+/// node at its stop and the transfer nodes of a stop are chained in time order, so an
+/// arrival reaches every later departure from its stop through the first transfer node at
+/// least the stop's minimum transfer time later. Footpaths from transfers.txt add an
+/// arrival node at the other stop. Arc costs are the milliseconds between the times of
+/// their nodes, so the cheapest path from the first transfer node after the departure time
+/// is the earliest arrival. Each stop has a node that all its arrivals lead to at no cost,
+/// which is the target of a query.
+pub struct TransitNetwork {
+    network: Network,
+    stops: Vec<gtfs::Stop>,
+    stop_positions: HashMap<String, usize>,
+    trips: Vec<gtfs::Trip>,
+    // the route name of each trip, empty for trips that don't run
+    trip_routes: Vec<String>,
+    // the node id of each event is its position plus the number of stops, the ids below
+    // that are the stops' target nodes
+    events: Vec<Event>,
+    // the transfer events of each stop in time order
+    transfers: Vec<Vec<usize>>,
+    // the (to, seconds) footpaths leaving each stop
+    footpaths: Vec<Vec<(usize, u32)>>,
+}
+
+impl TransitNetwork {
+    /// loads a GTFS directory and builds the graph of the trips running on the YYYYMMDD date
+    pub fn load(dir: &Path, date: u32) -> Result<TransitNetwork, GtfsError> {
+        let feed = Feed::read_dir(dir)?;
+        TransitNetwork::new(feed, date)
+    }
+
+    pub fn new(feed: Feed, date: u32) -> Result<TransitNetwork, GtfsError> {
+        let stop_positions = stop_positions(&feed);
+        let stop_position = |file: &str, column: &str, stop_id: &str| stop_position(&stop_positions, file, column, stop_id);
+        let route_names: HashMap<&str, &str> =
+            feed.routes.iter().map(|route| (route.id.as_str(), route.name.as_str())).collect();
+        let trip_stop_times = running_stop_times(&feed, date)?;
+
+        let mut builder = NetworkBuilder::new();
+        let mut events: Vec<Event> = vec![];
+        let mut arcs: Vec<(usize, usize, OSMWayId)> = vec![];
+        let mut arrivals: Vec<usize> = vec![];
+        let mut trip_routes = vec![String::new(); feed.trips.len()];
+        for (trip, stop_times) in trip_stop_times.iter().enumerate() {
+            if stop_times.is_empty() {
+                continue;
+            }
+            let route_id = &feed.trips[trip].route_id;
+            let route_name = route_names.get(route_id.as_str()).ok_or_else(|| GtfsError::UnknownReference {
+                file: "trips.txt".to_string(),
+                column: "route_id".to_string(),
+                value: route_id.clone(),
+            })?;
+            trip_routes[trip] = route_name.to_string();
+            let way = trip as OSMWayId + FIRST_TRIP_WAY;
+            builder.insert_way_info(WayInfo {
+                id: way,
+                name: Some(route_name.to_string()),
+            });
+
+            let mut previous_departure: Option<usize> = None;
+            for (i, stop_time) in stop_times.iter().enumerate() {
+                let stop = stop_position("stop_times.txt", "stop_id", &stop_time.stop_id)?;
+                let arrival = previous_departure.map(|departure| {
+                    events.push(Event {
+                        stop,
+                        time: stop_time.arrival,
+                        kind: EventKind::Arrival,
+                    });
+                    arcs.push((departure, events.len() - 1, way));
+                    arrivals.push(events.len() - 1);
+                    events.len() - 1
+                });
+                previous_departure = if i + 1 < stop_times.len() {
+                    events.push(Event {
+                        stop,
+                        time: stop_time.departure,
+                        kind: EventKind::Departure,
+                    });
+                    let departure = events.len() - 1;
+                    events.push(Event {
+                        stop,
+                        time: stop_time.departure,
+                        kind: EventKind::Transfer,
+                    });
+                    arcs.push((departure + 1, departure, WAITING_WAY));
+                    if let Some(arrival) = arrival {
+                        arcs.push((arrival, departure, way));
+                    }
+                    Some(departure)
+                } else {
+                    None
+                };
+            }
+        }
+
+        let mut transfers: Vec<Vec<usize>> = vec![vec![]; feed.stops.len()];
+        for (position, event) in events.iter().enumerate() {
+            if event.kind == EventKind::Transfer {
+                transfers[event.stop].push(position);
+            }
+        }
+        for stop_transfers in &mut transfers {
+            stop_transfers.sort_by_key(|&position| (events[position].time, position));
+            for pair in stop_transfers.windows(2) {
+                arcs.push((pair[0], pair[1], WAITING_WAY));
+            }
+        }
+
+        let (min_transfer_times, footpaths) = transfer_times(&feed, &stop_positions)?;
+
+        // footpaths aren't chained, a walk only starts from a trip's arrival
+        let mut walk_arrivals = vec![];
+        for &arrival in &arrivals {
+            let Event { stop, time, .. } = events[arrival];
+            for &(_, to, walk_time) in footpaths.iter().filter(|(from, _, _)| *from == stop) {
+                events.push(Event {
+                    stop: to,
+                    time: time + walk_time,
+                    kind: EventKind::Arrival,
+                });
+                arcs.push((arrival, events.len() - 1, WALKING_WAY));
+                walk_arrivals.push(events.len() - 1);
+            }
+        }
+        // a footpath's time already includes changing
+        let change_times = arrivals
+            .iter()
+            .map(|&arrival| (arrival, min_transfer_times[events[arrival].stop]))
+            .chain(walk_arrivals.iter().map(|&arrival| (arrival, 0)));
+        for (arrival, change_time) in change_times {
+            let Event { stop, time, .. } = events[arrival];
+            if let Some(transfer) = first_transfer(&transfers[stop], &events, time + change_time) {
+                arcs.push((arrival, transfer, WAITING_WAY));
+            }
+        }
+        builder.insert_way_info(WayInfo {
+            id: WALKING_WAY,
+            name: Some("walk".to_string()),
+        });
+
+        let stop_count = feed.stops.len();
+        let node_id = |event: usize| (event + stop_count) as OSMNodeId;
+        for (position, stop) in feed.stops.iter().enumerate() {
+            builder.insert_node(Node::new(position as OSMNodeId, stop.lat, stop.long));
+        }
+        for (position, event) in events.iter().enumerate() {
+            let stop = &feed.stops[event.stop];
+            builder.insert_node(Node::new(node_id(position), stop.lat, stop.long));
+        }
+        for (tail, head, way) in arcs {
+            let (from, to) = (&feed.stops[events[tail].stop], &feed.stops[events[head].stop]);
+            let distance = haversine_distance_metres((from.lat, from.long), (to.lat, to.long));
+            builder.insert_arc(
+                node_id(tail),
+                Arc {
+                    head_node: node_id(head),
+                    distance,
+                    // running_stop_times refuses trips whose times go backwards
+                    cost: (events[head].time - events[tail].time) as u64 * 1000,
+                    part_of_way: way,
+                },
+            );
+        }
+        for &arrival in arrivals.iter().chain(&walk_arrivals) {
+            builder.insert_arc(
+                node_id(arrival),
+                Arc {
+                    head_node: events[arrival].stop as OSMNodeId,
+                    distance: 0,
+                    cost: 0,
+                    part_of_way: WAITING_WAY,
+                },
+            );
+        }
+
+        let network = builder.build_network().unwrap();
+        info!(
+            "time expanded graph for {} with {} nodes and {} arcs",
+            date,
+            network.node_count(),
+            network.arc_count()
+        );
+        let mut stop_footpaths = vec![vec![]; feed.stops.len()];
+        for (from, to, walk_time) in footpaths {
+            stop_footpaths[from].push((to, walk_time));
+        }
+        Ok(TransitNetwork {
+            network,
+            stops: feed.stops,
+            stop_positions,
+            trips: feed.trips,
+            trip_routes,
+            events,
+            transfers,
+            footpaths: stop_footpaths,
+        })
+    }
+
+    pub fn network(&self) -> &Network {
+        &self.network
+    }
+
+    /// the earliest arrival at to_stop leaving from_stop no earlier than departure, in
+    /// seconds after midnight, None if the stops aren't in the feed or can't be reached.
+    /// A journey may start by walking a footpath from from_stop, the graph's footpaths only
+    /// leave trip arrivals so each walk is tried with a search from the stop it reaches
+    pub fn earliest_arrival(&self, from_stop: &str, to_stop: &str, departure: u32) -> Option<Journey> {
+        let from = *self.stop_positions.get(from_stop)?;
+        let to = *self.stop_positions.get(to_stop)?;
+        let walks = self.footpaths[from].iter().map(|&(stop, walk_time)| {
+            let walk = Leg::Walk {
+                from_stop: self.stops[from].id.clone(),
+                to_stop: self.stops[stop].id.clone(),
+                departure,
+                arrival: departure + walk_time,
+            };
+            (stop, departure + walk_time, Some(walk))
+        });
+        let mut workspace = DijkstraWorkspace::for_network(&self.network);
+        // on a tie the journey that doesn't walk first wins
+        std::iter::once((from, departure, None))
+            .chain(walks)
+            .filter_map(|(stop, time, walk)| {
+                let mut journey = self.ride_from(&mut workspace, stop, to, time)?;
+                journey.legs.splice(0..0, walk);
+                Some(journey)
+            })
+            .min_by_key(|journey| journey.arrival)
+    }
+
+    // the earliest arrival boarding at from, or changing there after a walk, at time or later
+    fn ride_from(&self, workspace: &mut DijkstraWorkspace, from: usize, to: usize, departure: u32) -> Option<Journey> {
+        if from == to {
+            return Some(Journey {
+                arrival: departure,
+                legs: vec![],
+            });
+        }
+        let start = first_transfer(&self.transfers[from], &self.events, departure)?;
+        let start_id = (start + self.stops.len()) as OSMNodeId;
+
+        let target_index = self.network.node_index(&(to as OSMNodeId))?;
+        let entry = search_dijkstra_in(workspace, start_id, to as OSMNodeId, &self.network, 0, false).entry?;
+        let path = workspace.path_to(target_index, &self.network)?;
+
+        let start_index = self.network.node_index(&start_id)?;
+        let tails = std::iter::once(start_index).chain(path.iter().map(|arc| arc.head_node));
+        Some(Journey {
+            arrival: self.events[start].time + (entry.cost / 1000) as u32,
+            legs: self.legs(tails.zip(&path).map(|(tail, arc)| (tail, arc.head_node, arc.part_of_way))),
+        })
+    }
+
+    fn event(&self, index: NodeIndex) -> Option<&Event> {
+        let id = self.network.node_at(index).id as usize;
+        id.checked_sub(self.stops.len()).map(|position| &self.events[position])
+    }
+
+    // merges the arcs of a path on the same trip into one leg and drops waiting
+    fn legs(&self, arcs: impl Iterator<Item = (NodeIndex, NodeIndex, OSMWayId)>) -> Vec<Leg> {
+        let mut legs = vec![];
+        let mut ride: Option<(OSMWayId, &Event, &Event)> = None;
+        for (tail, head, way) in arcs {
+            match ride {
+                Some((ride_way, from, _)) if ride_way == way => ride = Some((way, from, self.event(head).unwrap())),
+                _ => {
+                    if let Some(leg) = ride.take().map(|(way, from, to)| self.ride_leg(way, from, to)) {
+                        legs.push(leg);
+                    }
+                    if way == WALKING_WAY {
+                        let (from, to) = (self.event(tail).unwrap(), self.event(head).unwrap());
+                        legs.push(Leg::Walk {
+                            from_stop: self.stops[from.stop].id.clone(),
+                            to_stop: self.stops[to.stop].id.clone(),
+                            departure: from.time,
+                            arrival: to.time,
+                        });
+                    } else if way >= FIRST_TRIP_WAY {
+                        ride = Some((way, self.event(tail).unwrap(), self.event(head).unwrap()));
+                    }
+                }
+            }
+        }
+        legs.extend(ride.map(|(way, from, to)| self.ride_leg(way, from, to)));
+        legs
+    }
+
+    fn ride_leg(&self, way: OSMWayId, from: &Event, to: &Event) -> Leg {
+        let trip = (way - FIRST_TRIP_WAY) as usize;
+        Leg::Ride {
+            route: self.trip_routes[trip].clone(),
+            trip: self.trips[trip].id.clone(),
+            from_stop: self.stops[from.stop].id.clone(),
+            to_stop: self.stops[to.stop].id.clone(),
+            departure: from.time,
+            arrival: to.time,
+        }
+    }
+}
+
+/// A time dependent graph of the trips running on one day with a node per stop. The arc
+/// from a stop to the next one a trip calls at has a timetable of the trips between them
+/// and footpaths are arcs taking a constant time. Unlike the time expanded graph changing
+/// at a stop takes no time, and the timetables repeat every day so a journey can arrive on
+/// a later day. A trip overtaken by another between two stops is left out of their
+/// timetable as it never arrives first, which keeps every timetable FIFO.
+pub struct StationGraph {
+    td_network: TimeDependentNetwork,
+    stop_positions: HashMap<String, usize>,
+}
+
+impl StationGraph {
+    pub fn load(dir: &Path, date: u32) -> Result<StationGraph, GtfsError> {
+        let feed = Feed::read_dir(dir)?;
+        StationGraph::new(&feed, date)
+    }
+
+    pub fn new(feed: &Feed, date: u32) -> Result<StationGraph, GtfsError> {
+        let stop_positions = stop_positions(feed);
+        let mut timetables: HashMap<(usize, usize), Vec<Connection>> = HashMap::new();
+        for stop_times in running_stop_times(feed, date)? {
+            for pair in stop_times.windows(2) {
+                let from = stop_position(&stop_positions, "stop_times.txt", "stop_id", &pair[0].stop_id)?;
+                let to = stop_position(&stop_positions, "stop_times.txt", "stop_id", &pair[1].stop_id)?;
+                timetables.entry((from, to)).or_default().push(Connection {
+                    departure: pair[0].departure as u64 * 1000 % DAY,
+                    // running_stop_times refuses trips arriving before they left
+                    duration: (pair[1].arrival - pair[0].departure) as u64 * 1000,
+                });
+            }
+        }
+        for connections in timetables.values_mut() {
+            *connections = fastest_connections(connections);
+        }
+        let (_, footpaths) = transfer_times(feed, &stop_positions)?;
+
+        let mut builder = NetworkBuilder::new();
+        for (position, stop) in feed.stops.iter().enumerate() {
+            builder.insert_node(Node::new(position as OSMNodeId, stop.lat, stop.long));
+        }
+        let distance = |from: usize, to: usize| {
+            let (from, to) = (&feed.stops[from], &feed.stops[to]);
+            haversine_distance_metres((from.lat, from.long), (to.lat, to.long))
+        };
+        // the arcs' own costs are the quickest connection, a lower bound on the travel time
+        for (&(from, to), connections) in &timetables {
+            let arc = Arc {
+                head_node: to as OSMNodeId,
+                distance: distance(from, to),
+                cost: connections.iter().map(|connection| connection.duration).min().unwrap_or(0),
+                part_of_way: WAITING_WAY,
+            };
+            builder.insert_arc(from as OSMNodeId, arc);
+        }
+        for &(from, to, walk_time) in &footpaths {
+            let arc = Arc {
+                head_node: to as OSMNodeId,
+                distance: distance(from, to),
+                cost: walk_time as u64 * 1000,
+                part_of_way: WALKING_WAY,
+            };
+            builder.insert_arc(from as OSMNodeId, arc);
+        }
+
+        let network = builder.build_network().unwrap();
+        let functions = (0..network.node_count())
+            .flat_map(|node| network.fwd_arcs(node).iter().map(move |arc| (node, arc)))
+            .map(|(node, arc)| {
+                let key = (network.node_at(node).id as usize, network.node_at(arc.head_node).id as usize);
+                match arc.part_of_way {
+                    WALKING_WAY => TravelTimeFunction::Constant(arc.cost),
+                    _ => TravelTimeFunction::Timetable(timetables[&key].clone()),
+                }
+            })
+            .collect();
+        let td_network =
+            TimeDependentNetwork::new(network, functions).expect("timetables without overtaken trips are FIFO");
+        Ok(StationGraph {
+            td_network,
+            stop_positions,
+        })
+    }
+
+    pub fn td_network(&self) -> &TimeDependentNetwork {
+        &self.td_network
+    }
+
+    /// the earliest arrival at to_stop leaving from_stop at departure, both in seconds after
+    /// midnight of the date, None if the stops aren't in the feed or can't be reached
+    pub fn earliest_arrival(&self, from_stop: &str, to_stop: &str, departure: u32) -> Option<u32> {
+        let from = *self.stop_positions.get(from_stop)?;
+        let to = *self.stop_positions.get(to_stop)?;
+        if from == to {
+            return Some(departure);
+        }
+        let entry = run_td_dijkstra(from as OSMNodeId, to as OSMNodeId, &self.td_network, departure as u64 * 1000, false)?;
+        Some(departure + (entry.cost / 1000) as u32)
+    }
+}
+
+// the connections in departure order without those that arrive no earlier than one leaving
+// later, counting the next day's connections
+fn fastest_connections(connections: &[Connection]) -> Vec<Connection> {
+    let mut connections = connections.to_vec();
+    connections.sort_by_key(|connection| (connection.departure, connection.duration));
+    let arrival = |connection: &Connection| connection.departure + connection.duration;
+    let mut earliest_later = connections.iter().map(arrival).min().unwrap_or(0) + DAY;
+    let mut fastest: Vec<Connection> = connections
+        .iter()
+        .rev()
+        .filter(|connection| {
+            let keep = arrival(connection) < earliest_later;
+            earliest_later = earliest_later.min(arrival(connection));
+            keep
+        })
+        .cloned()
+        .collect();
+    fastest.reverse();
+    fastest
+}
+
+fn stop_positions(feed: &Feed) -> HashMap<String, usize> {
+    feed.stops.iter().enumerate().map(|(position, stop)| (stop.id.clone(), position)).collect()
+}
+
+fn stop_position(stop_positions: &HashMap<String, usize>, file: &str, column: &str, stop_id: &str) -> Result<usize, GtfsError> {
+    stop_positions.get(stop_id).cloned().ok_or_else(|| GtfsError::UnknownReference {
+        file: file.to_string(),
+        column: column.to_string(),
+        value: stop_id.to_string(),
+    })
+}
+
+// the stop times of each trip in sequence order, none for trips that don't run on the date.
+// A trip can't depart a stop before it arrives or arrive before it left the previous stop
+fn running_stop_times(feed: &Feed, date: u32) -> Result<Vec<Vec<&gtfs::StopTime>>, GtfsError> {
+    let running: Vec<bool> = feed
+        .services
+        .iter()
+        .filter(|service| service.runs_on(date))
+        .map(|service| service.id.as_str())
+        .fold(vec![false; feed.trips.len()], |mut running, service_id| {
+            for (position, trip) in feed.trips.iter().enumerate() {
+                running[position] |= trip.service_id == service_id;
+            }
+            running
+        });
+    let trip_positions: HashMap<&str, usize> =
+        feed.trips.iter().enumerate().map(|(position, trip)| (trip.id.as_str(), position)).collect();
+
+    let mut trip_stop_times: Vec<Vec<&gtfs::StopTime>> = vec![vec![]; feed.trips.len()];
+    for stop_time in &feed.stop_times {
+        let trip = trip_positions.get(stop_time.trip_id.as_str()).cloned().ok_or_else(|| {
+            GtfsError::UnknownReference {
+                file: "stop_times.txt".to_string(),
+                column: "trip_id".to_string(),
+                value: stop_time.trip_id.clone(),
+            }
+        })?;
+        trip_stop_times[trip].push(stop_time);
+    }
+    let backwards = |stop_time: &gtfs::StopTime, column: &str, time: u32| GtfsError::BadValue {
+        file: "stop_times.txt".to_string(),
+        line: stop_time.line,
+        column: column.to_string(),
+        value: format_time(time),
+    };
+    for (trip, stop_times) in trip_stop_times.iter_mut().enumerate() {
+        stop_times.sort_by_key(|stop_time| stop_time.sequence);
+        if let Some(stop_time) = stop_times.iter().find(|stop_time| stop_time.departure < stop_time.arrival) {
+            return Err(backwards(stop_time, "departure_time", stop_time.departure));
+        }
+        if let Some(pair) = stop_times.windows(2).find(|pair| pair[1].arrival < pair[0].departure) {
+            return Err(backwards(pair[1], "arrival_time", pair[1].arrival));
+        }
+        if !running[trip] {
+            stop_times.clear();
+        }
+    }
+    Ok(trip_stop_times)
+}
+
+// the minimum time to change at each stop and the (from, to, time) footpaths between stops
+fn transfer_times(feed: &Feed, stop_positions: &HashMap<String, usize>) -> Result<(Vec<u32>, Vec<Footpath>), GtfsError> {
+    let mut min_transfer_times = vec![0; feed.stops.len()];
+    let mut footpaths = vec![];
+    for transfer in feed.transfers.iter().filter(|transfer| !transfer.forbidden) {
+        let from = stop_position(stop_positions, "transfers.txt", "from_stop_id", &transfer.from_stop_id)?;
+        let to = stop_position(stop_positions, "transfers.txt", "to_stop_id", &transfer.to_stop_id)?;
+        match (from == to, transfer.min_transfer_time) {
+            (true, Some(time)) => min_transfer_times[from] = time,
+            (false, Some(time)) => footpaths.push((from, to, time)),
+            _ => (),
+        }
+    }
+    Ok((min_transfer_times, footpaths))
+}
+
+// the first of a stop's transfer events in time order that isn't before the time
+fn first_transfer(transfers: &[usize], events: &[Event], time: u32) -> Option<usize> {
+    let position = transfers.partition_point(|&event| events[event].time < time);
+    transfers.get(position).cloned()
+}
+
+#[cfg(test)]
+mod transit_test {
+    use super::*;
+
+    fn time(time: &str) -> u32 {
+        parse_time(time).unwrap()
+    }
+
+    fn monday() -> TransitNetwork {
+        TransitNetwork::load(Path::new("data/gtfs-tiny"), 20240115).unwrap()
+    }
+
+    fn ride(route: &str, trip: &str, from_stop: &str, to_stop: &str, departure: &str, arrival: &str) -> Leg {
+        Leg::Ride {
+            route: route.to_string(),
+            trip: trip.to_string(),
+            from_stop: from_stop.to_string(),
+            to_stop: to_stop.to_string(),
+            departure: time(departure),
+            arrival: time(arrival),
+        }
+    }
+
+    #[test]
+    fn earliest_arrivals() {
+        let transit = monday();
+
+        // changing at B takes 3 minutes so the 08:12 is missed, walking from C is quicker than the 08:25
+        let journey = transit.earliest_arrival("A", "D", time("07:55:00")).unwrap();
+        assert_eq!(time("08:35:00"), journey.arrival);
+        assert_eq!(
+            vec![
+                ride("1", "T1", "A", "C", "08:00:00", "08:20:00"),
+                Leg::Walk {
+                    from_stop: "C".to_string(),
+                    to_stop: "D".to_string(),
+                    departure: time("08:20:00"),
+                    arrival: time("08:35:00"),
+                },
+            ],
+            journey.legs
+        );
+
+        let journey = transit.earliest_arrival("A", "B", time("07:55:00")).unwrap();
+        assert_eq!(vec![ride("1", "T1", "A", "B", "08:00:00", "08:10:00")], journey.legs);
+
+        // no transfer time when starting at a stop
+        let journey = transit.earliest_arrival("B", "D", time("08:11:00")).unwrap();
+        assert_eq!(vec![ride("2", "T3", "B", "D", "08:12:00", "08:30:00")], journey.legs);
+
+        let journey = transit.earliest_arrival("A", "D", time("08:01:00")).unwrap();
+        assert_eq!(vec![ride("3", "T5", "A", "D", "08:05:00", "08:50:00")], journey.legs);
+
+        // nothing leaves C, the footpath does
+        let journey = transit.earliest_arrival("C", "D", time("08:00:00")).unwrap();
+        assert_eq!(time("08:15:00"), journey.arrival);
+        assert_eq!(
+            vec![Leg::Walk {
+                from_stop: "C".to_string(),
+                to_stop: "D".to_string(),
+                departure: time("08:00:00"),
+                arrival: time("08:15:00"),
+            }],
+            journey.legs
+        );
+    }
+
+    #[test]
+    fn unreachable_stops() {
+        let transit = monday();
+        assert!(transit.earliest_arrival("A", "C", time("08:31:00")).is_none());
+        assert!(transit.earliest_arrival("D", "A", time("06:00:00")).is_none());
+        assert!(transit.earliest_arrival("A", "Z", time("06:00:00")).is_none());
+        assert_eq!(time("06:00:00"), transit.earliest_arrival("A", "A", time("06:00:00")).unwrap().arrival);
+    }
+
+    #[test]
+    fn services_by_day() {
+        let saturday = TransitNetwork::load(Path::new("data/gtfs-tiny"), 20240120).unwrap();
+        let journey = saturday.earliest_arrival("A", "C", time("07:00:00")).unwrap();
+        assert_eq!(vec![ride("1", "T6", "A", "C", "09:00:00", "09:20:00")], journey.legs);
+        // route 2 doesn't run at the weekend
+        assert_eq!(time("09:35:00"), saturday.earliest_arrival("B", "D", time("07:00:00")).unwrap().arrival);
+    }
+
+    #[test]
+    fn station_graph() {
+        let stations = StationGraph::load(Path::new("data/gtfs-tiny"), 20240115).unwrap();
+        // changing at B takes no time here so the 08:12 is caught
+        assert_eq!(Some(time("08:30:00")), stations.earliest_arrival("A", "D", time("07:55:00")));
+        assert_eq!(Some(time("08:50:00")), stations.earliest_arrival("A", "D", time("08:01:00")));
+        assert_eq!(Some(time("08:30:00")), stations.earliest_arrival("B", "D", time("08:11:00")));
+        // the first trip of the next day
+        assert_eq!(Some(time("32:20:00")), stations.earliest_arrival("A", "C", time("08:31:00")));
+        assert_eq!(None, stations.earliest_arrival("D", "A", time("06:00:00")));
+
+        let overtaken = [
+            Connection { departure: 10, duration: 100 },
+            Connection { departure: 0, duration: 200 },
+            Connection { departure: 20, duration: 50 },
+            Connection { departure: DAY - 5, duration: 100 },
+        ];
+        assert_eq!(vec![overtaken[2]], fastest_connections(&overtaken));
+    }
+
+    #[test]
+    fn load_errors() {
+        match TransitNetwork::load(Path::new("data/no-such-feed"), 20240115) {
+            Err(GtfsError::Io { file, .. }) => assert_eq!("stops.txt", file),
+            _ => panic!("expected an i/o error"),
+        }
+
+        // T1 reaching C at 08:05, before it left B at 08:11
+        let mut feed = Feed::read_dir(Path::new("data/gtfs-tiny")).unwrap();
+        feed.stop_times[2].arrival = time("08:05:00");
+        feed.stop_times[2].departure = time("08:05:00");
+        for result in [TransitNetwork::new(feed.clone(), 20240115).err(), StationGraph::new(&feed, 20240115).err()] {
+            match result {
+                Some(GtfsError::BadValue { line, column, value, .. }) => {
+                    assert_eq!((4, "arrival_time", "08:05:00"), (line, column.as_str(), value.as_str()))
+                }
+                _ => panic!("expected a bad arrival time"),
+            }
+        }
+
+        // leaving B before arriving, on a trip that doesn't run that day
+        let mut feed = Feed::read_dir(Path::new("data/gtfs-tiny")).unwrap();
+        let weekend_b = feed.stop_times.iter().position(|stop_time| stop_time.trip_id == "T6" && stop_time.stop_id == "B");
+        let stop_time = &mut feed.stop_times[weekend_b.unwrap()];
+        stop_time.departure = stop_time.arrival - 60;
+        match TransitNetwork::new(feed, 20240115) {
+            Err(error) => assert!(error.to_string().contains("departure_time")),
+            Ok(_) => panic!("expected a bad departure time"),
+        }
+    }
+}
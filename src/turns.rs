@@ -0,0 +1,180 @@
+use crate::network::{Network, NodeIndex};
+use crate::utils;
+
+use std::collections::HashSet;
+
+/// turns closer to straight on than this many degrees are free
+const STRAIGHT_DEGREES: f64 = 30.0;
+/// turns sharper than this many degrees double back on themselves
+const U_TURN_DEGREES: f64 = 150.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Turn {
+    Straight,
+    Left,
+    Right,
+    UTurn,
+}
+
+/// classify the change of heading from one compass bearing to another
+pub fn classify(in_bearing: f64, out_bearing: f64) -> Turn {
+    // positive is clockwise, a turn to the right
+    let mut angle = (out_bearing - in_bearing) % 360.0;
+    if angle > 180.0 {
+        angle -= 360.0;
+    } else if angle <= -180.0 {
+        angle += 360.0;
+    }
+    if angle.abs() < STRAIGHT_DEGREES {
+        Turn::Straight
+    } else if angle.abs() > U_TURN_DEGREES {
+        Turn::UTurn
+    } else if angle > 0.0 {
+        Turn::Right
+    } else {
+        Turn::Left
+    }
+}
+
+/// penalties in milliseconds, the same units as arc costs, added when a path passes
+/// through a node
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TurnCostModel {
+    pub straight: u64,
+    pub left: u64,
+    pub right: u64,
+    pub u_turn: u64,
+    pub traffic_signals: u64,
+}
+
+/// for traffic driving on the left, where right turns cross the oncoming lanes
+impl Default for TurnCostModel {
+    fn default() -> Self {
+        TurnCostModel {
+            straight: 0,
+            left: 3_000,
+            right: 6_000,
+            u_turn: 20_000,
+            traffic_signals: 8_000,
+        }
+    }
+}
+
+impl TurnCostModel {
+    /// the penalty for arriving at via from tail and leaving towards head. Bends along a
+    /// road are free, only junctions with more than two neighbouring nodes charge for
+    /// turning left or right
+    pub fn turn_cost(&self, network: &Network, tail: NodeIndex, via: NodeIndex, head: NodeIndex) -> u64 {
+        let via_lat_long = network.node_at(via).lat_long_f64();
+        let in_bearing = utils::initial_bearing_degrees(network.node_at(tail).lat_long_f64(), via_lat_long);
+        let out_bearing = utils::initial_bearing_degrees(via_lat_long, network.node_at(head).lat_long_f64());
+
+        let turn = if tail == head { Turn::UTurn } else { classify(in_bearing, out_bearing) };
+        let turn_cost = match turn {
+            Turn::UTurn => self.u_turn,
+            _ if !is_junction(network, via) => 0,
+            Turn::Straight => self.straight,
+            Turn::Left => self.left,
+            Turn::Right => self.right,
+        };
+        let signal_cost = if network.has_traffic_signals(via) { self.traffic_signals } else { 0 };
+        turn_cost + signal_cost
+    }
+}
+
+fn is_junction(network: &Network, index: NodeIndex) -> bool {
+    let neighbours: HashSet<NodeIndex> = network
+        .fwd_arcs(index)
+        .iter()
+        .chain(network.bwd_arcs(index))
+        .map(|arc| arc.head_node)
+        .collect();
+    neighbours.len() > 2
+}
+
+#[cfg(test)]
+mod turns_test {
+    use super::*;
+    use crate::dijkstra::{run_dijsktra, run_turn_aware_dijkstra};
+    use crate::osm::load_xml::load_network_from_string;
+    use crate::osm::profile::Car;
+    use crate::test_networks::{grid_id, make_grid_network};
+
+    #[test]
+    fn classify_turns() {
+        assert_eq!(Turn::Straight, classify(90.0, 100.0));
+        assert_eq!(Turn::Straight, classify(355.0, 10.0));
+        assert_eq!(Turn::Right, classify(0.0, 90.0));
+        assert_eq!(Turn::Left, classify(0.0, 270.0));
+        assert_eq!(Turn::Right, classify(300.0, 20.0));
+        assert_eq!(Turn::Left, classify(20.0, 300.0));
+        assert_eq!(Turn::UTurn, classify(90.0, 265.0));
+    }
+
+    #[test]
+    fn grid_turns() {
+        let network = make_grid_network();
+        let model = TurnCostModel::default();
+        let index = |row, col| network.node_index(&grid_id(row, col)).unwrap();
+
+        // rows run north and columns east. Heading east along row 2 through (2, 2)
+        let (tail, via) = (index(2, 1), index(2, 2));
+        assert_eq!(model.straight, model.turn_cost(&network, tail, via, index(2, 3)));
+        assert_eq!(model.left, model.turn_cost(&network, tail, via, index(3, 2)));
+        assert_eq!(model.right, model.turn_cost(&network, tail, via, index(1, 2)));
+        assert_eq!(model.u_turn, model.turn_cost(&network, tail, via, tail));
+    }
+
+    #[test]
+    fn avoids_turns_and_signals() {
+        // straight through a signal at 2, or along a longer bend through 4
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osm version="0.6">
+  <node id="1" lat="52.6000" lon="-0.7000"/>
+  <node id="2" lat="52.6000" lon="-0.6984">
+    <tag k="highway" v="traffic_signals"/>
+  </node>
+  <node id="3" lat="52.6000" lon="-0.6968"/>
+  <node id="4" lat="52.5998" lon="-0.6984"/>
+  <node id="5" lat="52.6010" lon="-0.6984"/>
+  <way id="10">
+    <nd ref="1"/>
+    <nd ref="2"/>
+    <nd ref="3"/>
+    <tag k="highway" v="residential"/>
+    <tag k="name" v="Signal Street"/>
+  </way>
+  <way id="20">
+    <nd ref="1"/>
+    <nd ref="4"/>
+    <nd ref="3"/>
+    <tag k="highway" v="residential"/>
+    <tag k="name" v="Bend Road"/>
+  </way>
+  <way id="30">
+    <nd ref="2"/>
+    <nd ref="5"/>
+    <tag k="highway" v="residential"/>
+    <tag k="name" v="Side Street"/>
+  </way>
+</osm>"#;
+        let network = load_network_from_string(xml, &Car).unwrap();
+        assert_eq!(1, network.traffic_signal_count());
+
+        let shortest = run_dijsktra(1, 3, &network, 0, true).unwrap();
+        assert_eq!("Signal Street", shortest.report_traversed_ways());
+        let without_penalties = run_turn_aware_dijkstra(1, 3, &network, None).unwrap();
+        assert_eq!(shortest.cost, without_penalties.cost);
+
+        let model = TurnCostModel::default();
+        let with_penalties = run_turn_aware_dijkstra(1, 3, &network, Some(&model)).unwrap();
+        assert_eq!("Bend Road", with_penalties.report_traversed_ways());
+        assert!(with_penalties.cost > shortest.cost);
+        assert!(with_penalties.cost < shortest.cost + model.traffic_signals);
+
+        // turning left off Signal Street at the signals
+        let turning = run_turn_aware_dijkstra(1, 5, &network, Some(&model)).unwrap();
+        let plain = run_dijsktra(1, 5, &network, 0, false).unwrap();
+        assert_eq!(plain.cost + model.left + model.traffic_signals, turning.cost);
+    }
+}
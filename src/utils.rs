@@ -5,10 +5,18 @@ pub fn bytes_to_string(bytes: Cow<'_, [u8]>) -> Result<String, FromUtf8Error> {
     String::from_utf8(bytes.to_vec())
 }
 
+/// rounded up so that a path is never shorter than the straight line between its ends
 pub fn haversine_distance_metres(
     from_lat_long_deg: (f64, f64),
     to_lat_long_deg: (f64, f64),
 ) -> u64 {
+    haversine_distance_metres_f64(from_lat_long_deg, to_lat_long_deg).ceil() as u64
+}
+
+pub fn haversine_distance_metres_f64(
+    from_lat_long_deg: (f64, f64),
+    to_lat_long_deg: (f64, f64),
+) -> f64 {
     const EARTH_RADIUS_KILOMETER: f64 = 6371.0_f64;
 
     let (from_lat_deg, from_long_deg) = from_lat_long_deg;
@@ -25,5 +33,20 @@ pub fn haversine_distance_metres(
     let central_angle = 2.0 * central_angle_inner.sqrt().asin();
 
     let distance = EARTH_RADIUS_KILOMETER * central_angle;
-    (distance * 1000.0) as u64
+    distance * 1000.0
+}
+
+/// the compass bearing in degrees, clockwise from north, when setting off from one point
+/// towards another along a great circle
+pub fn initial_bearing_degrees(from_lat_long_deg: (f64, f64), to_lat_long_deg: (f64, f64)) -> f64 {
+    let (from_lat_deg, from_long_deg) = from_lat_long_deg;
+    let (to_lat_deg, to_long_deg) = to_lat_long_deg;
+
+    let from_latitude = from_lat_deg.to_radians();
+    let to_latitude = to_lat_deg.to_radians();
+    let delta_longitude = (to_long_deg - from_long_deg).to_radians();
+
+    let y = delta_longitude.sin() * to_latitude.cos();
+    let x = from_latitude.cos() * to_latitude.sin() - from_latitude.sin() * to_latitude.cos() * delta_longitude.cos();
+    (y.atan2(x).to_degrees() + 360.0) % 360.0
 }